@@ -1,9 +1,9 @@
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock, RwLock};
 
 use serde::Deserialize;
 use tracing::warn;
 
-use meme_generator_core::config::read_config_file;
+use meme_generator_core::{config::read_config_file, error::Error};
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
@@ -25,7 +25,17 @@ impl Default for Config {
 pub struct MemeConfig {
     pub load_builtin_memes: bool,
     pub load_external_memes: bool,
+    /// 加载与 rustc 版本绑定的旧式表情包，默认关闭
+    pub load_legacy_libraries: bool,
+    pub load_template_memes: bool,
+    /// 禁用的表情，可以使用 `key` 或 `pack:key`
     pub meme_disabled_list: Vec<String>,
+    /// 多个表情包提供同名表情时，靠前的表情包占用不带命名空间的键，未列出的表情包按名称排在最后
+    pub pack_priority: Vec<String>,
+    /// 单次调用 WASM 插件可消耗的燃料（约等于执行的指令数）
+    pub wasm_fuel_limit: u64,
+    /// WASM 插件可使用的最大内存，单位为 MB
+    pub wasm_memory_limit: usize,
 }
 
 impl Default for MemeConfig {
@@ -33,7 +43,12 @@ impl Default for MemeConfig {
         MemeConfig {
             load_builtin_memes: true,
             load_external_memes: false,
+            load_legacy_libraries: false,
+            load_template_memes: true,
             meme_disabled_list: vec![],
+            pack_priority: vec!["builtin".to_string()],
+            wasm_fuel_limit: 10_000_000_000,
+            wasm_memory_limit: 512,
         }
     }
 }
@@ -54,16 +69,33 @@ impl Default for ResourceConfig {
     }
 }
 
-fn parse_config() -> Config {
+fn parse_config() -> Result<Config, Error> {
     let config_content = read_config_file();
     if config_content.is_empty() {
-        Config::default()
-    } else {
-        toml::from_str(&config_content).unwrap_or_else(|_| {
-            warn!("Failed to parse config file, using default config");
-            Config::default()
-        })
+        return Ok(Config::default());
     }
+    toml::from_str(&config_content)
+        .map_err(|err| Error::DeserializeError(format!("Failed to parse config file: {err}")))
+}
+
+static CONFIG: LazyLock<RwLock<Arc<Config>>> = LazyLock::new(|| {
+    let config = parse_config().unwrap_or_else(|err| {
+        warn!("{err}, using default config");
+        Config::default()
+    });
+    RwLock::new(Arc::new(config))
+});
+
+/// 当前配置的快照，重新加载后再次调用才会得到新配置
+pub(crate) fn config() -> Arc<Config> {
+    CONFIG.read().unwrap().clone()
 }
 
-pub static CONFIG: LazyLock<Config> = LazyLock::new(parse_config);
+/// 重新读取配置文件，解析失败时保留当前配置
+///
+/// 只重新加载本模块的配置，`meme_generator_utils` 中编码、解码和字体等配置需要重启才能生效
+pub(crate) fn reload_config() -> Result<(), Error> {
+    let config = parse_config()?;
+    *CONFIG.write().unwrap() = Arc::new(config);
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! 已加载字体的信息和文字的字形覆盖检查，用于排查字体配置
+
+pub use meme_generator_utils::text::{
+    GlyphCoverage, TypefaceInfo, check_glyph_coverage, loaded_typefaces,
+};
@@ -4,12 +4,16 @@ mod registry;
 mod search;
 mod version;
 
+pub mod fonts;
+pub mod parser;
 pub mod resources;
+pub mod shortcuts;
 pub mod tools;
+pub mod wasm;
 pub use meme_generator_core::{
     config::{MEME_HOME, read_config_file},
-    error, meme,
+    error, format, meme,
 };
-pub use memes::{get_meme, get_meme_keys, get_memes};
+pub use memes::{ReloadResult, get_meme, get_meme_keys, get_memes, reload_memes};
 pub use search::search_memes;
 pub use version::VERSION;
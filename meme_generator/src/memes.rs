@@ -1,26 +1,100 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
 
-use meme_generator_core::meme::Meme;
+use serde::Serialize;
+use tracing::warn;
 
-use crate::registry::load_memes;
+use meme_generator_core::{error::Error, meme::Meme};
 
-static LOADED_MEMES: LazyLock<HashMap<String, Box<dyn Meme>>> = LazyLock::new(|| load_memes());
+use crate::{
+    config::reload_config,
+    registry::{LoadedMemes, load_memes},
+};
 
-pub fn get_meme(key: &str) -> Option<&'static Box<dyn Meme>> {
-    LOADED_MEMES.get(key)
+static LOADED_MEMES: LazyLock<RwLock<Arc<LoadedMemes>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(load_memes())));
+
+static RELOAD_LOCK: Mutex<()> = Mutex::new(());
+
+pub(crate) fn loaded_memes() -> Arc<LoadedMemes> {
+    LOADED_MEMES.read().unwrap().clone()
 }
 
-pub fn get_memes() -> Vec<&'static Box<dyn Meme>> {
-    let mut memes = LOADED_MEMES.values().into_iter().collect::<Vec<_>>();
+/// 获取表情，`key` 可以带有表情包命名空间，如 `builtin:petpet`
+pub fn get_meme(key: &str) -> Option<Arc<dyn Meme>> {
+    let loaded = loaded_memes();
+    loaded
+        .memes
+        .get(key)
+        .or_else(|| loaded.namespaced.get(key))
+        .cloned()
+}
+
+pub fn get_memes() -> Vec<Arc<dyn Meme>> {
+    let mut memes = loaded_memes().memes.values().cloned().collect::<Vec<_>>();
     memes.sort_by_key(|meme| meme.key());
     memes
 }
 
-pub fn get_meme_keys() -> Vec<&'static str> {
-    let mut keys = LOADED_MEMES
-        .keys()
-        .map(|key| key.as_str())
-        .collect::<Vec<_>>();
+pub fn get_meme_keys() -> Vec<String> {
+    let mut keys = loaded_memes().memes.keys().cloned().collect::<Vec<_>>();
     keys.sort();
     keys
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadResult {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub total: usize,
+    /// 文件已更新但仍在使用旧版本的动态库，需要重启才能生效
+    pub restart_required: Vec<String>,
+}
+
+/// 重新读取配置并重新加载所有表情
+///
+/// 新的表情列表加载完成后整体替换旧列表，正在进行的生成仍使用旧的表情直到完成。
+/// 配置文件解析失败时返回错误，保留当前的配置和表情。
+/// 编码、解码和字体相关的配置不会重新加载，修改后需要重启。
+/// 已加载的动态库不会被重新加载，文件已更新的动态库在结果的 `restart_required` 中列出
+pub fn reload_memes() -> Result<ReloadResult, Error> {
+    let _guard = RELOAD_LOCK.lock().unwrap();
+    reload_config()?;
+    let mut loaded = load_memes();
+    let old_loaded = loaded_memes();
+
+    // 加载仍被引用的动态库得到的是旧版本，保留旧的修改时间以便之后继续提示
+    let mut restart_required = Vec::new();
+    for (path, modified) in loaded.libraries.iter_mut() {
+        let old_modified = old_loaded.libraries.get(path);
+        if let Some(old_modified) = old_modified.filter(|old_modified| *old_modified != modified) {
+            *modified = *old_modified;
+            let name = path.file_name().unwrap_or_default();
+            warn!("Library {name:?} has been updated, restart to load the new version");
+            restart_required.push(name.to_string_lossy().into_owned());
+        }
+    }
+    restart_required.sort();
+
+    let loaded = Arc::new(loaded);
+    *LOADED_MEMES.write().unwrap() = Arc::clone(&loaded);
+    let (memes, old_memes) = (&loaded.memes, &old_loaded.memes);
+
+    let mut added = memes
+        .keys()
+        .filter(|key| !old_memes.contains_key(*key))
+        .cloned()
+        .collect::<Vec<_>>();
+    added.sort();
+    let mut removed = old_memes
+        .keys()
+        .filter(|key| !memes.contains_key(*key))
+        .cloned()
+        .collect::<Vec<_>>();
+    removed.sort();
+    Ok(ReloadResult {
+        added,
+        removed,
+        total: memes.len(),
+        restart_required,
+    })
+}
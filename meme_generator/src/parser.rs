@@ -0,0 +1,389 @@
+use std::{collections::HashMap, error, fmt};
+
+use serde::Serialize;
+
+use meme_generator_core::{
+    error::Error,
+    meme::{MemeOption, MemeParams, OptionValue, ParserFlags},
+};
+
+use crate::memes::{get_meme, get_memes};
+
+#[derive(Debug)]
+pub enum ParseError {
+    MemeNotFound(String),
+    UnclosedQuote,
+    UnknownOption(String),
+    MissingValue(String),
+    /// 选项的值不符合其声明，为 [`Error::InvalidOptionValue`]
+    InvalidOption(Error),
+}
+
+impl ParseError {
+    /// 与 [`Error::code`] 使用同一套错误码，选项值无效时与生成表情时相同
+    pub fn code(&self) -> u16 {
+        match self {
+            ParseError::MemeNotFound(_) => 580,
+            ParseError::UnclosedQuote => 581,
+            ParseError::UnknownOption(_) => 582,
+            ParseError::MissingValue(_) => 583,
+            ParseError::InvalidOption(err) => err.code(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MemeNotFound(command) => write!(f, "Meme `{command}` not found"),
+            ParseError::UnclosedQuote => write!(f, "Unclosed quote in arguments"),
+            ParseError::UnknownOption(option) => write!(f, "Unknown option `{option}`"),
+            ParseError::MissingValue(option) => write!(f, "Option `{option}` requires a value"),
+            ParseError::InvalidOption(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParsedArgs {
+    pub texts: Vec<String>,
+    pub options: HashMap<String, OptionValue>,
+    /// 以 `@` 开头的参数，去掉 `@` 后作为图片引用，由调用方换成对应的图片
+    pub images: Vec<String>,
+}
+
+/// 解析后的表情调用
+#[derive(Debug, Clone, Serialize)]
+pub struct ParsedCommand {
+    pub key: String,
+    #[serde(flatten)]
+    pub args: ParsedArgs,
+}
+
+/// 按照 shell 的规则分割参数，支持单引号、双引号和反斜杠转义
+fn split_args(args: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_token = false;
+    let mut quote = None;
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    token.push(next);
+                }
+                in_token = true;
+            }
+            (Some(_), c) => token.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_token = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            (None, c) => {
+                token.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(ParseError::UnclosedQuote);
+    }
+    if in_token {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn find_long<'a>(options: &'a [MemeOption], name: &str) -> Option<&'a MemeOption> {
+    options.iter().find(|option| {
+        let flags = parser_flags(option);
+        (flags.long && option.name() == name) || flags.long_aliases.iter().any(|a| a == name)
+    })
+}
+
+fn find_short(options: &[MemeOption], name: char) -> Option<&MemeOption> {
+    options.iter().find(|option| {
+        let flags = parser_flags(option);
+        (flags.short && option.name().starts_with(name)) || flags.short_aliases.contains(&name)
+    })
+}
+
+fn parser_flags(option: &MemeOption) -> &ParserFlags {
+    match option {
+        MemeOption::Boolean { parser_flags, .. }
+        | MemeOption::String { parser_flags, .. }
+        | MemeOption::Integer { parser_flags, .. }
+        | MemeOption::Float { parser_flags, .. } => parser_flags,
+    }
+}
+
+fn takes_value(option: &MemeOption) -> bool {
+    !matches!(option, MemeOption::Boolean { .. })
+}
+
+/// 将参数转换为选项声明的类型，并检查取值范围和可选项
+fn parse_value(option: &MemeOption, value: Option<String>) -> Result<OptionValue, ParseError> {
+    let name = option.name().to_string();
+    let invalid = |value: &str, expected: &str| {
+        ParseError::InvalidOption(Error::InvalidOptionValue {
+            name: name.clone(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        })
+    };
+    let value = match (option, value) {
+        (MemeOption::Boolean { default, .. }, None) => {
+            OptionValue::Boolean(!default.unwrap_or(false))
+        }
+        (MemeOption::Boolean { .. }, Some(value)) => match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => OptionValue::Boolean(true),
+            "false" | "no" | "off" | "0" => OptionValue::Boolean(false),
+            _ => return Err(invalid(&value, "a boolean")),
+        },
+        (_, None) => return Err(ParseError::MissingValue(option.name().to_string())),
+        (MemeOption::String { .. }, Some(value)) => OptionValue::String(value),
+        (MemeOption::Integer { .. }, Some(value)) => match value.parse() {
+            Ok(value) => OptionValue::Integer(value),
+            Err(_) => return Err(invalid(&value, "an integer")),
+        },
+        (MemeOption::Float { .. }, Some(value)) => match value.parse() {
+            Ok(value) => OptionValue::Float(value),
+            Err(_) => return Err(invalid(&value, "a float")),
+        },
+    };
+    option.validate(&value).map_err(ParseError::InvalidOption)?;
+    Ok(value)
+}
+
+/// 按照表情选项的 [`ParserFlags`] 解析参数
+///
+/// 支持 `--name value`、`--name=value`、`-n value` 和组合的短布尔选项 `-ab`，`--` 之后的参数都视为文字。
+pub fn parse_args(params: &MemeParams, args: &str) -> Result<ParsedArgs, ParseError> {
+    let mut parsed = ParsedArgs::default();
+    let mut tokens = split_args(args)?.into_iter();
+    let mut only_positional = false;
+
+    while let Some(token) = tokens.next() {
+        if !only_positional && token == "--" {
+            only_positional = true;
+        } else if let Some(long) = token.strip_prefix("--").filter(|_| !only_positional) {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let option = find_long(&params.options, name)
+                .ok_or_else(|| ParseError::UnknownOption(format!("--{name}")))?;
+            let value = if value.is_none() && takes_value(option) {
+                tokens.next()
+            } else {
+                value
+            };
+            parsed
+                .options
+                .insert(option.name().to_string(), parse_value(option, value)?);
+        } else if let Some(short) = token
+            .strip_prefix('-')
+            .filter(|short| !only_positional && !short.is_empty())
+            .filter(|short| !short.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            for (index, c) in short.char_indices() {
+                let option = find_short(&params.options, c)
+                    .ok_or_else(|| ParseError::UnknownOption(format!("-{c}")))?;
+                if !takes_value(option) {
+                    parsed
+                        .options
+                        .insert(option.name().to_string(), parse_value(option, None)?);
+                    continue;
+                }
+                let rest = &short[index + c.len_utf8()..];
+                let value = match rest.strip_prefix('=').unwrap_or(rest) {
+                    "" => tokens.next(),
+                    rest => Some(rest.to_string()),
+                };
+                parsed
+                    .options
+                    .insert(option.name().to_string(), parse_value(option, value)?);
+                break;
+            }
+        } else if let Some(image) = token.strip_prefix('@').filter(|_| !only_positional) {
+            parsed.images.push(image.to_string());
+        } else {
+            parsed.texts.push(token);
+        }
+    }
+    Ok(parsed)
+}
+
+/// 解析一条表情指令，`command` 为表情的键或关键词，`args` 为剩余的参数文字
+pub fn parse_command(command: &str, args: &str) -> Result<ParsedCommand, ParseError> {
+    let meme = get_meme(command)
+        .or_else(|| {
+            get_memes().into_iter().find(|meme| {
+                meme.info()
+                    .keywords
+                    .iter()
+                    .any(|keyword| keyword == command)
+            })
+        })
+        .ok_or_else(|| ParseError::MemeNotFound(command.to_string()))?;
+    let info = meme.info();
+    Ok(ParsedCommand {
+        args: parse_args(&info.params, args)?,
+        key: info.key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(short: bool, long_aliases: &[&str]) -> ParserFlags {
+        ParserFlags {
+            short,
+            long: true,
+            short_aliases: Vec::new(),
+            long_aliases: long_aliases.iter().map(|alias| alias.to_string()).collect(),
+        }
+    }
+
+    fn params() -> MemeParams {
+        MemeParams {
+            options: vec![
+                MemeOption::Boolean {
+                    name: "circle".to_string(),
+                    default: Some(false),
+                    description: None,
+                    parser_flags: flags(true, &[]),
+                },
+                MemeOption::Boolean {
+                    name: "bold".to_string(),
+                    default: Some(false),
+                    description: None,
+                    parser_flags: flags(true, &[]),
+                },
+                MemeOption::Integer {
+                    name: "number".to_string(),
+                    default: Some(1),
+                    minimum: Some(1),
+                    maximum: Some(10),
+                    description: None,
+                    parser_flags: flags(true, &[]),
+                },
+                MemeOption::String {
+                    name: "position".to_string(),
+                    default: None,
+                    choices: Some(vec!["left".to_string(), "right".to_string()]),
+                    description: None,
+                    parser_flags: flags(false, &["pos"]),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn parse(args: &str) -> ParsedArgs {
+        parse_args(&params(), args).unwrap()
+    }
+
+    fn boolean(parsed: &ParsedArgs, name: &str) -> Option<bool> {
+        match parsed.options.get(name) {
+            Some(OptionValue::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn integer(parsed: &ParsedArgs, name: &str) -> Option<i32> {
+        match parsed.options.get(name) {
+            Some(OptionValue::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn split_quotes() {
+        let tokens = split_args(r#"a "b c" 'd e' f\ g "h\"i" 'j\k' """#).unwrap();
+        assert_eq!(tokens, ["a", "b c", "d e", "f g", "h\"i", "j\\k", ""]);
+        assert_eq!(split_args("  a\t b \n").unwrap(), ["a", "b"]);
+        assert_eq!(split_args("a\"b c\"d").unwrap(), ["ab cd"]);
+    }
+
+    #[test]
+    fn split_unclosed_quote() {
+        assert!(matches!(
+            split_args("\"abc"),
+            Err(ParseError::UnclosedQuote)
+        ));
+        assert!(matches!(split_args("a 'b"), Err(ParseError::UnclosedQuote)));
+    }
+
+    #[test]
+    fn texts_and_images() {
+        let parsed = parse("hello \"hello world\" @avatar -5");
+        assert_eq!(parsed.texts, ["hello", "hello world", "-5"]);
+        assert_eq!(parsed.images, ["avatar"]);
+        assert!(parsed.options.is_empty());
+    }
+
+    #[test]
+    fn double_dash() {
+        let parsed = parse("--circle -- --bold -n @avatar");
+        assert_eq!(boolean(&parsed, "circle"), Some(true));
+        assert_eq!(boolean(&parsed, "bold"), None);
+        assert_eq!(parsed.texts, ["--bold", "-n", "@avatar"]);
+        assert!(parsed.images.is_empty());
+    }
+
+    #[test]
+    fn combined_short_options() {
+        let parsed = parse("-cb");
+        assert_eq!(boolean(&parsed, "circle"), Some(true));
+        assert_eq!(boolean(&parsed, "bold"), Some(true));
+
+        // 需要值的短选项之后的部分作为它的值
+        let parsed = parse("-cn3");
+        assert_eq!(boolean(&parsed, "circle"), Some(true));
+        assert_eq!(integer(&parsed, "number"), Some(3));
+    }
+
+    #[test]
+    fn option_values() {
+        for args in ["--number=5", "--number 5", "-n5", "-n=5", "-n 5"] {
+            assert_eq!(integer(&parse(args), "number"), Some(5), "{args}");
+        }
+        let parsed = parse("--pos=left --circle=false");
+        assert!(matches!(
+            parsed.options.get("position"),
+            Some(OptionValue::String(value)) if value == "left"
+        ));
+        assert_eq!(boolean(&parsed, "circle"), Some(false));
+    }
+
+    #[test]
+    fn option_errors() {
+        let err = |args: &str| parse_args(&params(), args).unwrap_err();
+        assert!(matches!(err("--size"), ParseError::UnknownOption(option) if option == "--size"));
+        assert!(matches!(err("-x"), ParseError::UnknownOption(option) if option == "-x"));
+        assert!(matches!(err("--number"), ParseError::MissingValue(option) if option == "number"));
+        for args in ["--number=abc", "-n 20", "--pos=top"] {
+            let err = err(args);
+            assert!(
+                matches!(
+                    err,
+                    ParseError::InvalidOption(Error::InvalidOptionValue { .. })
+                ),
+                "{args}"
+            );
+            assert_eq!(err.code(), 541);
+        }
+    }
+}
@@ -1,43 +1,214 @@
-use std::{collections::HashMap, fs::DirEntry, path::PathBuf, rc::Rc, sync::LazyLock};
+use std::{
+    collections::HashMap,
+    env::consts::DLL_PREFIX,
+    fs::{self, DirEntry},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
 
 use libloading::Library;
 
 use meme_generator_core::{
     config::MEME_HOME,
     error::Error,
-    meme::{Image, Meme, MemeInfo, OptionValue},
-    registry::{CORE_VERSION, MemePackDeclaration, RUSTC_VERSION},
+    meme::{GeneratedImage, Image, Meme, MemeInfo, OptionValue},
+    plugin::{PluginError, load_plugin},
+    registry::{CORE_VERSION, MemePackDeclaration, MemeRegistry as _, RUSTC_VERSION},
 };
+use meme_generator_utils::template::{MemeTemplate, TemplateMeme};
 use tracing::{info, warn};
 
-use crate::config::CONFIG;
+use crate::{
+    config::{Config, config},
+    shortcuts::ShortcutMatcher,
+    wasm::load_wasm_plugin,
+};
 
 const LIBRARIES_DIR: LazyLock<PathBuf> = LazyLock::new(|| MEME_HOME.join("libraries"));
+const TEMPLATES_DIR: LazyLock<PathBuf> = LazyLock::new(|| MEME_HOME.join("templates"));
+
+const BUILTIN_PACK: &str = "builtin";
+const TEMPLATE_PACK: &str = "templates";
+
+struct RegisteredMeme {
+    pack: String,
+    key: String,
+    meme: Box<dyn Meme>,
+}
 
 struct MemeRegistry {
-    memes: HashMap<String, Box<dyn Meme>>,
+    /// 当前正在加载的表情包
+    pack: String,
+    memes: Vec<RegisteredMeme>,
+    disabled_list: Vec<String>,
+    /// 已加载的动态库及其修改时间
+    libraries: HashMap<PathBuf, SystemTime>,
 }
 
 impl MemeRegistry {
-    fn new() -> Self {
+    fn new(disabled_list: Vec<String>) -> Self {
         Self {
-            memes: HashMap::default(),
+            pack: BUILTIN_PACK.to_string(),
+            memes: Vec::new(),
+            disabled_list,
+            libraries: HashMap::new(),
         }
     }
+
+    /// 记录已加载的动态库，用于在重新加载时检查文件是否已更新
+    fn add_library(&mut self, path: &Path) {
+        if let Ok(modified) = fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            self.libraries.insert(path.to_path_buf(), modified);
+        }
+    }
+
+    fn set_pack(&mut self, pack: impl Into<String>) {
+        self.pack = pack.into();
+    }
+
+    /// 按优先级决定不带命名空间的键对应哪个表情，未被选中的表情只能通过 `pack:key` 访问
+    fn into_loaded_memes(self, pack_priority: &[String]) -> LoadedMemes {
+        let rank = |pack: &str| {
+            let position = pack_priority
+                .iter()
+                .position(|name| name == pack)
+                .unwrap_or(pack_priority.len());
+            (position, pack.to_string())
+        };
+
+        let mut groups: HashMap<String, Vec<RegisteredMeme>> = HashMap::new();
+        for meme in self.memes {
+            groups.entry(meme.key.clone()).or_default().push(meme);
+        }
+
+        let mut loaded = LoadedMemes {
+            libraries: self.libraries,
+            ..Default::default()
+        };
+        for (key, mut candidates) in groups {
+            candidates.sort_by_cached_key(|candidate| rank(&candidate.pack));
+            if candidates.len() > 1 {
+                let packs = candidates
+                    .iter()
+                    .map(|candidate| format!("`{}`", candidate.pack))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                warn!(
+                    "Meme `{key}` is provided by {packs}, `{key}` refers to the one from `{}`",
+                    candidates[0].pack
+                );
+            }
+            for (index, candidate) in candidates.into_iter().enumerate() {
+                let namespaced_key = format!("{}:{}", candidate.pack, key);
+                if loaded.namespaced.contains_key(&namespaced_key) {
+                    warn!(
+                        "Meme `{namespaced_key}` is registered more than once, ignoring the later one"
+                    );
+                    continue;
+                }
+                let exposed_key = if index == 0 {
+                    key.clone()
+                } else {
+                    namespaced_key.clone()
+                };
+                let meme: Arc<dyn Meme> = Arc::new(PackMeme {
+                    key: exposed_key.clone(),
+                    pack: candidate.pack,
+                    meme: candidate.meme,
+                });
+                loaded.namespaced.insert(namespaced_key, Arc::clone(&meme));
+                loaded.memes.insert(exposed_key, meme);
+            }
+        }
+        loaded.shortcuts = ShortcutMatcher::new(loaded.memes.values());
+        loaded
+    }
 }
 
 impl meme_generator_core::registry::MemeRegistry for MemeRegistry {
     fn register_meme(&mut self, key: &str, meme: Box<dyn Meme>) {
-        if CONFIG.meme.meme_disabled_list.contains(&key.to_string()) {
+        let namespaced_key = format!("{}:{}", self.pack, key);
+        if self
+            .disabled_list
+            .iter()
+            .any(|disabled| disabled == key || *disabled == namespaced_key)
+        {
             return;
         }
-        self.memes.insert(key.to_string(), meme);
+        self.memes.push(RegisteredMeme {
+            pack: self.pack.clone(),
+            key: key.to_string(),
+            meme,
+        });
+    }
+}
+
+/// 加载后的表情
+#[derive(Default)]
+pub(crate) struct LoadedMemes {
+    /// 表情列表，键冲突时优先级较低的表情以 `pack:key` 作为键
+    pub(crate) memes: HashMap<String, Arc<dyn Meme>>,
+    /// 所有表情的 `pack:key`
+    pub(crate) namespaced: HashMap<String, Arc<dyn Meme>>,
+    pub(crate) shortcuts: ShortcutMatcher,
+    /// 已加载的动态库及加载时的修改时间
+    pub(crate) libraries: HashMap<PathBuf, SystemTime>,
+}
+
+/// 记录表情所属的表情包，`key` 为表情在列表中实际使用的键
+struct PackMeme {
+    key: String,
+    pack: String,
+    meme: Box<dyn Meme>,
+}
+
+impl Meme for PackMeme {
+    fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    fn info(&self) -> MemeInfo {
+        MemeInfo {
+            key: self.key.clone(),
+            pack: self.pack.clone(),
+            ..self.meme.info()
+        }
+    }
+
+    fn generate(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        self.meme.generate(images, texts, options)
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        self.meme.generate_preview(options)
+    }
+
+    fn generate_image(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        self.meme.generate_image(images, texts, options)
+    }
+
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        self.meme.generate_preview_image(options)
     }
 }
 
 struct ExternalMeme {
     meme: Box<dyn Meme>,
-    _library: Rc<Library>,
+    _library: Arc<Library>,
 }
 
 impl Meme for ExternalMeme {
@@ -61,18 +232,34 @@ impl Meme for ExternalMeme {
     fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
         self.meme.generate_preview(options)
     }
+
+    fn generate_image(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        self.meme.generate_image(images, texts, options)
+    }
+
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        self.meme.generate_preview_image(options)
+    }
 }
 
 unsafe impl Send for ExternalMeme {}
 unsafe impl Sync for ExternalMeme {}
 
 struct ExternalMemeRegistry {
-    library: Rc<Library>,
+    library: Arc<Library>,
     memes: HashMap<String, ExternalMeme>,
 }
 
 impl ExternalMemeRegistry {
-    fn new(library: Rc<Library>) -> Self {
+    fn new(library: Arc<Library>) -> Self {
         Self {
             library,
             memes: HashMap::default(),
@@ -82,23 +269,21 @@ impl ExternalMemeRegistry {
 
 impl meme_generator_core::registry::MemeRegistry for ExternalMemeRegistry {
     fn register_meme(&mut self, key: &str, meme: Box<dyn Meme>) {
-        if CONFIG.meme.meme_disabled_list.contains(&key.to_string()) {
-            return;
-        }
         self.memes.insert(
             key.to_string(),
             ExternalMeme {
                 meme,
-                _library: Rc::clone(&self.library),
+                _library: Arc::clone(&self.library),
             },
         );
     }
 }
 
-unsafe fn load_library(
+/// 加载与 rustc 版本绑定的旧式表情包，需要开启 `load_legacy_libraries`
+unsafe fn load_legacy_library(
     library_path: &DirEntry,
 ) -> Result<Option<HashMap<String, ExternalMeme>>, libloading::Error> {
-    let library = Rc::new(unsafe { Library::new(library_path.path()) }?);
+    let library = Arc::new(unsafe { Library::new(library_path.path()) }?);
 
     let declaration = unsafe {
         library
@@ -131,7 +316,20 @@ unsafe fn load_library(
     Ok(Some(registry.memes))
 }
 
-fn load_external_memes(registry: &mut MemeRegistry) -> Result<(), std::io::Error> {
+/// 外部表情包以文件名作为命名空间，如 `libcontrib.so` 为 `contrib`
+fn pack_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("wasm") => stem.as_str(),
+        _ => stem.strip_prefix(DLL_PREFIX).unwrap_or(&stem),
+    };
+    stem.replace(':', "_")
+}
+
+fn load_external_memes(registry: &mut MemeRegistry, config: &Config) -> Result<(), std::io::Error> {
     if !LIBRARIES_DIR.exists() {
         return Ok(());
     }
@@ -142,10 +340,59 @@ fn load_external_memes(registry: &mut MemeRegistry) -> Result<(), std::io::Error
             continue;
         }
         let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        registry.set_pack(pack_name(&path));
+        if ext == "wasm" {
+            match load_wasm_plugin(&path) {
+                Ok(memes) => {
+                    info!(
+                        "Loaded wasm plugin {:?} with {} memes",
+                        entry.file_name(),
+                        memes.len()
+                    );
+                    for meme in memes {
+                        registry.register_meme(&meme.key(), Box::new(meme));
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to load wasm plugin {:?}: {}",
+                        entry.file_name(),
+                        err
+                    );
+                }
+            }
+            continue;
+        }
         if !["dll", "so", "dylib"].contains(&ext) {
             continue;
         }
-        match unsafe { load_library(&entry) } {
+        match unsafe { load_plugin(&path) } {
+            Ok(memes) => {
+                info!(
+                    "Loaded plugin {:?} with {} memes",
+                    entry.file_name(),
+                    memes.len()
+                );
+                for meme in memes {
+                    registry.register_meme(&meme.key(), Box::new(meme));
+                }
+                registry.add_library(&path);
+                continue;
+            }
+            Err(PluginError::NotAPlugin) => {}
+            Err(err) => {
+                warn!("Failed to load plugin {:?}: {}", entry.file_name(), err);
+                continue;
+            }
+        }
+        if !config.meme.load_legacy_libraries {
+            warn!(
+                "Library {:?} is not a meme plugin, set `load_legacy_libraries` to load it as a legacy library",
+                entry.file_name(),
+            );
+            continue;
+        }
+        match unsafe { load_legacy_library(&entry) } {
             Ok(Some(memes)) => {
                 info!(
                     "Loaded library {:?} with {} memes",
@@ -153,8 +400,9 @@ fn load_external_memes(registry: &mut MemeRegistry) -> Result<(), std::io::Error
                     memes.len()
                 );
                 for (key, meme) in memes {
-                    registry.memes.insert(key, Box::new(meme));
+                    registry.register_meme(&key, Box::new(meme));
                 }
+                registry.add_library(&path);
             }
             Ok(None) => {}
             Err(err) => {
@@ -165,18 +413,67 @@ fn load_external_memes(registry: &mut MemeRegistry) -> Result<(), std::io::Error
     Ok(())
 }
 
-pub(crate) fn load_memes() -> HashMap<String, Box<dyn Meme>> {
-    let mut registry = MemeRegistry::new();
+fn load_template(path: &Path) -> Result<TemplateMeme, Error> {
+    let content =
+        fs::read_to_string(path).map_err(|err| Error::DeserializeError(err.to_string()))?;
+    let template: MemeTemplate = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content)
+            .map_err(|err| Error::DeserializeError(err.to_string()))?,
+        _ => toml::from_str(&content).map_err(|err| Error::DeserializeError(err.to_string()))?,
+    };
+    TemplateMeme::new(template, TEMPLATES_DIR.clone())
+}
+
+fn load_template_memes(registry: &mut MemeRegistry) -> Result<(), std::io::Error> {
+    if !TEMPLATES_DIR.exists() {
+        return Ok(());
+    }
+    registry.set_pack(TEMPLATE_PACK);
+    for entry in TEMPLATES_DIR.read_dir()? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        if !["toml", "json"].contains(&ext) {
+            continue;
+        }
+        match load_template(&path) {
+            Ok(meme) => {
+                registry.register_meme(&meme.key(), Box::new(meme));
+            }
+            Err(err) => {
+                warn!("Failed to load template {:?}: {}", entry.file_name(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按照当前配置加载所有表情
+///
+/// 旧的动态库在仍被引用时不会被卸载，此时加载同一路径会得到旧版本，更新动态库时应使用新的文件名或重启
+pub(crate) fn load_memes() -> LoadedMemes {
+    let config = config();
+    let mut registry = MemeRegistry::new(config.meme.meme_disabled_list.clone());
 
-    if CONFIG.meme.load_builtin_memes {
+    if config.meme.load_builtin_memes {
+        registry.set_pack(BUILTIN_PACK);
         meme_generator_memes::register_memes(&mut registry);
     }
 
-    if CONFIG.meme.load_external_memes {
-        if let Err(err) = load_external_memes(&mut registry) {
+    if config.meme.load_external_memes {
+        if let Err(err) = load_external_memes(&mut registry, &config) {
             warn!("Error while loading external memes: {}", err);
         }
     }
 
-    registry.memes
+    if config.meme.load_template_memes {
+        if let Err(err) = load_template_memes(&mut registry) {
+            warn!("Error while loading template memes: {}", err);
+        }
+    }
+
+    registry.into_loaded_memes(&config.meme.pack_priority)
 }
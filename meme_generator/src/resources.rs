@@ -13,9 +13,12 @@ use tokio::{
 };
 use tracing::{info, warn};
 
-use meme_generator_utils::config::{FONTS_DIR, IMAGES_DIR};
+use meme_generator_utils::{
+    config::{FONTS_DIR, IMAGES_DIR},
+    text::reload_fonts,
+};
 
-use crate::config::CONFIG;
+use crate::config::config;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -36,15 +39,17 @@ fn resource_url(base_url: &str, name: &str) -> String {
 }
 
 pub async fn check_resources(base_url: Option<String>) {
-    let base_url = base_url.unwrap_or(CONFIG.resource.resource_url.clone());
+    let base_url = base_url.unwrap_or(config().resource.resource_url.clone());
     let client = Client::new();
     let resources = match fetch_resource_list(&client, &base_url).await {
         Some(resources) => resources,
         None => return,
     };
 
-    if CONFIG.resource.download_fonts {
-        download_resources(&client, &base_url, "fonts", &resources.fonts).await;
+    if config().resource.download_fonts
+        && download_resources(&client, &base_url, "fonts", &resources.fonts).await
+    {
+        reload_fonts();
     }
     download_resources(&client, &base_url, "images", &resources.images).await;
 }
@@ -77,16 +82,17 @@ async fn fetch_resource_list(client: &Client, base_url: &str) -> Option<Resource
     }
 }
 
+/// 返回是否下载了文件
 async fn download_resources(
     client: &Client,
     base_url: &str,
     resource_type: &str,
     resources: &[FileWithHash],
-) {
+) -> bool {
     let resources_dir = match resource_type {
         "fonts" => &FONTS_DIR,
         "images" => &IMAGES_DIR,
-        _ => return,
+        _ => return false,
     };
 
     let mut to_download = vec![];
@@ -98,7 +104,7 @@ async fn download_resources(
     }
     let total_files = to_download.len();
     if total_files == 0 {
-        return;
+        return false;
     }
 
     let pb = ProgressBar::new(total_files as u64);
@@ -140,6 +146,7 @@ async fn download_resources(
     }
 
     pb.finish();
+    true
 }
 
 async fn is_file_hash_equal(file_path: &Path, expected_hash: &str) -> bool {
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, sync::Arc};
+
+use regex::{Captures, Regex};
+use serde::Serialize;
+use tracing::warn;
+
+use meme_generator_core::meme::{Meme, MemeShortcut, OptionValue};
+
+use crate::memes::loaded_memes;
+
+/// 快捷指令匹配后得到的表情调用
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcutInvocation {
+    pub key: String,
+    /// 快捷指令中的文字，之后为消息剩余部分按空白分割得到的文字
+    pub texts: Vec<String>,
+    pub options: HashMap<String, OptionValue>,
+    /// 图片名称提示，如 `{name}` 替换后的用户名
+    pub names: Vec<String>,
+    /// 匹配到的快捷指令
+    pub pattern: String,
+}
+
+/// 不同表情的快捷指令可能匹配同一条消息
+#[derive(Debug, Clone, Serialize)]
+pub struct ShortcutOverlap {
+    pub key: String,
+    pub pattern: String,
+    pub other_key: String,
+    pub other_pattern: String,
+    /// 两个快捷指令都能匹配的消息
+    pub example: String,
+}
+
+struct CompiledShortcut {
+    key: String,
+    shortcut: MemeShortcut,
+    /// 从消息开头匹配
+    prefix: Regex,
+    /// 匹配整条消息
+    full: Regex,
+}
+
+impl CompiledShortcut {
+    fn new(key: &str, shortcut: &MemeShortcut) -> Result<Self, regex::Error> {
+        Ok(Self {
+            key: key.to_string(),
+            shortcut: shortcut.clone(),
+            prefix: Regex::new(&format!("^(?:{})", shortcut.pattern))?,
+            full: Regex::new(&format!("^(?:{})$", shortcut.pattern))?,
+        })
+    }
+
+    fn invoke(&self, message: &str) -> Option<(usize, ShortcutInvocation)> {
+        let captures = self.prefix.captures(message)?;
+        let matched = captures.get(0)?.end();
+        let rest = &message[matched..];
+        // 快捷指令之后需要是空白或消息结尾，避免只匹配到词的一部分
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let substitute = |value: &str| substitute(value, &self.prefix, &captures);
+        let mut texts = self
+            .shortcut
+            .texts
+            .iter()
+            .map(|text| substitute(text))
+            .collect::<Vec<_>>();
+        texts.extend(rest.split_whitespace().map(str::to_string));
+        let names = self
+            .shortcut
+            .names
+            .iter()
+            .map(|name| substitute(name))
+            .collect();
+        let options = self
+            .shortcut
+            .options
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    OptionValue::String(value) => OptionValue::String(substitute(value)),
+                    value => value.clone(),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        Some((
+            matched,
+            ShortcutInvocation {
+                key: self.key.clone(),
+                texts,
+                options,
+                names,
+                pattern: self.shortcut.pattern.clone(),
+            },
+        ))
+    }
+
+    /// 用于检测重叠的示例消息：不含正则语法的快捷指令本身，以及将 `xx` 替换后的说明文字
+    fn examples(&self) -> Vec<String> {
+        let mut examples = Vec::new();
+        if regex::escape(&self.shortcut.pattern) == self.shortcut.pattern {
+            examples.push(self.shortcut.pattern.clone());
+        }
+        if let Some(humanized) = &self.shortcut.humanized {
+            examples.push(humanized.replace("xx", "测试"));
+        }
+        examples
+    }
+}
+
+/// 将 `{name}` 替换为同名捕获组匹配到的内容，未匹配的捕获组替换为空字符串
+fn substitute(value: &str, regex: &Regex, captures: &Captures) -> String {
+    let mut value = value.to_string();
+    for name in regex.capture_names().flatten() {
+        let captured = captures.name(name).map_or("", |m| m.as_str());
+        value = value.replace(&format!("{{{name}}}"), captured);
+    }
+    value
+}
+
+/// 编译后的所有表情的快捷指令，随表情列表一起加载
+#[derive(Default)]
+pub(crate) struct ShortcutMatcher {
+    shortcuts: Vec<CompiledShortcut>,
+}
+
+impl ShortcutMatcher {
+    pub(crate) fn new<'a>(memes: impl Iterator<Item = &'a Arc<dyn Meme>>) -> Self {
+        let mut memes = memes.collect::<Vec<_>>();
+        memes.sort_by_key(|meme| meme.key());
+        let mut shortcuts = Vec::new();
+        for meme in memes {
+            let info = meme.info();
+            for shortcut in &info.shortcuts {
+                match CompiledShortcut::new(&info.key, shortcut) {
+                    Ok(shortcut) => shortcuts.push(shortcut),
+                    Err(err) => warn!(
+                        "Invalid shortcut pattern `{}` of meme `{}`: {}",
+                        shortcut.pattern, info.key, err
+                    ),
+                }
+            }
+        }
+        Self { shortcuts }
+    }
+
+    fn match_all(&self, message: &str) -> Vec<ShortcutInvocation> {
+        let message = message.trim();
+        let mut matches = self
+            .shortcuts
+            .iter()
+            .filter_map(|shortcut| shortcut.invoke(message))
+            .collect::<Vec<_>>();
+        // 匹配内容较长的快捷指令更具体，排在前面
+        matches.sort_by_key(|(matched, _)| std::cmp::Reverse(*matched));
+        matches
+            .into_iter()
+            .map(|(_, invocation)| invocation)
+            .collect()
+    }
+
+    /// 每对冲突的快捷指令只报告一次，任一方的示例能被另一方匹配即视为冲突
+    fn overlaps(&self) -> Vec<ShortcutOverlap> {
+        let mut overlaps = Vec::new();
+        for (i, shortcut) in self.shortcuts.iter().enumerate() {
+            for other in &self.shortcuts[i + 1..] {
+                if other.key == shortcut.key {
+                    continue;
+                }
+                let example = if other.shortcut.pattern == shortcut.shortcut.pattern {
+                    Some(shortcut.shortcut.pattern.clone())
+                } else {
+                    shortcut
+                        .examples()
+                        .into_iter()
+                        .find(|example| other.full.is_match(example))
+                        .or_else(|| {
+                            other
+                                .examples()
+                                .into_iter()
+                                .find(|example| shortcut.full.is_match(example))
+                        })
+                };
+                if let Some(example) = example {
+                    overlaps.push(ShortcutOverlap {
+                        key: shortcut.key.clone(),
+                        pattern: shortcut.shortcut.pattern.clone(),
+                        other_key: other.key.clone(),
+                        other_pattern: other.shortcut.pattern.clone(),
+                        example,
+                    });
+                }
+            }
+        }
+        overlaps
+    }
+}
+
+/// 匹配消息开头的快捷指令，返回最具体的一个
+pub fn match_shortcut(message: &str) -> Option<ShortcutInvocation> {
+    match_shortcuts(message).into_iter().next()
+}
+
+/// 匹配消息开头的所有快捷指令，匹配内容较长的排在前面
+pub fn match_shortcuts(message: &str) -> Vec<ShortcutInvocation> {
+    loaded_memes().shortcuts.match_all(message)
+}
+
+/// 检测不同表情之间可能匹配同一条消息的快捷指令
+///
+/// 通过不含正则语法的快捷指令和快捷指令的说明文字构造示例消息进行检测，不能保证找出所有重叠
+pub fn find_shortcut_overlaps() -> Vec<ShortcutOverlap> {
+    loaded_memes().shortcuts.overlaps()
+}
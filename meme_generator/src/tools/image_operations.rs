@@ -1,25 +1,48 @@
 use meme_generator_core::{error::Error, meme};
 use meme_generator_utils::{
     builder::InputImage,
-    decoder::CodecExt,
+    decoder::{CodecExt, DecodeBudget, FrameDecoder},
     encoder::{GifEncoder, encode_png, make_png_or_gif},
     image::{Fit, ImageExt},
     tools::new_surface,
 };
 use serde::{Deserialize, Serialize};
-use skia_safe::{Codec, Data, IRect, Image};
+use skia_safe::{IRect, Image};
 
-fn decode_image(data: Vec<u8>) -> Result<Codec<'static>, Error> {
-    let data = Data::new_copy(&data);
-    Codec::from_data(data).ok_or(Error::ImageDecodeError("Skia decode error".to_string()))
+fn decode_image(data: Vec<u8>) -> Result<FrameDecoder<'static>, Error> {
+    decode_images(vec![data]).map(|mut codecs| codecs.remove(0))
+}
+
+/// 解码多张图片，所有图片共用解码后的字节数限制
+fn decode_images(images: Vec<Vec<u8>>) -> Result<Vec<FrameDecoder<'static>>, Error> {
+    let mut budget = DecodeBudget::default();
+    images
+        .iter()
+        .enumerate()
+        .map(|(index, data)| {
+            FrameDecoder::from_input(data, &mut budget).map_err(|err| err.into_error(index, ""))
+        })
+        .collect()
 }
 
 fn input_image(data: Vec<u8>) -> Result<InputImage<'static>, Error> {
-    let image = meme::Image {
-        name: String::new(),
-        data,
-    };
-    InputImage::from(&image)
+    input_images(vec![data]).map(|mut images| images.remove(0))
+}
+
+/// 解码多张图片，所有图片共用解码后的字节数限制
+fn input_images(images: Vec<Vec<u8>>) -> Result<Vec<InputImage<'static>>, Error> {
+    let mut budget = DecodeBudget::default();
+    images
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let image = meme::Image {
+                name: String::new(),
+                data,
+            };
+            InputImage::from(&image, index, &mut budget)
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +55,8 @@ pub struct ImageInfo {
 }
 
 pub fn inspect(image: Vec<u8>) -> Result<ImageInfo, Error> {
-    let mut codec = decode_image(image)?;
+    // 只读取图片头部，不受输入限制
+    let mut codec = FrameDecoder::from_data(&image)?;
     let is_multi_frame = codec.is_multi_frame();
     let frame_count = if is_multi_frame {
         Some(codec.get_frame_count() as i32)
@@ -136,10 +160,7 @@ pub fn invert(image: Vec<u8>) -> Result<Vec<u8>, Error> {
 }
 
 pub fn merge_horizontal(images: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
-    let images = images
-        .into_iter()
-        .map(|image| input_image(image))
-        .collect::<Result<Vec<_>, _>>()?;
+    let images = input_images(images)?;
 
     let func = |images: Vec<Image>| {
         let img_h = images.iter().map(|img| img.height()).min().unwrap();
@@ -162,10 +183,7 @@ pub fn merge_horizontal(images: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
 }
 
 pub fn merge_vertical(images: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
-    let images = images
-        .into_iter()
-        .map(|image| input_image(image))
-        .collect::<Result<Vec<_>, _>>()?;
+    let images = input_images(images)?;
 
     let func = |images: Vec<Image>| {
         let img_w = images.iter().map(|img| img.width()).min().unwrap();
@@ -202,10 +220,7 @@ pub fn gif_split(image: Vec<u8>) -> Result<Vec<Vec<u8>>, Error> {
 }
 
 pub fn gif_merge(images: Vec<Vec<u8>>, duration: Option<f32>) -> Result<Vec<u8>, Error> {
-    let codecs = images
-        .into_iter()
-        .map(|image| decode_image(image))
-        .collect::<Result<Vec<_>, _>>()?;
+    let codecs = decode_images(images)?;
     let mut frames = vec![];
     for mut codec in codecs {
         let count = codec.get_frame_count();
@@ -222,7 +237,7 @@ pub fn gif_merge(images: Vec<Vec<u8>>, duration: Option<f32>) -> Result<Vec<u8>,
     for frame in frames {
         encoder.add_frame(frame.resize_fit((min_w, min_h), Fit::Contain), duration)?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 pub fn gif_reverse(image: Vec<u8>) -> Result<Vec<u8>, Error> {
@@ -233,13 +248,13 @@ pub fn gif_reverse(image: Vec<u8>) -> Result<Vec<u8>, Error> {
         let frame = codec.get_frame(i)?;
         frames.push(frame);
     }
-    let duration = codec.get_average_duration()?;
+    let durations = codec.get_durations()?;
 
     let mut encoder = GifEncoder::new();
-    for i in 0..count {
-        encoder.add_frame(frames[count - i - 1].clone(), duration)?;
+    for i in (0..count).rev() {
+        encoder.add_frame(frames[i].clone(), durations[i])?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 pub fn gif_change_duration(image: Vec<u8>, duration: f32) -> Result<Vec<u8>, Error> {
@@ -250,5 +265,5 @@ pub fn gif_change_duration(image: Vec<u8>, duration: f32) -> Result<Vec<u8>, Err
         let frame = codec.get_frame(i)?;
         encoder.add_frame(frame, duration)?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
 
 use pinyin::{Pinyin, to_pinyin_vec};
 use serde::{Deserialize, Serialize};
@@ -275,10 +278,10 @@ impl Default for RenderMemeListParams {
 pub fn render_meme_list(params: RenderMemeListParams) -> Result<Vec<u8>, Error> {
     let mut memes = get_memes();
 
-    let keywords = |meme: &Box<dyn Meme>| meme.info().keywords.join("/");
+    let keywords = |meme: &Arc<dyn Meme>| meme.info().keywords.join("/");
     let keywords_pinyin =
-        |meme: &Box<dyn Meme>| to_pinyin_vec(keywords(meme).as_str(), Pinyin::plain).join(" ");
-    let shortcuts = |meme: &Box<dyn Meme>| {
+        |meme: &Arc<dyn Meme>| to_pinyin_vec(keywords(meme).as_str(), Pinyin::plain).join(" ");
+    let shortcuts = |meme: &Arc<dyn Meme>| {
         meme.info()
             .shortcuts
             .iter()
@@ -286,7 +289,7 @@ pub fn render_meme_list(params: RenderMemeListParams) -> Result<Vec<u8>, Error>
             .collect::<Vec<_>>()
             .join("/")
     };
-    let tags = |meme: &Box<dyn Meme>| meme.info().tags.into_iter().collect::<Vec<_>>().join("/");
+    let tags = |meme: &Arc<dyn Meme>| meme.info().tags.into_iter().collect::<Vec<_>>().join("/");
 
     match params.sort_by {
         MemeSortBy::Key => memes.sort_by(|a, b| a.key().cmp(&b.key())),
@@ -322,7 +325,7 @@ pub fn render_meme_list(params: RenderMemeListParams) -> Result<Vec<u8>, Error>
     let text_template = params.text_template;
     let add_category_icon = params.add_category_icon;
 
-    let meme_text = |index: usize, meme: &Box<dyn Meme>| -> String {
+    let meme_text = |index: usize, meme: &Arc<dyn Meme>| -> String {
         let mut vars = HashMap::new();
         vars.insert("index", (index + 1).to_string());
         vars.insert("key", meme.key());
@@ -0,0 +1,357 @@
+//! 在沙箱中运行的 `.wasm` 表情插件，接口见 [`meme_generator_core::wasm`]
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use skia_safe::{ISize, surfaces};
+use wasmi::{
+    AsContext, AsContextMut, Caller, Engine, Extern, Instance, Linker, Memory, Module, Store,
+    StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use meme_generator_core::{
+    error::Error,
+    meme::{Image, Meme, MemeInfo, OptionValue},
+    wasm::{
+        MEME_WASM_ABI_VERSION, TextRenderRequest, WASM_HOST_MODULE, WasmGenerateRequest, WasmImage,
+        WasmPreviewRequest, decode_result, encode_result, pack, unpack,
+    },
+};
+use meme_generator_utils::{
+    config::IMAGES_DIR,
+    encoder::encode_png,
+    text::{Text2Image, TextParams},
+    tools::{color_from_str, new_paint, new_stroke_paint},
+};
+
+use crate::config::config;
+
+#[derive(Debug)]
+pub enum WasmPluginError {
+    IoError(std::io::Error),
+    RuntimeError(wasmi::Error),
+    AbiVersionMismatch(i32),
+    InvalidMemeInfo(String),
+}
+
+impl fmt::Display for WasmPluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmPluginError::IoError(err) => write!(f, "Failed to read module: {err}"),
+            WasmPluginError::RuntimeError(err) => write!(f, "WebAssembly error: {err}"),
+            WasmPluginError::AbiVersionMismatch(version) => write!(
+                f,
+                "Plugin ABI version {version} is not supported, expected {MEME_WASM_ABI_VERSION}",
+            ),
+            WasmPluginError::InvalidMemeInfo(err) => write!(f, "Invalid meme info: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WasmPluginError {}
+
+impl From<wasmi::Error> for WasmPluginError {
+    fn from(err: wasmi::Error) -> Self {
+        WasmPluginError::RuntimeError(err)
+    }
+}
+
+struct HostState {
+    limits: StoreLimits,
+}
+
+/// 插件导出的内存和分配函数
+struct GuestMemory {
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+}
+
+impl GuestMemory {
+    fn new(
+        ctx: impl AsContext,
+        get_export: impl Fn(&str) -> Option<Extern>,
+    ) -> Result<Self, wasmi::Error> {
+        let memory = get_export("memory")
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| wasmi::Error::new("missing export `memory`"))?;
+        let alloc = get_export("meme_alloc")
+            .and_then(Extern::into_func)
+            .ok_or_else(|| wasmi::Error::new("missing export `meme_alloc`"))?
+            .typed::<i32, i32>(&ctx)?;
+        Ok(Self { memory, alloc })
+    }
+
+    fn read(&self, ctx: impl AsContext, ptr: u32, len: u32) -> Result<Vec<u8>, wasmi::Error> {
+        // 先检查范围再复制，避免插件传入过大的长度使宿主预先分配内存
+        let (start, len) = (ptr as usize, len as usize);
+        self.memory
+            .data(ctx.as_context())
+            .get(start..start.saturating_add(len))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| wasmi::Error::new("out of bounds memory access"))
+    }
+
+    fn read_packed(&self, ctx: impl AsContext, packed: i64) -> Result<Vec<u8>, wasmi::Error> {
+        let (ptr, len) = unpack(packed);
+        self.read(ctx, ptr, len)
+    }
+
+    fn write(&self, mut ctx: impl AsContextMut, data: &[u8]) -> Result<u32, wasmi::Error> {
+        let len = i32::try_from(data.len())
+            .map_err(|_| wasmi::Error::new("data too large for plugin memory"))?;
+        let ptr = self.alloc.call(&mut ctx, len)? as u32;
+        self.memory.write(&mut ctx, ptr as usize, data)?;
+        Ok(ptr)
+    }
+
+    fn write_packed(&self, ctx: impl AsContextMut, data: &[u8]) -> Result<i64, wasmi::Error> {
+        let ptr = self.write(ctx, data)?;
+        Ok(pack(ptr, data.len() as u32))
+    }
+}
+
+fn load_asset(path: &str) -> Result<Vec<u8>, Error> {
+    // 只允许访问图片资源目录下的文件
+    let is_relative = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let asset_path = IMAGES_DIR.join(path);
+    if !is_relative || !asset_path.is_file() {
+        return Err(Error::ImageAssetMissing(path.to_string()));
+    }
+    fs::read(asset_path).map_err(|_| Error::ImageAssetMissing(path.to_string()))
+}
+
+/// 插件请求渲染的文字的字节数上限
+const MAX_RENDER_TEXT_LENGTH: usize = 10_000;
+const MAX_RENDER_FONT_SIZE: f32 = 512.0;
+const MAX_RENDER_STROKE_WIDTH: f32 = 64.0;
+/// 渲染结果的最大宽度和高度
+const MAX_RENDER_SIZE: f32 = 4096.0;
+
+/// 将插件传入的数值限制在 `min` 和 `max` 之间，NaN 视为 `min`
+fn clamp_param(value: f32, min: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        min
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+fn render_text(request: &[u8]) -> Result<Vec<u8>, Error> {
+    let request: TextRenderRequest =
+        serde_json::from_slice(request).map_err(|err| Error::DeserializeError(err.to_string()))?;
+    if request.text.len() > MAX_RENDER_TEXT_LENGTH {
+        return Err(Error::text_over_length(request.text));
+    }
+    let font_size = clamp_param(request.font_size, 1.0, MAX_RENDER_FONT_SIZE);
+    let stroke_width = clamp_param(request.stroke_width, 0.0, MAX_RENDER_STROKE_WIDTH);
+    let text_params = TextParams {
+        font_families: request.font_families,
+        paint: new_paint(color_from_str(&request.color)),
+        stroke_paint: request
+            .stroke_color
+            .map(|color| new_stroke_paint(color_from_str(&color), stroke_width)),
+        ..Default::default()
+    };
+    let mut text2image = if request.bbcode {
+        Text2Image::from_bbcode_text(request.text.as_str(), font_size, text_params)?
+    } else {
+        Text2Image::from_text(request.text.as_str(), font_size, text_params)
+    };
+    if let Some(max_width) = request.max_width {
+        text2image.layout(clamp_param(max_width, 1.0, MAX_RENDER_SIZE));
+    }
+    let padding = stroke_width.ceil();
+    let width = text2image.longest_line() + padding * 2.0;
+    let height = text2image.height() + padding * 2.0;
+    if width > MAX_RENDER_SIZE || height > MAX_RENDER_SIZE {
+        return Err(Error::text_over_length(request.text));
+    }
+    let mut surface = surfaces::raster_n32_premul(ISize::new(
+        width.ceil().max(1.0) as i32,
+        height.ceil().max(1.0) as i32,
+    ))
+    .ok_or_else(|| Error::ImageEncodeError("Failed to create surface".to_string()))?;
+    text2image.draw_on_canvas(surface.canvas(), (padding, padding));
+    encode_png(surface.image_snapshot())
+}
+
+fn new_linker(engine: &Engine) -> Result<Linker<HostState>, wasmi::Error> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        WASM_HOST_MODULE,
+        "load_asset",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let guest = GuestMemory::new(&caller, |name| caller.get_export(name))?;
+            let path = guest.read(&caller, ptr as u32, len as u32)?;
+            let result = load_asset(&String::from_utf8_lossy(&path));
+            guest.write_packed(&mut caller, &encode_result(result))
+        },
+    )?;
+    linker.func_wrap(
+        WASM_HOST_MODULE,
+        "render_text",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let guest = GuestMemory::new(&caller, |name| caller.get_export(name))?;
+            let request = guest.read(&caller, ptr as u32, len as u32)?;
+            let result = render_text(&request);
+            guest.write_packed(&mut caller, &encode_result(result))
+        },
+    )?;
+    Ok(linker)
+}
+
+struct WasmPlugin {
+    engine: Engine,
+    module: Module,
+    linker: Linker<HostState>,
+}
+
+impl WasmPlugin {
+    fn new(wasm: &[u8]) -> Result<Self, wasmi::Error> {
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)?;
+        let linker = new_linker(&engine)?;
+        Ok(Self {
+            engine,
+            module,
+            linker,
+        })
+    }
+
+    /// 创建受内存和燃料限制的新实例，每次调用都使用新实例，调用失败不会影响后续调用
+    fn instantiate(&self) -> Result<(Store<HostState>, Instance, GuestMemory), wasmi::Error> {
+        let config = config();
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(config.meme.wasm_memory_limit * 1024 * 1024)
+            .build();
+        let mut store = Store::new(&self.engine, HostState { limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(config.meme.wasm_fuel_limit)?;
+        let instance = self
+            .linker
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let guest = GuestMemory::new(&store, |name| instance.get_export(&store, name))?;
+        Ok((store, instance, guest))
+    }
+
+    fn abi_version(&self) -> Result<i32, wasmi::Error> {
+        let (mut store, instance, _) = self.instantiate()?;
+        instance
+            .get_typed_func::<(), i32>(&store, "meme_abi_version")?
+            .call(&mut store, ())
+    }
+
+    fn infos(&self) -> Result<Vec<u8>, wasmi::Error> {
+        let (mut store, instance, guest) = self.instantiate()?;
+        let packed = instance
+            .get_typed_func::<(), i64>(&store, "meme_infos")?
+            .call(&mut store, ())?;
+        guest.read_packed(&store, packed)
+    }
+
+    /// 调用 `meme_generate` 或 `meme_generate_preview`，`request` 负责写入图片并生成请求
+    fn call(
+        &self,
+        name: &str,
+        request: impl FnOnce(&mut Store<HostState>, &GuestMemory) -> Result<Vec<u8>, wasmi::Error>,
+    ) -> Result<Vec<u8>, wasmi::Error> {
+        let (mut store, instance, guest) = self.instantiate()?;
+        let func = instance.get_typed_func::<(i32, i32), i64>(&store, name)?;
+        let request = request(&mut store, &guest)?;
+        let ptr = guest.write(&mut store, &request)?;
+        let packed = func.call(&mut store, (ptr as i32, request.len() as i32))?;
+        guest.read_packed(&store, packed)
+    }
+}
+
+fn trap_error(err: wasmi::Error) -> Error {
+    Error::MemeFeedback(format!("Plugin trapped: {err}"))
+}
+
+pub struct WasmMeme {
+    plugin: Arc<WasmPlugin>,
+    index: u32,
+    info: MemeInfo,
+}
+
+impl Meme for WasmMeme {
+    fn key(&self) -> String {
+        self.info.key.clone()
+    }
+
+    fn info(&self) -> MemeInfo {
+        self.info.clone()
+    }
+
+    fn generate(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        let result = self.plugin.call("meme_generate", |store, guest| {
+            let images = images
+                .iter()
+                .map(|image| {
+                    Ok(WasmImage {
+                        name: image.name.clone(),
+                        ptr: guest.write(&mut *store, &image.data)?,
+                        len: image.data.len() as u32,
+                    })
+                })
+                .collect::<Result<Vec<_>, wasmi::Error>>()?;
+            let request = WasmGenerateRequest {
+                index: self.index,
+                images,
+                texts,
+                options,
+            };
+            Ok(serde_json::to_vec(&request).unwrap_or_default())
+        });
+        decode_result(result.map_err(trap_error)?)
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        let result = self.plugin.call("meme_generate_preview", |_, _| {
+            let request = WasmPreviewRequest {
+                index: self.index,
+                options,
+            };
+            Ok(serde_json::to_vec(&request).unwrap_or_default())
+        });
+        decode_result(result.map_err(trap_error)?)
+    }
+}
+
+/// 加载 `.wasm` 插件，表情信息在加载时读取并缓存
+pub fn load_wasm_plugin(path: impl AsRef<Path>) -> Result<Vec<WasmMeme>, WasmPluginError> {
+    let wasm = fs::read(path).map_err(WasmPluginError::IoError)?;
+    let plugin = Arc::new(WasmPlugin::new(&wasm)?);
+
+    let abi_version = plugin.abi_version()?;
+    if abi_version != MEME_WASM_ABI_VERSION as i32 {
+        return Err(WasmPluginError::AbiVersionMismatch(abi_version));
+    }
+
+    let infos = plugin.infos()?;
+    let infos: Vec<MemeInfo> = serde_json::from_slice(&infos)
+        .map_err(|err| WasmPluginError::InvalidMemeInfo(err.to_string()))?;
+    Ok(infos
+        .into_iter()
+        .enumerate()
+        .map(|(index, info)| WasmMeme {
+            plugin: Arc::clone(&plugin),
+            index: index as u32,
+            info,
+        })
+        .collect())
+}
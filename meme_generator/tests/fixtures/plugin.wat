@@ -0,0 +1,72 @@
+;; 用于测试宿主的最小 WASM 插件，修改后使用 `wat2wasm plugin.wat -o plugin.wasm` 重新生成
+;;
+;; 插件不解析请求，只读取请求开头 `{"index":N` 中的数字决定行为：
+;; 0. 渲染正常的文字
+;; 1. 以超出限制的字号、描边宽度和换行宽度渲染文字
+;; 2. 以越界的地址和长度读取资源路径
+(module
+  (import "meme_host" "load_asset" (func $load_asset (param i32 i32) (result i64)))
+  (import "meme_host" "render_text" (func $render_text (param i32 i32) (result i64)))
+
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 4096))
+
+  (data (i32.const 0) "[{\"key\":\"wasm_text\",\"params\":{\"min_images\":0,\"max_images\":0,\"min_texts\":0,\"max_texts\":0,\"default_texts\":[],\"options\":[]},\"keywords\":[],\"shortcuts\":[],\"tags\":[],\"date_created\":\"2024-01-01T00:00:00+08:00\",\"date_modified\":\"2024-01-01T00:00:00+08:00\"},{\"key\":\"wasm_huge_text\",\"params\":{\"min_images\":0,\"max_images\":0,\"min_texts\":0,\"max_texts\":0,\"default_texts\":[],\"options\":[]},\"keywords\":[],\"shortcuts\":[],\"tags\":[],\"date_created\":\"2024-01-01T00:00:00+08:00\",\"date_modified\":\"2024-01-01T00:00:00+08:00\"},{\"key\":\"wasm_bad_pointer\",\"params\":{\"min_images\":0,\"max_images\":0,\"min_texts\":0,\"max_texts\":0,\"default_texts\":[],\"options\":[]},\"keywords\":[],\"shortcuts\":[],\"tags\":[],\"date_created\":\"2024-01-01T00:00:00+08:00\",\"date_modified\":\"2024-01-01T00:00:00+08:00\"}]")
+  (data (i32.const 2048) "{\"text\":\"wasm\",\"font_size\":32}")
+  (data (i32.const 2560) "{\"text\":\"wasm\",\"font_size\":1e30,\"stroke_color\":\"white\",\"stroke_width\":1e30,\"max_width\":1e30}")
+
+  (func $pack (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len))))
+
+  ;; 返回以 0 结尾的字符串的长度
+  (func $strlen (param $ptr i32) (result i32)
+    (local $end i32)
+    (local.set $end (local.get $ptr))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (i32.load8_u (local.get $end))))
+        (local.set $end (i32.add (local.get $end) (i32.const 1)))
+        (br $next)))
+    (i32.sub (local.get $end) (local.get $ptr)))
+
+  (func (export "meme_abi_version") (result i32)
+    (i32.const 1))
+
+  ;; 只分配不释放，每次调用都会创建新的实例
+  (func (export "meme_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (local.get $ptr) (local.get $len)))
+    (block $done
+      (loop $grow
+        (br_if $done
+          (i32.le_u (global.get $heap) (i32.shl (memory.size) (i32.const 16))))
+        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1))
+          (then (unreachable)))
+        (br $grow)))
+    (local.get $ptr))
+
+  (func (export "meme_infos") (result i64)
+    (call $pack (i32.const 0) (call $strlen (i32.const 0))))
+
+  (func $run (param $ptr i32) (result i64)
+    (local $index i32)
+    (local.set $index
+      (i32.sub (i32.load8_u offset=9 (local.get $ptr)) (i32.const 48)))
+    (if (i32.eq (local.get $index) (i32.const 1))
+      (then
+        (return
+          (call $render_text (i32.const 2560) (call $strlen (i32.const 2560))))))
+    (if (i32.eq (local.get $index) (i32.const 2))
+      (then
+        (return
+          (call $load_asset (i32.const -256) (i32.const -1)))))
+    (call $render_text (i32.const 2048) (call $strlen (i32.const 2048))))
+
+  (func (export "meme_generate") (param $ptr i32) (param $len i32) (result i64)
+    (call $run (local.get $ptr)))
+
+  (func (export "meme_generate_preview") (param $ptr i32) (param $len i32) (result i64)
+    (call $run (local.get $ptr))))
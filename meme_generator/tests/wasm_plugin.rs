@@ -0,0 +1,60 @@
+//! 使用 `tests/fixtures/plugin.wat` 编译的最小插件测试宿主对插件输入的检查
+
+use std::{collections::HashMap, path::Path};
+
+use meme_generator::{
+    error::Error,
+    meme::Meme,
+    wasm::{WasmMeme, load_wasm_plugin},
+};
+
+fn load_memes() -> HashMap<String, WasmMeme> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/plugin.wasm");
+    load_wasm_plugin(path)
+        .unwrap()
+        .into_iter()
+        .map(|meme| (meme.key(), meme))
+        .collect()
+}
+
+/// PNG 文件头中的宽和高
+fn png_size(data: &[u8]) -> (u32, u32) {
+    assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+    let width = u32::from_be_bytes(data[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(data[20..24].try_into().unwrap());
+    (width, height)
+}
+
+#[test]
+fn loads_memes_with_info() {
+    let memes = load_memes();
+    let mut keys = memes.keys().cloned().collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, ["wasm_bad_pointer", "wasm_huge_text", "wasm_text"]);
+}
+
+#[test]
+fn renders_text_through_host() {
+    let memes = load_memes();
+    let result = memes["wasm_text"].generate(Vec::new(), Vec::new(), HashMap::new());
+    let (width, height) = png_size(&result.unwrap());
+    assert!(width > 1 && height > 1);
+}
+
+#[test]
+fn clamps_oversized_text_request() {
+    let memes = load_memes();
+    let result = memes["wasm_huge_text"].generate(Vec::new(), Vec::new(), HashMap::new());
+    let (width, height) = png_size(&result.unwrap());
+    assert!(width <= 4096 && height <= 4096);
+}
+
+#[test]
+fn rejects_out_of_bounds_reads() {
+    let memes = load_memes();
+    let result = memes["wasm_bad_pointer"].generate(Vec::new(), Vec::new(), HashMap::new());
+    match result {
+        Err(Error::MemeFeedback(message)) => assert!(message.contains("out of bounds")),
+        _ => panic!("expected MemeFeedback, got {result:?}"),
+    }
+}
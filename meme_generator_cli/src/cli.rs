@@ -8,15 +8,17 @@ use std::{
 
 use clap::{
     Arg, ArgAction, ArgMatches, Command, arg,
-    builder::{PossibleValue, ValueParser},
+    builder::{PossibleValue, PossibleValuesParser, TypedValueParser, ValueParser},
     value_parser,
 };
 
 use meme_generator::{
     VERSION,
     error::Error,
+    fonts::{check_glyph_coverage, loaded_typefaces},
+    format::{Degradation, FrameMode, ImageFormat, OutputFormat},
     get_meme, get_meme_keys, get_memes,
-    meme::{Image, MemeOption, OptionValue},
+    meme::{GeneratedImage, Image, MemeOption, OptionValue, SEED_OPTION},
     resources::check_resources_sync,
     search_memes,
 };
@@ -25,6 +27,38 @@ use meme_generator_server::run_server_sync;
 
 use crate::tools::{handle_gif, handle_image};
 
+fn output_args() -> [Arg; 4] {
+    [
+        arg!(--format [FORMAT] "输出格式").value_parser(
+            PossibleValuesParser::new(["png", "jpeg", "webp", "gif", "apng"])
+                .map(|format| format.parse::<ImageFormat>().unwrap()),
+        ),
+        arg!(--quality [QUALITY] "jpeg 和 webp 的压缩质量")
+            .value_parser(value_parser!(u8).range(1..=100)),
+        arg!(--frames [FRAMES] "强制输出静图或动图").value_parser(
+            PossibleValuesParser::new(["auto", "static", "animated"])
+                .map(|frames| frames.parse::<FrameMode>().unwrap()),
+        ),
+        arg!(--"max-output-bytes" [MAX_OUTPUT_BYTES] "结果的最大字节数，超出时自动降低画质")
+            .value_parser(value_parser!(u32).range(1..=i32::MAX as i64)),
+    ]
+}
+
+fn output_options(sub_matches: &ArgMatches) -> HashMap<String, OptionValue> {
+    let output = OutputFormat {
+        format: sub_matches.get_one::<ImageFormat>("format").copied(),
+        quality: sub_matches.get_one::<u8>("quality").copied(),
+        frames: sub_matches
+            .get_one::<FrameMode>("frames")
+            .copied()
+            .unwrap_or_default(),
+        max_output_bytes: sub_matches.get_one::<u32>("max-output-bytes").copied(),
+    };
+    let mut options = HashMap::new();
+    output.apply_to(&mut options);
+    options
+}
+
 fn build_arg(option: MemeOption) -> Arg {
     match option {
         MemeOption::Boolean {
@@ -217,6 +251,8 @@ pub(crate) fn build_command() -> Command {
             )
             .arg(arg!(--names [NAMES] "图片名").num_args(1..))
             .arg(arg!(--texts [TEXTS] "文字").num_args(1..))
+            .arg(arg!(--seed [SEED] "随机数种子").value_parser(value_parser!(i32)))
+            .args(output_args())
             .arg_required_else_help(true);
         for option in options {
             let arg = build_arg(option);
@@ -264,6 +300,7 @@ pub(crate) fn build_command() -> Command {
                             .collect::<Vec<PossibleValue>>(),
                     ),
                 )
+                .args(output_args())
                 .arg_required_else_help(true),
         )
         .subcommand(
@@ -280,6 +317,11 @@ pub(crate) fn build_command() -> Command {
                     .value_parser(value_parser!(String)),
             ),
         )
+        .subcommand(
+            Command::new("fonts")
+                .about("查看已加载的字体")
+                .arg(arg!(--text [TEXT] "检查文字中的字符是否有可用的字体")),
+        )
         .subcommand(
             Command::new("tools")
                 .about("工具箱")
@@ -648,7 +690,7 @@ pub(crate) fn handle_search(sub_matches: &ArgMatches) {
 pub(crate) fn handle_preview(sub_matches: &ArgMatches) {
     let key = sub_matches.get_one::<String>("KEY").unwrap();
     let meme = get_meme(key).expect(format!("表情 `{key}` 不存在").as_str());
-    let result = meme.generate_preview(HashMap::new());
+    let result = meme.generate_preview_image(output_options(sub_matches));
     handle_result(result)
 }
 
@@ -686,7 +728,10 @@ pub(crate) fn handle_generate(sub_matches: &ArgMatches) {
         .flatten()
         .map(|text| text.to_string())
         .collect::<Vec<_>>();
-    let mut options = HashMap::new();
+    let mut options = output_options(sub_matches);
+    if let Some(seed) = sub_matches.get_one::<i32>("seed") {
+        options.insert(SEED_OPTION.to_string(), OptionValue::Integer(*seed));
+    }
     for option in meme.info().params.options {
         match option {
             MemeOption::Boolean { name, .. } => {
@@ -711,15 +756,21 @@ pub(crate) fn handle_generate(sub_matches: &ArgMatches) {
             }
         }
     }
-    let result = meme.generate(images, texts, options);
+    let result = meme.generate_image(images, texts, options);
     handle_result(result)
 }
 
-fn handle_result(result: Result<Vec<u8>, Error>) {
+fn handle_result(result: Result<GeneratedImage, Error>) {
     match result {
         Err(Error::ImageDecodeError(err)) => {
             eprintln!("图片解码失败：{err}");
         }
+        Err(Error::InvalidImage { index, name, error }) => {
+            eprintln!("第 {} 张图片（{name}）解码失败：{error}", index + 1);
+        }
+        Err(Error::ImageTooLarge { index, name, error }) => {
+            eprintln!("第 {} 张图片（{name}）过大：{error}", index + 1);
+        }
         Err(Error::ImageEncodeError(err)) => {
             eprintln!("图片编码失败：{err}");
         }
@@ -729,6 +780,13 @@ fn handle_result(result: Result<Vec<u8>, Error>) {
         Err(Error::DeserializeError(err)) => {
             eprintln!("反序列化失败：{err}");
         }
+        Err(Error::InvalidOptionValue {
+            name,
+            value,
+            expected,
+        }) => {
+            eprintln!("参数 `{name}` 的值 {value} 无效，应为 {expected}");
+        }
         Err(Error::ImageNumberMismatch(min, max, actual)) => {
             let range = {
                 if min == max {
@@ -749,19 +807,47 @@ fn handle_result(result: Result<Vec<u8>, Error>) {
             };
             eprintln!("文本数量不符，应为 {range}，实际传入 {actual}");
         }
-        Err(Error::TextOverLength(text)) => {
-            eprintln!("文字过长：{text}");
+        Err(Error::TextOverLength { text, index }) => match index {
+            Some(index) => eprintln!("第 {} 段文字过长：{text}", index + 1),
+            None => eprintln!("文字过长：{text}"),
+        },
+        Err(Error::ImageNameOverLength { name, index }) => {
+            eprintln!("第 {} 张图片的名字过长：{name}", index + 1);
+        }
+        Err(Error::TextGlyphMissing { index, chars, .. }) => {
+            eprintln!("第 {} 段文字中的字符没有可用的字体：{chars}", index + 1);
+        }
+        Err(Error::InvalidBBCode { index, error, .. }) => match index {
+            Some(index) => eprintln!("第 {} 段文字的 BBCode 有误：{error}", index + 1),
+            None => eprintln!("BBCode 有误：{error}"),
+        },
+        Err(Error::InvalidTextPath(err)) => {
+            eprintln!("文字路径无效：{err}");
         }
         Err(Error::MemeFeedback(feedback)) => {
             eprintln!("{feedback}");
         }
         Ok(result) => {
-            let kind = infer::get(&result).unwrap();
-            let extension = kind.extension();
+            let extension = result.format.extension();
             let filename_string = format!("result.{extension}");
             let filename = filename_string.as_str();
-            write(filename, result).expect("图片保存失败");
+            write(filename, &result.data).expect("图片保存失败");
             println!("表情制作成功！生成的表情文件为 `{filename}`");
+            if let Some(seed) = result.seed {
+                println!("随机数种子：{seed}，使用 `--seed {seed}` 可生成相同的结果");
+            }
+            for degradation in &result.degradations {
+                let step = match degradation {
+                    Degradation::Downscale { width, height } => {
+                        format!("分辨率降低为 {width}x{height}")
+                    }
+                    Degradation::DropFrames { frame_count } => {
+                        format!("帧数减少为 {frame_count}")
+                    }
+                    Degradation::ReducePalette { colors } => format!("颜色数减少为 {colors}"),
+                };
+                println!("为满足大小限制，{step}");
+            }
         }
     };
 }
@@ -771,6 +857,47 @@ pub(crate) fn handle_download(sub_matches: &ArgMatches) {
     check_resources_sync(resource_url.cloned());
 }
 
+pub(crate) fn handle_fonts(sub_matches: &ArgMatches) {
+    if let Some(text) = sub_matches.get_one::<String>("text") {
+        let coverage = check_glyph_coverage(text, &[]);
+        if coverage.is_complete() {
+            println!("所有字符都有可用的字体");
+        }
+        if !coverage.fallback.is_empty() {
+            let chars = coverage.fallback.iter().collect::<String>();
+            println!("使用回退字体的字符：{chars}");
+        }
+        if !coverage.missing.is_empty() {
+            let chars = coverage.missing.iter().collect::<String>();
+            println!("没有可用字体的字符：{chars}");
+        }
+        return;
+    }
+
+    let typefaces = loaded_typefaces();
+    if typefaces.is_empty() {
+        eprintln!("未加载任何字体");
+        return;
+    }
+    let list = typefaces
+        .into_iter()
+        .enumerate()
+        .map(|(i, typeface)| {
+            let source = if typeface.local { "本地" } else { "系统" };
+            format!(
+                "{}. {} [{source}] (字重 {}，{} 个字形，覆盖 {} 个字符)",
+                i + 1,
+                typeface.family_name,
+                *typeface.font_style.weight(),
+                typeface.glyph_count,
+                typeface.char_count(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    println!("已加载的字体：\n{list}");
+}
+
 pub(crate) fn handle_tools(sub_matches: &ArgMatches) {
     match sub_matches.subcommand() {
         Some(("image", sub_matches)) => {
@@ -6,8 +6,8 @@ use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 #[cfg(feature = "server")]
 use cli::handle_run;
 use cli::{
-    build_command, handle_download, handle_generate, handle_info, handle_list, handle_preview,
-    handle_search, handle_tools,
+    build_command, handle_download, handle_fonts, handle_generate, handle_info, handle_list,
+    handle_preview, handle_search, handle_tools,
 };
 
 fn main() {
@@ -37,6 +37,9 @@ fn main() {
         Some(("download", sub_matches)) => {
             handle_download(sub_matches);
         }
+        Some(("fonts", sub_matches)) => {
+            handle_fonts(sub_matches);
+        }
         Some(("tools", sub_matches)) => {
             handle_tools(sub_matches);
         }
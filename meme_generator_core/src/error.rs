@@ -1,24 +1,137 @@
 use std::{error, fmt};
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Error {
     ImageDecodeError(String),
     ImageEncodeError(String),
     ImageAssetMissing(String),
     DeserializeError(String),
+    /// 传入的第 `index` 张图片无法解码
+    InvalidImage {
+        index: usize,
+        name: String,
+        error: String,
+    },
+    /// 传入的第 `index` 张图片的尺寸、帧数或解码后的大小超出限制
+    ImageTooLarge {
+        index: usize,
+        name: String,
+        error: String,
+    },
+    /// 选项 `name` 的值不符合其声明
+    InvalidOptionValue {
+        name: String,
+        value: String,
+        expected: String,
+    },
     ImageNumberMismatch(u8, u8, u8),
     TextNumberMismatch(u8, u8, u8),
-    TextOverLength(String),
+    /// 文字过长，`index` 为该文字在传入文字列表中的位置
+    TextOverLength {
+        text: String,
+        index: Option<usize>,
+    },
+    /// 图片名过长，`index` 为对应图片在传入图片列表中的位置
+    ImageNameOverLength {
+        name: String,
+        index: usize,
+    },
+    /// 第 `index` 段文字中的字符 `chars` 在所有字体中都没有字形
+    TextGlyphMissing {
+        text: String,
+        index: usize,
+        chars: String,
+    },
+    /// 文字中的 BBCode 标签有误，`index` 为该文字在传入文字列表中的位置
+    InvalidBBCode {
+        text: String,
+        index: Option<usize>,
+        error: String,
+    },
+    /// 表情中用于排列文字的路径无效，如没有任何轮廓
+    InvalidTextPath(String),
     MemeFeedback(String),
 }
 
+impl Error {
+    pub fn text_over_length(text: impl Into<String>) -> Self {
+        Error::TextOverLength {
+            text: text.into(),
+            index: None,
+        }
+    }
+
+    /// 第 `index` 段文字过长，表情函数知道文字位置时应优先使用
+    pub fn text_over_length_at(index: usize, text: impl Into<String>) -> Self {
+        Error::TextOverLength {
+            text: text.into(),
+            index: Some(index),
+        }
+    }
+
+    /// 第 `index` 张图片的图片名过长
+    pub fn image_name_over_length(index: usize, name: impl Into<String>) -> Self {
+        Error::ImageNameOverLength {
+            name: name.into(),
+            index,
+        }
+    }
+
+    pub fn invalid_bbcode(text: impl Into<String>, error: impl Into<String>) -> Self {
+        Error::InvalidBBCode {
+            text: text.into(),
+            index: None,
+            error: error.into(),
+        }
+    }
+
+    /// 稳定的错误码，供服务端、Python 绑定等下游区分错误类型
+    ///
+    /// 580 ~ 583 为解析表情指令时的错误，见 `meme_generator::parser::ParseError`
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::ImageDecodeError(_) => 510,
+            Error::InvalidImage { .. } => 511,
+            Error::ImageTooLarge { .. } => 512,
+            Error::ImageEncodeError(_) => 520,
+            Error::ImageAssetMissing(_) => 530,
+            Error::DeserializeError(_) => 540,
+            Error::InvalidOptionValue { .. } => 541,
+            Error::ImageNumberMismatch(..) => 550,
+            Error::TextNumberMismatch(..) => 551,
+            Error::TextOverLength { .. } => 560,
+            Error::ImageNameOverLength { .. } => 561,
+            Error::TextGlyphMissing { .. } => 562,
+            Error::InvalidBBCode { .. } => 563,
+            Error::InvalidTextPath(_) => 564,
+            Error::MemeFeedback(_) => 570,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ImageDecodeError(err) => write!(f, "Failed to decode image: {err}"),
+            Error::InvalidImage { index, name, error } => {
+                write!(f, "Failed to decode image {index} ({name}): {error}")
+            }
+            Error::ImageTooLarge { index, name, error } => {
+                write!(f, "Image {index} ({name}) is too large: {error}")
+            }
             Error::ImageEncodeError(err) => write!(f, "Failed to encode image: {err}"),
             Error::ImageAssetMissing(path) => write!(f, "Image asset missing: {path}"),
             Error::DeserializeError(err) => write!(f, "Failed to deserialize: {err}"),
+            Error::InvalidOptionValue {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value for option `{name}`: {value}, expected {expected}",
+            ),
             Error::ImageNumberMismatch(min, max, actual) => write!(
                 f,
                 "Image number mismatch: expected between {min} and {max}, got {actual}",
@@ -27,7 +140,24 @@ impl fmt::Display for Error {
                 f,
                 "Text number mismatch: expected between {min} and {max}, got {actual}",
             ),
-            Error::TextOverLength(text) => write!(f, "Text is too long: {text}"),
+            Error::TextOverLength { text, index } => match index {
+                Some(index) => write!(f, "Text {index} is too long: {text}"),
+                None => write!(f, "Text is too long: {text}"),
+            },
+            Error::ImageNameOverLength { name, index } => {
+                write!(f, "Name of image {index} is too long: {name}")
+            }
+            Error::TextGlyphMissing { index, chars, .. } => {
+                write!(
+                    f,
+                    "Text {index} contains characters without glyphs: {chars}"
+                )
+            }
+            Error::InvalidBBCode { index, error, .. } => match index {
+                Some(index) => write!(f, "Invalid BBCode in text {index}: {error}"),
+                None => write!(f, "Invalid BBCode: {error}"),
+            },
+            Error::InvalidTextPath(err) => write!(f, "Invalid text path: {err}"),
             Error::MemeFeedback(feedback) => write!(f, "{feedback}"),
         }
     }
@@ -0,0 +1,483 @@
+//! 通过文件头识别生成结果的格式、尺寸和帧信息，不解码图片数据
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    meme::{FORMAT_OPTION, FRAMES_OPTION, MAX_OUTPUT_BYTES_OPTION, OptionValue, QUALITY_OPTION},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Apng,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Apng => "image/apng",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png | ImageFormat::Apng => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Webp => "webp",
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Apng => "apng",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Webp => "webp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "apng" => Ok(ImageFormat::Apng),
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+            "gif" => Ok(ImageFormat::Gif),
+            "webp" => Ok(ImageFormat::Webp),
+            _ => Err(format!("Unknown image format `{s}`")),
+        }
+    }
+}
+
+/// 输出静图还是动图
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameMode {
+    /// 由表情决定
+    #[default]
+    Auto,
+
+    /// 动图只输出第一帧
+    Static,
+
+    /// 静图也输出为只有一帧的动图
+    Animated,
+}
+
+impl FromStr for FrameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(FrameMode::Auto),
+            "static" => Ok(FrameMode::Static),
+            "animated" => Ok(FrameMode::Animated),
+            _ => Err(format!("Unknown frame mode `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for FrameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FrameMode::Auto => "auto",
+            FrameMode::Static => "static",
+            FrameMode::Animated => "animated",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// 调用方对输出格式的要求，未指定格式时静图为 png，动图为 gif
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputFormat {
+    pub format: Option<ImageFormat>,
+    /// jpeg 和 webp 的压缩质量，1 到 100
+    pub quality: Option<u8>,
+    pub frames: FrameMode,
+    /// 结果的最大字节数，未指定时使用配置中的限制
+    pub max_output_bytes: Option<u32>,
+}
+
+impl OutputFormat {
+    /// 输出静图时使用的格式
+    pub fn static_format(&self) -> ImageFormat {
+        match self.format {
+            None | Some(ImageFormat::Apng) => ImageFormat::Png,
+            Some(format) => format,
+        }
+    }
+
+    /// 输出动图时使用的格式，jpeg 不支持动图，此时输出第一帧
+    pub fn animated_format(&self) -> Option<ImageFormat> {
+        match self.format {
+            None | Some(ImageFormat::Gif) => Some(ImageFormat::Gif),
+            Some(ImageFormat::Png | ImageFormat::Apng) => Some(ImageFormat::Apng),
+            Some(ImageFormat::Webp) => Some(ImageFormat::Webp),
+            Some(ImageFormat::Jpeg) => None,
+        }
+    }
+
+    /// 从保留选项中读取输出格式
+    pub fn from_options(options: &HashMap<String, OptionValue>) -> Result<Self, Error> {
+        let invalid = |name: &str, value: &OptionValue, expected: &str| Error::InvalidOptionValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        };
+        let mut output = OutputFormat::default();
+        if let Some(value) = options.get(FORMAT_OPTION) {
+            output.format = match value {
+                OptionValue::String(format) => format.parse().ok(),
+                _ => None,
+            };
+            if output.format.is_none() {
+                let expected = "one of [png, jpeg, webp, gif, apng]";
+                return Err(invalid(FORMAT_OPTION, value, expected));
+            }
+        }
+        if let Some(value) = options.get(QUALITY_OPTION) {
+            output.quality = match value {
+                OptionValue::Integer(quality @ 1..=100) => Some(*quality as u8),
+                _ => {
+                    return Err(invalid(
+                        QUALITY_OPTION,
+                        value,
+                        "an integer between 1 and 100",
+                    ));
+                }
+            };
+        }
+        if let Some(value) = options.get(FRAMES_OPTION) {
+            output.frames = match value {
+                OptionValue::String(frames) => frames.parse().map_err(|_| {
+                    invalid(FRAMES_OPTION, value, "one of [auto, static, animated]")
+                })?,
+                _ => {
+                    return Err(invalid(
+                        FRAMES_OPTION,
+                        value,
+                        "one of [auto, static, animated]",
+                    ));
+                }
+            };
+        }
+        if let Some(value) = options.get(MAX_OUTPUT_BYTES_OPTION) {
+            output.max_output_bytes = match value {
+                OptionValue::Integer(max_bytes @ 1..) => Some(*max_bytes as u32),
+                _ => {
+                    return Err(invalid(
+                        MAX_OUTPUT_BYTES_OPTION,
+                        value,
+                        "a positive integer",
+                    ));
+                }
+            };
+        }
+        if output.frames == FrameMode::Animated && output.animated_format().is_none() {
+            let value = &OptionValue::String(output.frames.to_string());
+            return Err(invalid(
+                FRAMES_OPTION,
+                value,
+                "`static` or `auto` for jpeg output",
+            ));
+        }
+        Ok(output)
+    }
+
+    /// 写入保留选项，与 [`OutputFormat::from_options`] 相对应
+    pub fn apply_to(&self, options: &mut HashMap<String, OptionValue>) {
+        if let Some(format) = self.format {
+            options.insert(
+                FORMAT_OPTION.to_string(),
+                OptionValue::String(format.to_string()),
+            );
+        }
+        if let Some(quality) = self.quality {
+            options.insert(
+                QUALITY_OPTION.to_string(),
+                OptionValue::Integer(quality as i32),
+            );
+        }
+        if self.frames != FrameMode::Auto {
+            options.insert(
+                FRAMES_OPTION.to_string(),
+                OptionValue::String(self.frames.to_string()),
+            );
+        }
+        if let Some(max_bytes) = self.max_output_bytes {
+            options.insert(
+                MAX_OUTPUT_BYTES_OPTION.to_string(),
+                OptionValue::Integer(max_bytes.min(i32::MAX as u32) as i32),
+            );
+        }
+    }
+}
+
+/// 结果超出大小限制时所做的降级处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Degradation {
+    /// 缩小到该尺寸
+    Downscale { width: u32, height: u32 },
+    /// 减少到该帧数，去掉的帧的时长合并到前一帧
+    DropFrames { frame_count: u32 },
+    /// 将 gif 的调色板减少到该颜色数
+    ReducePalette { colors: u16 },
+}
+
+impl fmt::Display for Degradation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Degradation::Downscale { width, height } => write!(f, "downscale={width}x{height}"),
+            Degradation::DropFrames { frame_count } => write!(f, "drop_frames={frame_count}"),
+            Degradation::ReducePalette { colors } => write!(f, "reduce_palette={colors}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageProbe {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    /// 每一帧的时长，单位为秒，静图只有一帧且时长为 0
+    pub durations: Vec<f32>,
+}
+
+fn u16_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as u32)
+}
+
+fn u16_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as u32)
+}
+
+fn u24_le(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    Some(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16)
+}
+
+fn u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const GIF_TRAILER: u8 = 0x3b;
+
+/// 遍历 PNG 的数据块，返回块类型和块数据
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = PNG_SIGNATURE.len();
+    std::iter::from_fn(move || {
+        let len = u32_be(data, offset)? as usize;
+        let chunk_type = data.get(offset + 4..offset + 8)?;
+        let chunk_data = data.get(offset + 8..offset + 8 + len)?;
+        offset += len + 12;
+        Some((chunk_type, chunk_data))
+    })
+}
+
+fn probe_png(data: &[u8]) -> Option<ImageProbe> {
+    let (width, height) = (u32_be(data, 16)?, u32_be(data, 20)?);
+    let mut animated = false;
+    let mut durations = Vec::new();
+    for (chunk_type, chunk_data) in png_chunks(data) {
+        match chunk_type {
+            b"acTL" => animated = true,
+            b"fcTL" => {
+                let numerator = u16_be(chunk_data, 20)?;
+                let denominator = match u16_be(chunk_data, 22)? {
+                    0 => 100,
+                    denominator => denominator,
+                };
+                durations.push(numerator as f32 / denominator as f32);
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    if !animated {
+        durations = vec![0.0];
+    }
+    Some(ImageProbe {
+        format: if animated {
+            ImageFormat::Apng
+        } else {
+            ImageFormat::Png
+        },
+        width,
+        height,
+        durations,
+    })
+}
+
+/// 跳过 GIF 的子数据块，返回之后的位置
+fn skip_gif_sub_blocks(data: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(offset)? as usize;
+        offset += 1 + len;
+        if len == 0 {
+            return Some(offset);
+        }
+    }
+}
+
+fn probe_gif(data: &[u8]) -> Option<ImageProbe> {
+    let (width, height) = (u16_le(data, 6)?, u16_le(data, 8)?);
+    let flags = *data.get(10)?;
+    let mut offset = 13;
+    if flags & 0x80 != 0 {
+        offset += 3 << ((flags & 0x07) + 1);
+    }
+    let mut delay = 0.0;
+    let mut durations = Vec::new();
+    loop {
+        match *data.get(offset)? {
+            0x21 => {
+                if *data.get(offset + 1)? == 0xf9 {
+                    delay = u16_le(data, offset + 4)? as f32 / 100.0;
+                }
+                offset = skip_gif_sub_blocks(data, offset + 2)?;
+            }
+            0x2c => {
+                let flags = *data.get(offset + 9)?;
+                offset += 10;
+                if flags & 0x80 != 0 {
+                    offset += 3 << ((flags & 0x07) + 1);
+                }
+                offset = skip_gif_sub_blocks(data, offset + 1)?;
+                durations.push(delay);
+                delay = 0.0;
+            }
+            GIF_TRAILER => break,
+            _ => return None,
+        }
+    }
+    if durations.len() == 1 {
+        durations = vec![0.0];
+    }
+    Some(ImageProbe {
+        format: ImageFormat::Gif,
+        width,
+        height,
+        durations,
+    })
+}
+
+/// 遍历 JPEG 图像数据之前的段，返回标记和段数据
+fn jpeg_segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut offset = 2;
+    std::iter::from_fn(move || {
+        if *data.get(offset)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(offset + 1)?;
+        // 之后是压缩的图像数据
+        if marker == 0xda {
+            return None;
+        }
+        let len = u16_be(data, offset + 2)? as usize;
+        let segment = data.get(offset + 4..offset + 2 + len)?;
+        offset += 2 + len;
+        Some((marker, segment))
+    })
+}
+
+fn probe_jpeg(data: &[u8]) -> Option<ImageProbe> {
+    let (_, segment) = jpeg_segments(data).find(|(marker, _)| {
+        (0xc0..=0xcf).contains(marker) && ![0xc4, 0xc8, 0xcc].contains(marker)
+    })?;
+    Some(ImageProbe {
+        format: ImageFormat::Jpeg,
+        width: u16_be(segment, 3)?,
+        height: u16_be(segment, 1)?,
+        durations: vec![0.0],
+    })
+}
+
+/// 遍历 WebP 的数据块，返回块类型和块数据
+fn webp_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 12;
+    std::iter::from_fn(move || {
+        let chunk_type = data.get(offset..offset + 4)?;
+        let len = u32_le(data, offset + 4)? as usize;
+        let chunk_data = data.get(offset + 8..offset + 8 + len)?;
+        offset += 8 + len + len % 2;
+        Some((chunk_type, chunk_data))
+    })
+}
+
+fn probe_webp(data: &[u8]) -> Option<ImageProbe> {
+    let mut size = None;
+    let mut durations = Vec::new();
+    for (chunk_type, chunk_data) in webp_chunks(data) {
+        match chunk_type {
+            b"VP8X" => size = Some((u24_le(chunk_data, 4)? + 1, u24_le(chunk_data, 7)? + 1)),
+            b"VP8 " if size.is_none() => {
+                size = Some((
+                    u16_le(chunk_data, 6)? & 0x3fff,
+                    u16_le(chunk_data, 8)? & 0x3fff,
+                ))
+            }
+            b"VP8L" if size.is_none() => {
+                let bits = u32_le(chunk_data, 1)?;
+                size = Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1));
+            }
+            b"ANMF" => durations.push(u24_le(chunk_data, 12)? as f32 / 1000.0),
+            _ => {}
+        }
+    }
+    let (width, height) = size?;
+    if durations.is_empty() {
+        durations = vec![0.0];
+    }
+    Some(ImageProbe {
+        format: ImageFormat::Webp,
+        width,
+        height,
+        durations,
+    })
+}
+
+/// 识别图片格式并读取尺寸和每帧时长，无法识别时返回 `None`
+pub fn probe(data: &[u8]) -> Option<ImageProbe> {
+    if data.starts_with(PNG_SIGNATURE) {
+        probe_png(data)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        probe_gif(data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        probe_jpeg(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        probe_webp(data)
+    } else {
+        None
+    }
+}
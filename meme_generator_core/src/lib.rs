@@ -1,4 +1,7 @@
 pub mod config;
 pub mod error;
+pub mod format;
 pub mod meme;
+pub mod plugin;
 pub mod registry;
+pub mod wasm;
@@ -1,9 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    format::{Degradation, ImageFormat, OutputFormat, probe},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParserFlags {
@@ -31,6 +37,7 @@ pub enum MemeOption {
         name: String,
         default: Option<bool>,
         description: Option<String>,
+        #[serde(default)]
         parser_flags: ParserFlags,
     },
     String {
@@ -38,6 +45,7 @@ pub enum MemeOption {
         default: Option<String>,
         choices: Option<Vec<String>>,
         description: Option<String>,
+        #[serde(default)]
         parser_flags: ParserFlags,
     },
     Integer {
@@ -46,6 +54,7 @@ pub enum MemeOption {
         minimum: Option<i32>,
         maximum: Option<i32>,
         description: Option<String>,
+        #[serde(default)]
         parser_flags: ParserFlags,
     },
     Float {
@@ -54,10 +63,113 @@ pub enum MemeOption {
         minimum: Option<f32>,
         maximum: Option<f32>,
         description: Option<String>,
+        #[serde(default)]
         parser_flags: ParserFlags,
     },
 }
 
+impl MemeOption {
+    pub fn name(&self) -> &str {
+        match self {
+            MemeOption::Boolean { name, .. }
+            | MemeOption::String { name, .. }
+            | MemeOption::Integer { name, .. }
+            | MemeOption::Float { name, .. } => name,
+        }
+    }
+
+    /// 检查选项值是否符合该选项的类型、取值范围和可选项
+    pub fn validate(&self, value: &OptionValue) -> Result<(), Error> {
+        let invalid = |expected: String| {
+            Err(Error::InvalidOptionValue {
+                name: self.name().to_string(),
+                value: value.to_string(),
+                expected,
+            })
+        };
+        match (self, value) {
+            (MemeOption::Boolean { .. }, OptionValue::Boolean(_)) => Ok(()),
+            (MemeOption::Boolean { .. }, _) => invalid("a boolean".to_string()),
+            (MemeOption::String { choices, .. }, OptionValue::String(value)) => match choices {
+                Some(choices) if !choices.contains(value) => {
+                    invalid(format!("one of [{}]", choices.join(", ")))
+                }
+                _ => Ok(()),
+            },
+            (MemeOption::String { .. }, _) => invalid("a string".to_string()),
+            (
+                MemeOption::Integer {
+                    minimum, maximum, ..
+                },
+                OptionValue::Integer(value),
+            ) => {
+                if minimum.is_some_and(|minimum| *value < minimum)
+                    || maximum.is_some_and(|maximum| *value > maximum)
+                {
+                    invalid(describe_range(minimum, maximum))
+                } else {
+                    Ok(())
+                }
+            }
+            (MemeOption::Integer { .. }, _) => invalid("an integer".to_string()),
+            (
+                MemeOption::Float {
+                    minimum, maximum, ..
+                },
+                OptionValue::Float(_) | OptionValue::Integer(_),
+            ) => {
+                let value = match value {
+                    OptionValue::Float(value) => *value,
+                    OptionValue::Integer(value) => *value as f32,
+                    _ => unreachable!(),
+                };
+                if value.is_nan()
+                    || minimum.is_some_and(|minimum| value < minimum)
+                    || maximum.is_some_and(|maximum| value > maximum)
+                {
+                    invalid(describe_range(minimum, maximum))
+                } else {
+                    Ok(())
+                }
+            }
+            (MemeOption::Float { .. }, _) => invalid("a float".to_string()),
+        }
+    }
+}
+
+fn describe_range<T: fmt::Display>(minimum: &Option<T>, maximum: &Option<T>) -> String {
+    match (minimum, maximum) {
+        (Some(minimum), Some(maximum)) => format!("a number between {minimum} and {maximum}"),
+        (Some(minimum), None) => format!("a number greater than or equal to {minimum}"),
+        (None, Some(maximum)) => format!("a number less than or equal to {maximum}"),
+        (None, None) => "a number".to_string(),
+    }
+}
+
+/// 保留的选项名，所有表情都接受，用于固定随机数种子使结果可以复现
+pub const SEED_OPTION: &str = "seed";
+
+/// 保留的选项名，所有表情都接受，用于指定输出格式，见 [`OutputFormat`]
+pub const FORMAT_OPTION: &str = "format";
+
+/// 保留的选项名，jpeg 和 webp 的压缩质量
+pub const QUALITY_OPTION: &str = "quality";
+
+/// 保留的选项名，强制输出静图或动图
+pub const FRAMES_OPTION: &str = "frames";
+
+/// 保留的选项名，生成结果的最大字节数，超出时降低分辨率、帧数或颜色数重新编码
+pub const MAX_OUTPUT_BYTES_OPTION: &str = "max_output_bytes";
+
+/// 所有表情都接受的保留选项，不会传给表情本身
+pub const RESERVED_OPTIONS: [&str; 5] = [
+    SEED_OPTION,
+    FORMAT_OPTION,
+    QUALITY_OPTION,
+    FRAMES_OPTION,
+    MAX_OUTPUT_BYTES_OPTION,
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemeParams {
     pub min_images: u8,
@@ -81,7 +193,46 @@ impl Default for MemeParams {
     }
 }
 
+impl MemeParams {
+    /// 根据声明的选项检查传入的选项值，未声明的选项名也视为无效
+    pub fn validate_options(&self, options: &HashMap<String, OptionValue>) -> Result<(), Error> {
+        OutputFormat::from_options(options)?;
+        for (name, value) in options {
+            if name == SEED_OPTION {
+                if !matches!(value, OptionValue::Integer(_)) {
+                    return Err(Error::InvalidOptionValue {
+                        name: name.clone(),
+                        value: value.to_string(),
+                        expected: "an integer".to_string(),
+                    });
+                }
+                continue;
+            }
+            if RESERVED_OPTIONS.contains(&name.as_str()) {
+                continue;
+            }
+            match self.options.iter().find(|option| option.name() == name) {
+                Some(option) => option.validate(value)?,
+                None => {
+                    let names = self
+                        .options
+                        .iter()
+                        .map(|option| option.name())
+                        .collect::<Vec<_>>();
+                    return Err(Error::InvalidOptionValue {
+                        name: name.clone(),
+                        value: value.to_string(),
+                        expected: format!("a known option name, one of [{}]", names.join(", ")),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemeShortcut {
     pub pattern: String,
     pub humanized: Option<String>,
@@ -105,6 +256,9 @@ impl Default for MemeShortcut {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemeInfo {
     pub key: String,
+    /// 提供该表情的表情包，由加载表情的宿主填写，如 `builtin`、插件文件名等
+    #[serde(default)]
+    pub pack: String,
     pub params: MemeParams,
     pub keywords: Vec<String>,
     pub shortcuts: Vec<MemeShortcut>,
@@ -117,6 +271,7 @@ impl Default for MemeInfo {
     fn default() -> Self {
         MemeInfo {
             key: String::new(),
+            pack: String::new(),
             params: MemeParams::default(),
             keywords: Vec::new(),
             shortcuts: Vec::new(),
@@ -141,6 +296,17 @@ pub enum OptionValue {
     Float(f32),
 }
 
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Boolean(value) => write!(f, "{value}"),
+            OptionValue::String(value) => write!(f, "\"{value}\""),
+            OptionValue::Integer(value) => write!(f, "{value}"),
+            OptionValue::Float(value) => write!(f, "{value}"),
+        }
+    }
+}
+
 impl Into<OptionValue> for bool {
     fn into(self) -> OptionValue {
         OptionValue::Boolean(self)
@@ -171,6 +337,55 @@ impl Into<OptionValue> for f32 {
     }
 }
 
+/// 表情生成结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedImage {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: u32,
+    /// 每一帧的时长，单位为秒，静图只有一帧且时长为 0
+    pub durations: Vec<f32>,
+    /// 生成时使用的随机数种子，表情没有用到随机数时为 `None`
+    pub seed: Option<i32>,
+    /// 为满足大小限制对结果做的降级处理，按处理顺序排列
+    #[serde(default)]
+    pub degradations: Vec<Degradation>,
+}
+
+impl GeneratedImage {
+    /// 从编码后的图片数据读取格式、尺寸和帧信息，用于不知道生成过程的表情（如插件）
+    pub fn from_data(data: Vec<u8>) -> Result<Self, Error> {
+        let probe = probe(&data)
+            .ok_or_else(|| Error::ImageEncodeError("Unrecognized image format".to_string()))?;
+        Ok(GeneratedImage {
+            seed: None,
+            degradations: Vec::new(),
+            format: probe.format,
+            width: probe.width,
+            height: probe.height,
+            frame_count: probe.durations.len() as u32,
+            durations: probe.durations,
+            data,
+        })
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        self.format.mime_type()
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.frame_count > 1
+    }
+}
+
+impl From<GeneratedImage> for Vec<u8> {
+    fn from(image: GeneratedImage) -> Self {
+        image.data
+    }
+}
+
 pub trait Meme: Send + Sync {
     fn key(&self) -> String;
     fn info(&self) -> MemeInfo;
@@ -181,4 +396,21 @@ pub trait Meme: Send + Sync {
         options: HashMap<String, OptionValue>,
     ) -> Result<Vec<u8>, Error>;
     fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error>;
+
+    /// 生成表情并返回包含格式和帧信息的结果，默认由 [`Meme::generate`] 的结果读取
+    fn generate_image(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        GeneratedImage::from_data(self.generate(images, texts, options)?)
+    }
+
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        GeneratedImage::from_data(self.generate_preview(options)?)
+    }
 }
@@ -0,0 +1,547 @@
+//! 跨编译器版本稳定的 C ABI 插件接口
+//!
+//! 插件与宿主之间只传递不透明句柄、C 字符串和字节缓冲区，
+//! 表情信息、选项和错误以 JSON 编码，因此插件不需要与宿主使用相同的 rustc 版本编译。
+//! 插件使用 [`declare_meme_plugin!`](crate::declare_meme_plugin) 导出 `MEME_PLUGIN_DECLARATION`，
+//! 宿主使用 [`load_plugin`] 加载。
+
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsStr, c_char},
+    fmt, ptr, slice,
+    sync::Arc,
+};
+
+use libloading::Library;
+
+use crate::{
+    error::Error,
+    meme::{Image, Meme, MemeInfo, OptionValue},
+};
+
+/// 当前插件 ABI 版本，结构体布局或调用约定发生不兼容变化时递增
+pub const MEME_PLUGIN_ABI_VERSION: u32 = 1;
+
+pub static MEME_PLUGIN_SYMBOL: &[u8] = b"MEME_PLUGIN_DECLARATION";
+
+/// 插件内部状态的不透明句柄
+#[repr(C)]
+pub struct MemePluginHandle {
+    _private: [u8; 0],
+}
+
+/// 由插件分配的字节缓冲区，必须通过同一插件的 `free_buffer` 释放
+#[repr(C)]
+pub struct MemeBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl MemeBuffer {
+    pub fn empty() -> Self {
+        MemeBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        let mut data = std::mem::ManuallyDrop::new(data);
+        MemeBuffer {
+            data: data.as_mut_ptr(),
+            len: data.len(),
+            capacity: data.capacity(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// 缓冲区必须由当前模块中的 [`MemeBuffer::from_vec`] 创建
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        if self.data.is_null() {
+            return Vec::new();
+        }
+        unsafe { Vec::from_raw_parts(self.data, self.len, self.capacity) }
+    }
+
+    /// # Safety
+    ///
+    /// `data` 必须指向至少 `len` 字节的有效内存
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.data, self.len) }
+    }
+}
+
+#[repr(C)]
+pub struct MemeImageRef {
+    pub name: *const c_char,
+    pub data: *const u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct MemeRequest {
+    pub images: *const MemeImageRef,
+    pub image_count: usize,
+    pub texts: *const *const c_char,
+    pub text_count: usize,
+    /// JSON 编码的选项对象
+    pub options: *const c_char,
+}
+
+/// 插件函数的返回状态，跨越 FFI 边界时以 `i32` 传递，宿主使用 [`TryFrom`] 转换
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemeStatus {
+    /// 输出缓冲区中为结果数据
+    Ok = 0,
+    /// 输出缓冲区中为 JSON 编码的 [`Error`]
+    Error = 1,
+    /// 插件内部发生 panic，输出缓冲区中为 panic 信息
+    Panic = 2,
+}
+
+impl TryFrom<i32> for MemeStatus {
+    type Error = i32;
+
+    fn try_from(status: i32) -> Result<Self, i32> {
+        match status {
+            0 => Ok(MemeStatus::Ok),
+            1 => Ok(MemeStatus::Error),
+            2 => Ok(MemeStatus::Panic),
+            status => Err(status),
+        }
+    }
+}
+
+/// 插件导出的声明，符号名为 `MEME_PLUGIN_DECLARATION`
+#[repr(C)]
+pub struct MemePluginDeclaration {
+    pub abi_version: u32,
+    /// 插件编译时的 meme_generator_core 版本，仅用于诊断
+    pub core_version: *const c_char,
+    /// 注册表情时发生 panic 则返回空指针
+    pub init: unsafe extern "C" fn() -> *mut MemePluginHandle,
+    pub destroy: unsafe extern "C" fn(*mut MemePluginHandle),
+    pub meme_count: unsafe extern "C" fn(*const MemePluginHandle) -> usize,
+    /// 输出 JSON 编码的 [`MemeInfo`]，返回值为 [`MemeStatus`]
+    pub meme_info: unsafe extern "C" fn(*const MemePluginHandle, usize, *mut MemeBuffer) -> i32,
+    pub generate: unsafe extern "C" fn(
+        *const MemePluginHandle,
+        usize,
+        *const MemeRequest,
+        *mut MemeBuffer,
+    ) -> i32,
+    /// 第三个参数为 JSON 编码的选项对象
+    pub generate_preview:
+        unsafe extern "C" fn(*const MemePluginHandle, usize, *const c_char, *mut MemeBuffer) -> i32,
+    pub free_buffer: unsafe extern "C" fn(MemeBuffer),
+}
+
+unsafe impl Sync for MemePluginDeclaration {}
+
+/// 导出 C ABI 插件声明
+///
+/// `$register` 为 `fn(&mut dyn MemeRegistry)`，与内置表情的 `register_memes` 签名相同。
+#[macro_export]
+macro_rules! declare_meme_plugin {
+    ($register:expr) => {
+        unsafe extern "C" fn __meme_plugin_init() -> *mut $crate::plugin::MemePluginHandle {
+            $crate::plugin::export::init($register)
+        }
+
+        #[unsafe(no_mangle)]
+        pub static MEME_PLUGIN_DECLARATION: $crate::plugin::MemePluginDeclaration =
+            $crate::plugin::MemePluginDeclaration {
+                abi_version: $crate::plugin::MEME_PLUGIN_ABI_VERSION,
+                core_version: $crate::plugin::export::CORE_VERSION_CSTR.as_ptr(),
+                init: __meme_plugin_init,
+                destroy: $crate::plugin::export::destroy,
+                meme_count: $crate::plugin::export::meme_count,
+                meme_info: $crate::plugin::export::meme_info,
+                generate: $crate::plugin::export::generate,
+                generate_preview: $crate::plugin::export::generate_preview,
+                free_buffer: $crate::plugin::export::free_buffer,
+            };
+    };
+}
+
+/// 插件侧实现，由 [`declare_meme_plugin!`](crate::declare_meme_plugin) 引用
+#[doc(hidden)]
+pub mod export {
+    use std::{
+        collections::HashMap,
+        ffi::{CStr, c_char},
+        panic::{self, AssertUnwindSafe},
+        ptr, slice,
+    };
+
+    use super::{MemeBuffer, MemePluginHandle, MemeRequest, MemeStatus};
+    use crate::{
+        error::Error,
+        meme::{Image, Meme, OptionValue},
+        registry::MemeRegistry,
+    };
+
+    pub static CORE_VERSION_CSTR: &CStr =
+        match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+            Ok(version) => version,
+            Err(_) => panic!("invalid core version"),
+        };
+
+    struct PluginState {
+        memes: Vec<Box<dyn Meme>>,
+    }
+
+    impl MemeRegistry for PluginState {
+        fn register_meme(&mut self, _: &str, meme: Box<dyn Meme>) {
+            self.memes.push(meme);
+        }
+    }
+
+    pub fn init(register: fn(&mut dyn MemeRegistry)) -> *mut MemePluginHandle {
+        let mut state = PluginState { memes: Vec::new() };
+        // panic 不能越过 C ABI 展开，以空指针通知宿主
+        match panic::catch_unwind(AssertUnwindSafe(|| register(&mut state))) {
+            Ok(()) => Box::into_raw(Box::new(state)) as *mut MemePluginHandle,
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn state<'a>(handle: *const MemePluginHandle) -> &'a PluginState {
+        unsafe { &*(handle as *const PluginState) }
+    }
+
+    fn write_result(out: *mut MemeBuffer, result: Result<Vec<u8>, Error>) -> MemeStatus {
+        let (status, data) = match result {
+            Ok(data) => (MemeStatus::Ok, data),
+            Err(err) => (
+                MemeStatus::Error,
+                serde_json::to_vec(&err).unwrap_or_default(),
+            ),
+        };
+        unsafe { out.write(MemeBuffer::from_vec(data)) };
+        status
+    }
+
+    fn guard(out: *mut MemeBuffer, func: impl FnOnce() -> Result<Vec<u8>, Error>) -> i32 {
+        let status = match panic::catch_unwind(AssertUnwindSafe(func)) {
+            Ok(result) => write_result(out, result),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                unsafe { out.write(MemeBuffer::from_vec(message.into_bytes())) };
+                MemeStatus::Panic
+            }
+        };
+        status as i32
+    }
+
+    unsafe fn c_str(ptr: *const c_char) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+        unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    unsafe fn parse_options(ptr: *const c_char) -> Result<HashMap<String, OptionValue>, Error> {
+        let options = unsafe { c_str(ptr) };
+        if options.is_empty() {
+            return Ok(HashMap::new());
+        }
+        serde_json::from_str(&options).map_err(|err| Error::DeserializeError(err.to_string()))
+    }
+
+    fn get_meme(state: &PluginState, index: usize) -> Result<&dyn Meme, Error> {
+        state
+            .memes
+            .get(index)
+            .map(|meme| meme.as_ref())
+            .ok_or(Error::DeserializeError(format!("No meme at index {index}")))
+    }
+
+    pub unsafe extern "C" fn destroy(handle: *mut MemePluginHandle) {
+        if !handle.is_null() {
+            drop(unsafe { Box::from_raw(handle as *mut PluginState) });
+        }
+    }
+
+    pub unsafe extern "C" fn meme_count(handle: *const MemePluginHandle) -> usize {
+        unsafe { state(handle) }.memes.len()
+    }
+
+    pub unsafe extern "C" fn meme_info(
+        handle: *const MemePluginHandle,
+        index: usize,
+        out: *mut MemeBuffer,
+    ) -> i32 {
+        guard(out, || {
+            let meme = get_meme(unsafe { state(handle) }, index)?;
+            serde_json::to_vec(&meme.info()).map_err(|err| Error::DeserializeError(err.to_string()))
+        })
+    }
+
+    pub unsafe extern "C" fn generate(
+        handle: *const MemePluginHandle,
+        index: usize,
+        request: *const MemeRequest,
+        out: *mut MemeBuffer,
+    ) -> i32 {
+        guard(out, || {
+            let meme = get_meme(unsafe { state(handle) }, index)?;
+            let request = unsafe { &*request };
+            let images = if request.image_count == 0 {
+                Vec::new()
+            } else {
+                unsafe { slice::from_raw_parts(request.images, request.image_count) }
+                    .iter()
+                    .map(|image| Image {
+                        name: unsafe { c_str(image.name) },
+                        data: unsafe { slice::from_raw_parts(image.data, image.len) }.to_vec(),
+                    })
+                    .collect()
+            };
+            let texts = if request.text_count == 0 {
+                Vec::new()
+            } else {
+                unsafe { slice::from_raw_parts(request.texts, request.text_count) }
+                    .iter()
+                    .map(|text| unsafe { c_str(*text) })
+                    .collect()
+            };
+            let options = unsafe { parse_options(request.options) }?;
+            meme.generate(images, texts, options)
+        })
+    }
+
+    pub unsafe extern "C" fn generate_preview(
+        handle: *const MemePluginHandle,
+        index: usize,
+        options: *const c_char,
+        out: *mut MemeBuffer,
+    ) -> i32 {
+        guard(out, || {
+            let meme = get_meme(unsafe { state(handle) }, index)?;
+            let options = unsafe { parse_options(options) }?;
+            meme.generate_preview(options)
+        })
+    }
+
+    pub unsafe extern "C" fn free_buffer(buffer: MemeBuffer) {
+        drop(unsafe { buffer.into_vec() });
+    }
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    /// 动态库中没有 `MEME_PLUGIN_DECLARATION` 符号
+    NotAPlugin,
+    LibraryError(libloading::Error),
+    AbiVersionMismatch(u32),
+    InvalidMemeInfo(String),
+    /// 插件注册表情时发生 panic
+    InitFailed,
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::NotAPlugin => write!(f, "Library does not declare a meme plugin"),
+            PluginError::LibraryError(err) => write!(f, "Failed to load library: {err}"),
+            PluginError::AbiVersionMismatch(version) => write!(
+                f,
+                "Plugin ABI version {version} is not supported, expected {MEME_PLUGIN_ABI_VERSION}",
+            ),
+            PluginError::InvalidMemeInfo(err) => write!(f, "Invalid meme info: {err}"),
+            PluginError::InitFailed => write!(f, "Plugin failed to initialize"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+struct PluginLibrary {
+    declaration: *const MemePluginDeclaration,
+    handle: *mut MemePluginHandle,
+    _library: Library,
+}
+
+impl PluginLibrary {
+    fn declaration(&self) -> &MemePluginDeclaration {
+        unsafe { &*self.declaration }
+    }
+
+    fn read_buffer(&self, buffer: MemeBuffer) -> Vec<u8> {
+        let data = unsafe { buffer.as_slice() }.to_vec();
+        unsafe { (self.declaration().free_buffer)(buffer) };
+        data
+    }
+
+    fn handle_status(&self, status: i32, buffer: MemeBuffer) -> Result<Vec<u8>, Error> {
+        let data = self.read_buffer(buffer);
+        match MemeStatus::try_from(status) {
+            Ok(MemeStatus::Ok) => Ok(data),
+            Ok(MemeStatus::Error) => Err(serde_json::from_slice(&data).unwrap_or_else(|err| {
+                Error::DeserializeError(format!("Invalid error from plugin: {err}"))
+            })),
+            Ok(MemeStatus::Panic) => Err(Error::MemeFeedback(format!(
+                "Plugin panicked: {}",
+                String::from_utf8_lossy(&data)
+            ))),
+            Err(status) => Err(Error::DeserializeError(format!(
+                "Invalid status from plugin: {status}"
+            ))),
+        }
+    }
+}
+
+impl Drop for PluginLibrary {
+    fn drop(&mut self) {
+        unsafe { (self.declaration().destroy)(self.handle) };
+    }
+}
+
+unsafe impl Send for PluginLibrary {}
+unsafe impl Sync for PluginLibrary {}
+
+/// 通过 C ABI 加载的表情
+pub struct PluginMeme {
+    library: Arc<PluginLibrary>,
+    index: usize,
+    info: MemeInfo,
+}
+
+fn options_to_cstring(options: &HashMap<String, OptionValue>) -> Result<CString, Error> {
+    let options =
+        serde_json::to_string(options).map_err(|err| Error::DeserializeError(err.to_string()))?;
+    CString::new(options).map_err(|err| Error::DeserializeError(err.to_string()))
+}
+
+fn to_cstring(value: &str) -> Result<CString, Error> {
+    CString::new(value).map_err(|err| Error::DeserializeError(err.to_string()))
+}
+
+impl Meme for PluginMeme {
+    fn key(&self) -> String {
+        self.info.key.clone()
+    }
+
+    fn info(&self) -> MemeInfo {
+        self.info.clone()
+    }
+
+    fn generate(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        let names = images
+            .iter()
+            .map(|image| to_cstring(&image.name))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let image_refs = images
+            .iter()
+            .zip(names.iter())
+            .map(|(image, name)| MemeImageRef {
+                name: name.as_ptr(),
+                data: image.data.as_ptr(),
+                len: image.data.len(),
+            })
+            .collect::<Vec<_>>();
+        let texts = texts
+            .iter()
+            .map(|text| to_cstring(text))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let text_ptrs = texts.iter().map(|text| text.as_ptr()).collect::<Vec<_>>();
+        let options = options_to_cstring(&options)?;
+        let request = MemeRequest {
+            images: image_refs.as_ptr(),
+            image_count: image_refs.len(),
+            texts: text_ptrs.as_ptr(),
+            text_count: text_ptrs.len(),
+            options: options.as_ptr(),
+        };
+
+        let mut buffer = MemeBuffer::empty();
+        let status = unsafe {
+            (self.library.declaration().generate)(
+                self.library.handle,
+                self.index,
+                &request,
+                &mut buffer,
+            )
+        };
+        self.library.handle_status(status, buffer)
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        let options = options_to_cstring(&options)?;
+        let mut buffer = MemeBuffer::empty();
+        let status = unsafe {
+            (self.library.declaration().generate_preview)(
+                self.library.handle,
+                self.index,
+                options.as_ptr(),
+                &mut buffer,
+            )
+        };
+        self.library.handle_status(status, buffer)
+    }
+}
+
+/// 加载 C ABI 插件并返回其中的所有表情
+///
+/// # Safety
+///
+/// 加载动态库会执行其中的初始化代码，调用方需确保动态库来源可信
+pub unsafe fn load_plugin(path: impl AsRef<OsStr>) -> Result<Vec<PluginMeme>, PluginError> {
+    let library = unsafe { Library::new(path) }.map_err(PluginError::LibraryError)?;
+    let declaration =
+        match unsafe { library.get::<*const MemePluginDeclaration>(MEME_PLUGIN_SYMBOL) } {
+            Ok(symbol) => *symbol,
+            Err(_) => return Err(PluginError::NotAPlugin),
+        };
+    let abi_version = unsafe { (*declaration).abi_version };
+    if abi_version != MEME_PLUGIN_ABI_VERSION {
+        return Err(PluginError::AbiVersionMismatch(abi_version));
+    }
+
+    let handle = unsafe { ((*declaration).init)() };
+    if handle.is_null() {
+        return Err(PluginError::InitFailed);
+    }
+    let library = Arc::new(PluginLibrary {
+        declaration,
+        handle,
+        _library: library,
+    });
+
+    let count = unsafe { (library.declaration().meme_count)(handle) };
+    let mut memes = Vec::new();
+    for index in 0..count {
+        let mut buffer = MemeBuffer::empty();
+        let status = unsafe { (library.declaration().meme_info)(handle, index, &mut buffer) };
+        let data = library
+            .handle_status(status, buffer)
+            .map_err(|err| PluginError::InvalidMemeInfo(err.to_string()))?;
+        let info: MemeInfo = serde_json::from_slice(&data)
+            .map_err(|err| PluginError::InvalidMemeInfo(err.to_string()))?;
+        memes.push(PluginMeme {
+            library: Arc::clone(&library),
+            index,
+            info,
+        });
+    }
+    Ok(memes)
+}
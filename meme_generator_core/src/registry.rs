@@ -1,5 +1,8 @@
 use crate::meme::Meme;
 
+/// 旧式表情包声明，要求表情包与宿主使用完全相同的 rustc 和 meme_generator_core 版本编译
+///
+/// 新的表情包应使用 [`declare_meme_plugin!`](crate::declare_meme_plugin)
 #[allow(improper_ctypes_definitions)]
 pub struct MemePackDeclaration {
     pub rustc_version: &'static str,
@@ -12,8 +15,11 @@ pub trait MemeRegistry {
 }
 
 pub static RUSTC_VERSION: &str = env!("RUSTC_VERSION");
+/// 旧式表情包直接使用本 crate 中的类型，`Meme`、`MemeInfo`、`Error` 等类型的布局变化时必须提升版本，
+/// 使旧版本编译的表情包被拒绝加载
 pub static CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// 声明旧式表情包，仅在宿主开启 `load_legacy_libraries` 时加载
 #[macro_export]
 macro_rules! declare_meme_pack {
     ($register:expr) => {
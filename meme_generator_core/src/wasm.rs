@@ -0,0 +1,299 @@
+//! WebAssembly 沙箱插件接口
+//!
+//! `.wasm` 插件运行在宿主内嵌的解释器中，受内存和燃料（指令数）限制，
+//! 插件崩溃或超限只会使当次调用失败。每次调用都会创建新的实例，插件不应依赖调用之间的状态。
+//!
+//! 插件需要导出：
+//!
+//! - `memory`：线性内存
+//! - `meme_abi_version() -> i32`：返回 [`MEME_WASM_ABI_VERSION`]
+//! - `meme_alloc(len: i32) -> i32`：分配 `len` 字节，所有权交给插件
+//! - `meme_infos() -> i64`：返回 JSON 编码的 `Vec<MemeInfo>`
+//! - `meme_generate(ptr: i32, len: i32) -> i64`：参数为 JSON 编码的 [`WasmGenerateRequest`]
+//! - `meme_generate_preview(ptr: i32, len: i32) -> i64`：参数为 JSON 编码的 [`WasmPreviewRequest`]
+//!
+//! 返回值 `i64` 为打包的缓冲区位置，见 [`pack`]。生成结果以 [`encode_result`] 编码。
+//!
+//! 宿主在 [`WASM_HOST_MODULE`] 模块中提供：
+//!
+//! - `load_asset(ptr: i32, len: i32) -> i64`：读取图片资源目录下的文件
+//! - `render_text(ptr: i32, len: i32) -> i64`：按 [`TextRenderRequest`] 将文字渲染为 PNG
+//!
+//! 插件使用 [`declare_wasm_meme_plugin!`](crate::declare_wasm_meme_plugin) 导出上述接口。
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, meme::OptionValue};
+
+/// 当前 WASM 插件接口版本，导出函数或编码方式发生不兼容变化时递增
+pub const MEME_WASM_ABI_VERSION: u32 = 1;
+
+pub const WASM_HOST_MODULE: &str = "meme_host";
+
+/// 由宿主通过 `meme_alloc` 写入插件内存的图片
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmImage {
+    pub name: String,
+    pub ptr: u32,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmGenerateRequest {
+    /// 表情在 `meme_infos` 返回列表中的位置
+    pub index: u32,
+    pub images: Vec<WasmImage>,
+    pub texts: Vec<String>,
+    pub options: HashMap<String, OptionValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmPreviewRequest {
+    pub index: u32,
+    pub options: HashMap<String, OptionValue>,
+}
+
+/// 宿主函数 `render_text` 的参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextRenderRequest {
+    pub text: String,
+    pub font_size: f32,
+    pub color: String,
+    pub stroke_color: Option<String>,
+    pub stroke_width: f32,
+    pub font_families: Vec<String>,
+    /// 超过此宽度时自动换行
+    pub max_width: Option<f32>,
+    /// 是否按 BBCode 解析文字
+    pub bbcode: bool,
+}
+
+impl Default for TextRenderRequest {
+    fn default() -> Self {
+        TextRenderRequest {
+            text: String::new(),
+            font_size: 32.0,
+            color: "black".to_string(),
+            stroke_color: None,
+            stroke_width: 0.0,
+            font_families: Vec::new(),
+            max_width: None,
+            bbcode: false,
+        }
+    }
+}
+
+/// 将插件内存中的缓冲区位置打包为 `i64`，高 32 位为地址，低 32 位为长度
+pub fn pack(ptr: u32, len: u32) -> i64 {
+    (((ptr as u64) << 32) | len as u64) as i64
+}
+
+pub fn unpack(packed: i64) -> (u32, u32) {
+    let packed = packed as u64;
+    ((packed >> 32) as u32, packed as u32)
+}
+
+/// 首字节为 0 时后续为结果数据，为 1 时后续为 JSON 编码的 [`Error`]
+pub fn encode_result(result: Result<Vec<u8>, Error>) -> Vec<u8> {
+    match result {
+        Ok(mut data) => {
+            data.insert(0, 0);
+            data
+        }
+        Err(err) => {
+            let mut data = vec![1];
+            data.extend(serde_json::to_vec(&err).unwrap_or_default());
+            data
+        }
+    }
+}
+
+pub fn decode_result(mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if data.is_empty() {
+        return Err(Error::DeserializeError(
+            "Empty result from plugin".to_string(),
+        ));
+    }
+    match data.remove(0) {
+        0 => Ok(data),
+        1 => Err(serde_json::from_slice(&data).unwrap_or_else(|err| {
+            Error::DeserializeError(format!("Invalid error from plugin: {err}"))
+        })),
+        status => Err(Error::DeserializeError(format!(
+            "Invalid result status from plugin: {status}"
+        ))),
+    }
+}
+
+/// 导出 WASM 插件接口，仅在编译到 wasm 目标时生效
+///
+/// `$register` 为 `fn(&mut dyn MemeRegistry)`，与内置表情的 `register_memes` 签名相同。
+#[macro_export]
+macro_rules! declare_wasm_meme_plugin {
+    ($register:expr) => {
+        #[cfg(target_family = "wasm")]
+        const _: () = {
+            #[unsafe(no_mangle)]
+            pub extern "C" fn meme_abi_version() -> i32 {
+                $crate::wasm::MEME_WASM_ABI_VERSION as i32
+            }
+
+            #[unsafe(no_mangle)]
+            pub extern "C" fn meme_alloc(len: i32) -> i32 {
+                $crate::wasm::guest::alloc(len as usize) as i32
+            }
+
+            #[unsafe(no_mangle)]
+            pub extern "C" fn meme_infos() -> i64 {
+                $crate::wasm::guest::infos($register)
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn meme_generate(ptr: i32, len: i32) -> i64 {
+                unsafe { $crate::wasm::guest::generate($register, ptr as *mut u8, len as usize) }
+            }
+
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn meme_generate_preview(ptr: i32, len: i32) -> i64 {
+                unsafe {
+                    $crate::wasm::guest::generate_preview($register, ptr as *mut u8, len as usize)
+                }
+            }
+        };
+    };
+}
+
+/// 插件侧实现，插件可以通过 [`guest::load_asset`] 和 [`guest::render_text`] 调用宿主函数
+#[cfg(target_family = "wasm")]
+pub mod guest {
+    use std::sync::OnceLock;
+
+    use super::{TextRenderRequest, WasmGenerateRequest, WasmPreviewRequest, decode_result};
+    use crate::{
+        error::Error,
+        meme::{Image, Meme},
+        registry::MemeRegistry,
+    };
+
+    #[link(wasm_import_module = "meme_host")]
+    unsafe extern "C" {
+        #[link_name = "load_asset"]
+        fn host_load_asset(ptr: *const u8, len: usize) -> i64;
+        #[link_name = "render_text"]
+        fn host_render_text(ptr: *const u8, len: usize) -> i64;
+    }
+
+    struct PluginMemes(Vec<Box<dyn Meme>>);
+
+    impl MemeRegistry for PluginMemes {
+        fn register_meme(&mut self, _: &str, meme: Box<dyn Meme>) {
+            self.0.push(meme);
+        }
+    }
+
+    static MEMES: OnceLock<PluginMemes> = OnceLock::new();
+
+    fn memes(register: fn(&mut dyn MemeRegistry)) -> &'static [Box<dyn Meme>] {
+        &MEMES
+            .get_or_init(|| {
+                let mut memes = PluginMemes(Vec::new());
+                register(&mut memes);
+                memes
+            })
+            .0
+    }
+
+    #[doc(hidden)]
+    pub fn alloc(len: usize) -> *mut u8 {
+        Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+    }
+
+    /// 取回由 [`alloc`] 分配的内存
+    unsafe fn take(ptr: *mut u8, len: usize) -> Vec<u8> {
+        if len == 0 {
+            return Vec::new();
+        }
+        unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) }.into_vec()
+    }
+
+    /// 将数据交给宿主读取，宿主读取后整个实例即被丢弃，因此无需释放
+    fn leak(data: Vec<u8>) -> i64 {
+        let data = data.leak();
+        super::pack(data.as_ptr() as u32, data.len() as u32)
+    }
+
+    unsafe fn take_packed(packed: i64) -> Vec<u8> {
+        let (ptr, len) = super::unpack(packed);
+        unsafe { take(ptr as *mut u8, len as usize) }
+    }
+
+    #[doc(hidden)]
+    pub fn infos(register: fn(&mut dyn MemeRegistry)) -> i64 {
+        let infos = memes(register)
+            .iter()
+            .map(|meme| meme.info())
+            .collect::<Vec<_>>();
+        leak(serde_json::to_vec(&infos).unwrap_or_default())
+    }
+
+    fn find_meme(
+        register: fn(&mut dyn MemeRegistry),
+        index: u32,
+    ) -> Result<&'static dyn Meme, Error> {
+        memes(register)
+            .get(index as usize)
+            .map(|meme| meme.as_ref())
+            .ok_or_else(|| Error::DeserializeError(format!("Meme index {index} out of range")))
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn generate(register: fn(&mut dyn MemeRegistry), ptr: *mut u8, len: usize) -> i64 {
+        let request = unsafe { take(ptr, len) };
+        let result = serde_json::from_slice::<WasmGenerateRequest>(&request)
+            .map_err(|err| Error::DeserializeError(err.to_string()))
+            .and_then(|request| {
+                let meme = find_meme(register, request.index)?;
+                let images = request
+                    .images
+                    .into_iter()
+                    .map(|image| Image {
+                        name: image.name,
+                        data: unsafe { take(image.ptr as *mut u8, image.len as usize) },
+                    })
+                    .collect();
+                meme.generate(images, request.texts, request.options)
+            });
+        leak(super::encode_result(result))
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn generate_preview(
+        register: fn(&mut dyn MemeRegistry),
+        ptr: *mut u8,
+        len: usize,
+    ) -> i64 {
+        let request = unsafe { take(ptr, len) };
+        let result = serde_json::from_slice::<WasmPreviewRequest>(&request)
+            .map_err(|err| Error::DeserializeError(err.to_string()))
+            .and_then(|request| {
+                find_meme(register, request.index)?.generate_preview(request.options)
+            });
+        leak(super::encode_result(result))
+    }
+
+    /// 读取宿主图片资源目录下的文件
+    pub fn load_asset(path: &str) -> Result<Vec<u8>, Error> {
+        let packed = unsafe { host_load_asset(path.as_ptr(), path.len()) };
+        decode_result(unsafe { take_packed(packed) })
+    }
+
+    /// 由宿主渲染文字，返回 PNG 图片
+    pub fn render_text(request: &TextRenderRequest) -> Result<Vec<u8>, Error> {
+        let request = serde_json::to_vec(request).unwrap_or_default();
+        let packed = unsafe { host_render_text(request.as_ptr(), request.len()) };
+        decode_result(unsafe { take_packed(packed) })
+    }
+}
@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::make_png_or_gif,
     image::{Fit, ImageExt},
+    text::escape_bbcode,
     tools::{load_image, local_date},
 };
 
@@ -40,12 +41,15 @@ fn abstinence(images: Vec<InputImage>, _: Vec<String>, options: Time) -> Result<
     canvas
         .draw_bbcode_text_area_auto_font_size(
             IRect::from_ltrb(150, 650, 760, 800),
-            format!("戒导人：[u]{name}[/u]"),
+            format!("戒导人：[u]{}[/u]", escape_bbcode(name)),
             10.0,
             20.0,
             None,
         )
-        .map_err(|_| Error::TextOverLength(name.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     canvas
         .draw_bbcode_text_area(
             IRect::from_ltrb(150, 750, 760, 800),
@@ -101,7 +101,7 @@ fn ace_attorney_dialog(
             break Ok(shadow_texts);
         }
         if font_size <= min_font_size {
-            break Err(Error::TextOverLength(text.clone()));
+            break Err(Error::text_over_length_at(0, text));
         }
         font_size -= 10.0;
         shadow_width -= 0.3;
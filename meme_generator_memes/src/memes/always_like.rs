@@ -28,7 +28,10 @@ fn always_like(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<
             70.0,
             text_params!(font_style = FontStyle::bold()),
         )
-        .map_err(|_| Error::TextOverLength(name.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     let frame = surface.image_snapshot();
 
     let func = |images: Vec<Image>| {
@@ -22,7 +22,10 @@ fn ascension(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec
     let canvas = surface.canvas();
     canvas
         .draw_text_area_auto_font_size(IRect::from_ltrb(40, 30, 482, 135), text, 20.0, 50.0, None)
-        .map_err(|_| Error::TextOverLength(texts[0].to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::text_over_length_at(0, &texts[0]),
+            err => err,
+        })?;
 
     encode_png(surface.image_snapshot())
 }
@@ -42,7 +42,7 @@ fn ask(images: Vec<InputImage>, _: Vec<String>, options: Gender) -> Result<Vec<u
         ),
     );
     if name_image.longest_line() > 500.0 {
-        return Err(Error::TextOverLength(name.clone()));
+        return Err(Error::image_name_over_length(0, name));
     }
     let line_w = text_image.longest_line() + 200.0;
 
@@ -86,7 +86,10 @@ fn ask(images: Vec<InputImage>, _: Vec<String>, options: Gender) -> Result<Vec<u
             35.0,
             text_params!(text_align = TextAlign::Left),
         )
-        .map_err(|_| Error::TextOverLength(name.clone()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     canvas
         .draw_text_area(
             IRect::from_ltrb(
@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     text_params,
     tools::{load_image, local_date, new_paint, new_surface},
 };
@@ -35,7 +36,7 @@ fn atri_pillow(_: Vec<InputImage>, texts: Vec<String>, options: Mode) -> Result<
         "no"
     } else {
         options.mode.as_deref().unwrap_or({
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
             ["yes", "no"].choose(&mut rng).unwrap()
         })
     };
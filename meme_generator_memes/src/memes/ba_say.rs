@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     shortcut,
     tools::{load_image, local_date},
 };
@@ -34,7 +35,7 @@ struct Position {
 
 fn ba_say(_: Vec<InputImage>, texts: Vec<String>, options: Position) -> Result<Vec<u8>, Error> {
     let character = options.character.as_deref().unwrap_or({
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         ["arisu", "izuna", "key", "kokona", "mari", "sena", "yuuka"]
             .choose(&mut rng)
             .unwrap()
@@ -45,7 +46,7 @@ fn ba_say(_: Vec<InputImage>, texts: Vec<String>, options: Position) -> Result<V
         "right"
     } else {
         options.position.as_deref().unwrap_or({
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
             ["left", "right"].choose(&mut rng).unwrap()
         })
     };
@@ -4,7 +4,7 @@ use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
     encoder::encode_png,
-    text::Text2Image,
+    text::{Text2Image, escape_bbcode},
     text_params,
     tools::{load_image, local_date, new_stroke_paint, new_surface},
 };
@@ -21,14 +21,17 @@ fn batitle(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u
     let text2image = Text2Image::from_bbcode_text(
         format!(
             "[color={}]{}[/color][color={}][stroke=#ffffff]{}[/stroke][/color]",
-            color_blue, texts[0], color_gray, texts[1],
+            color_blue,
+            escape_bbcode(&texts[0]),
+            color_gray,
+            escape_bbcode(&texts[1]),
         ),
         fontsize,
         text_params!(
             font_families = font_families,
             stroke_paint = new_stroke_paint(Color::WHITE, 20.0),
         ),
-    );
+    )?;
 
     let text_w = text2image.longest_line();
     let text_h = text2image.height();
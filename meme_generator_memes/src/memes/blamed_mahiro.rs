@@ -4,7 +4,7 @@ use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
     canvas::CanvasExt,
-    encoder::GifEncoder,
+    encoder::AnimationEncoder,
     image::ImageExt,
     text_params,
     tools::{load_image, local_date, new_paint, new_stroke_paint, new_surface},
@@ -62,7 +62,7 @@ fn blamed_mahiro(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result
     )?;
     let text_image = surface.image_snapshot();
 
-    let mut encoder = GifEncoder::new();
+    let mut encoder = AnimationEncoder::new();
     for i in 0..24 {
         let frame = load_image(format!("blamed_mahiro/{i:02}.png"))?;
         let mut surface = frame.to_surface();
@@ -75,7 +75,7 @@ fn blamed_mahiro(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result
         let frame = surface.image_snapshot();
         encoder.add_frame(frame, 0.08)?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 register_meme!(
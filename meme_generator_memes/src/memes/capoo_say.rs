@@ -4,7 +4,7 @@ use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
     canvas::CanvasExt,
-    encoder::GifEncoder,
+    encoder::AnimationEncoder,
     image::ImageExt,
     text_params,
     tools::{load_image, local_date, new_surface},
@@ -13,7 +13,7 @@ use meme_generator_utils::{
 use crate::{options::NoOptions, register_meme, tags::MemeTags};
 
 fn capoo_say(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8>, Error> {
-    let mut encoder = GifEncoder::new();
+    let mut encoder = AnimationEncoder::new();
     for text in texts {
         let mut surface = new_surface((80, 80));
         let canvas = surface.canvas();
@@ -47,7 +47,7 @@ fn capoo_say(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec
             encoder.add_frame(surface.image_snapshot(), 0.1)?;
         }
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 register_meme! {
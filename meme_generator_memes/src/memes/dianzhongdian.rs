@@ -38,13 +38,18 @@ fn dianzhongdian(
     let mut surface = new_surface((frame_w, frame_h));
     let canvas = surface.canvas();
     canvas.clear(Color::BLACK);
-    canvas.draw_text_area_auto_font_size(
-        IRect::from_ltrb(20, img_h, frame_w - 20, img_h + 60),
-        &text,
-        25.0,
-        50.0,
-        text_params!(paint = new_paint(Color::WHITE)),
-    )?;
+    canvas
+        .draw_text_area_auto_font_size(
+            IRect::from_ltrb(20, img_h, frame_w - 20, img_h + 60),
+            &text,
+            25.0,
+            50.0,
+            text_params!(paint = new_paint(Color::WHITE)),
+        )
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::text_over_length_at(0, &text),
+            err => err,
+        })?;
     if let Some(trans) = trans {
         canvas
             .draw_text_area_auto_font_size(
@@ -54,7 +59,14 @@ fn dianzhongdian(
                 25.0,
                 text_params!(paint = new_paint(Color::WHITE)),
             )
-            .map_err(|_| Error::TextOverLength(text.to_string()))?;
+            .map_err(|err| match err {
+                Error::TextOverLength { .. } => {
+                    // 只有一段文字时翻译结果来自第一段文字
+                    let index = texts.len() - 1;
+                    Error::text_over_length_at(index, &texts[index])
+                }
+                err => err,
+            })?;
     };
     let frame = surface.image_snapshot();
 
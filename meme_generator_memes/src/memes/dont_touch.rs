@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::InputImage,
     encoder::make_png_or_gif,
     image::{Fit, ImageExt},
+    random::rng,
     tools::{load_image, local_date, new_paint},
 };
 
@@ -86,7 +87,7 @@ pub fn get_dominant_colors(image: &Image) -> Vec<Color> {
 fn draw_random_blocks(canvas: &Canvas, colors: &Vec<Color>, mask: &Image) {
     let (x1, y1, x2, y2) = (200, 300, 400, 650);
     let mut block_locs: Vec<(i32, i32)> = Vec::new();
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     let mask_pixmap = mask.peek_pixels().unwrap();
     for _ in 0..150 {
         let x = rng.gen_range(x1..=x2);
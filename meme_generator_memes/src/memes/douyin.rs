@@ -4,8 +4,9 @@ use skia_safe::{Color, IRect};
 use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
-    encoder::GifEncoder,
+    encoder::AnimationEncoder,
     image::ImageExt,
+    random::rng,
     text::Text2Image,
     text_params,
     tools::{color_from_hex_code, local_date, new_paint, new_stroke_paint, new_surface},
@@ -65,9 +66,9 @@ fn douyin(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
     let devide_num = 6;
     let seed = 20.0 * 0.05;
     let tilt = 0.17;
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
-    let mut encoder = GifEncoder::new();
+    let mut encoder = AnimationEncoder::new();
     for _ in 0..frame_num {
         let mut surface = frame.to_surface();
         let canvas = surface.canvas();
@@ -120,7 +121,7 @@ fn douyin(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
         encoder.add_frame(frame, 0.2)?;
     }
 
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 register_meme!(
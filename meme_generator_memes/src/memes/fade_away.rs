@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::InputImage,
     encoder::{GifInfo, make_gif_or_combined_gif},
     image::ImageExt,
+    random::rng,
     tools::{local_date, new_paint, new_surface},
 };
 
@@ -24,7 +25,7 @@ struct Dot {
 
 impl Dot {
     fn new(positon: (f32, f32), direction: (f32, f32)) -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         Self {
             x: positon.0,
             y: positon.1,
@@ -43,7 +44,7 @@ impl Dot {
         self.vy += a * self.dy;
         self.x += self.vx;
         self.y += self.vy;
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         if rng.gen_range(0.0..1.0) < 0.25 {
             self.radius -= 1.0;
         }
@@ -164,7 +165,7 @@ fn fade_away(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<Ve
             paint.set_shader(shader);
             canvas.draw_paint(&paint);
 
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
             let pixmap = img.peek_pixels().unwrap();
             for r in r1 as i32..r2 as i32 {
                 for theta in 0..180 {
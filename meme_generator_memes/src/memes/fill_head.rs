@@ -31,7 +31,10 @@ fn fill_head(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<Ve
             65.0,
             None,
         )
-        .map_err(|_| Error::TextOverLength(name.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     let frame = surface.image_snapshot();
 
     let func = |images: Vec<Image>| {
@@ -30,7 +30,7 @@ fn follow(images: Vec<InputImage>, _: Vec<String>, options: Gender) -> Result<Ve
     );
     let text_width = name_image.longest_line().max(follow_image.longest_line());
     if text_width >= 1000.0 {
-        return Err(Error::TextOverLength(name.to_string()));
+        return Err(Error::image_name_over_length(0, name));
     }
 
     let frame_w = 300 + text_width as i32 + 50;
@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::{InputImage, MemeOptions},
     encoder::{FrameAlign, GifInfo, make_gif_or_combined_gif},
     image::ImageExt,
+    random::rng,
     shortcut,
     tools::{load_image, local_date},
 };
@@ -25,7 +26,7 @@ fn genshin_eat(
     options: Character,
 ) -> Result<Vec<u8>, Error> {
     let character = options.character.as_deref().unwrap_or({
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         ["hutao", "keqing", "klee", "nilou", "yae_miko", "zhongli"]
             .choose(&mut rng)
             .unwrap()
@@ -1,13 +1,12 @@
 use std::fs::read;
 
-use skia_safe::{Codec, Color, Data};
+use skia_safe::{Color, Image, Matrix};
 
 use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
     config::IMAGES_DIR,
-    decoder::CodecExt,
-    encoder::GifEncoder,
+    encoder::make_png_or_gif_parallel,
     image::ImageExt,
     text::Text2Image,
     text_params,
@@ -27,44 +26,48 @@ fn gif_subtitle(
     if !(image_path.exists() && image_path.is_file()) {
         return Err(Error::ImageAssetMissing(path));
     }
-    let data = Data::new_copy(&read(&image_path).unwrap());
-    let mut codec =
-        Codec::from_data(data).ok_or(Error::ImageDecodeError("Skia decode error".to_string()))?;
+    let template = InputImage::from_asset(template_name, &read(&image_path).unwrap())?;
 
-    let mut encoder = GifEncoder::new();
-    let duration = codec.get_average_duration()?;
-    for i in 0..codec.get_frame_count() {
-        let mut frame = codec.get_frame(i)?;
+    // 文字在所有帧中相同，预先记录以便在各个线程中绘制
+    let subtitles = texts
+        .iter()
+        .map(|text| {
+            let text2image = Text2Image::from_text(
+                text,
+                font_size,
+                text_params!(
+                    paint = new_paint(Color::WHITE),
+                    stroke_paint = new_stroke_paint(Color::BLACK, font_size / 10.0),
+                ),
+            );
+            Ok((
+                text2image.to_picture()?,
+                text2image.longest_line(),
+                text2image.height(),
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
 
+    let func = |i: usize, images: Vec<Image>| {
+        let frame = &images[0];
         for (text_i, &(start, end)) in pieces.iter().enumerate() {
             if i >= start && i < end {
+                let (picture, text_w, text_h) = &subtitles[text_i];
                 let mut surface = frame.to_surface();
                 let canvas = surface.canvas();
                 let padding_y = 5.0;
-                let text2image = Text2Image::from_text(
-                    &texts[text_i],
-                    font_size,
-                    text_params!(
-                        paint = new_paint(Color::WHITE),
-                        stroke_paint = new_stroke_paint(Color::BLACK, font_size / 10.0),
-                    ),
+                let origin = (
+                    (frame.width() as f32 - text_w) / 2.0,
+                    frame.height() as f32 - padding_y - text_h,
                 );
-                text2image.draw_on_canvas(
-                    &canvas,
-                    (
-                        (frame.width() as f32 - text2image.longest_line()) / 2.0,
-                        frame.height() as f32 - padding_y - text2image.height(),
-                    ),
-                );
-                frame = surface.image_snapshot();
-                break;
+                canvas.draw_picture(picture, Some(&Matrix::translate(origin)), None);
+                return Ok(surface.image_snapshot());
             }
         }
+        Ok(frame.clone())
+    };
 
-        encoder.add_frame(frame, duration)?;
-    }
-
-    Ok(encoder.finish())
+    make_png_or_gif_parallel(vec![template], func)
 }
 
 macro_rules! register_gif_subtitle {
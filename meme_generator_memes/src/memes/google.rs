@@ -4,7 +4,7 @@ use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
     encoder::encode_png,
-    text::Text2Image,
+    text::{Text2Image, escape_bbcode},
     tools::{local_date, new_surface},
 };
 
@@ -23,11 +23,15 @@ fn google(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
             if char.is_whitespace() {
                 char.to_string()
             } else {
-                format!("[color={}]{}[/color]", colors[i % colors.len()], char)
+                format!(
+                    "[color={}]{}[/color]",
+                    colors[i % colors.len()],
+                    escape_bbcode(&char.to_string())
+                )
             }
         })
         .collect();
-    let text2image = Text2Image::from_bbcode_text(&bbcode_text, 200.0, None);
+    let text2image = Text2Image::from_bbcode_text(&bbcode_text, 200.0, None)?;
     let mut surface = new_surface((
         text2image.longest_line() as i32 + 100,
         text2image.height() as i32 + 100,
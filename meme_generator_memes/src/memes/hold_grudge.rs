@@ -38,7 +38,7 @@ fn hold_grudge(_: Vec<InputImage>, texts: Vec<String>, options: Time) -> Result<
         Text2Image::from_text(&text, 45.0, text_params!(text_align = TextAlign::Left));
     text2image.layout(440.0);
     if text2image.height() > 500.0 {
-        return Err(Error::TextOverLength(texts[0].clone()));
+        return Err(Error::text_over_length_at(0, &texts[0]));
     }
 
     let frame = load_image("hold_grudge/0.png")?;
@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     tools::{load_image, local_date},
 };
 
@@ -17,7 +18,7 @@ number_option!(Number, 1, 13);
 fn jinhsi(_: Vec<InputImage>, texts: Vec<String>, options: Number) -> Result<Vec<u8>, Error> {
     let text = &texts[0];
     let num = options.number.unwrap_or({
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         rng.gen_range(1..=13)
     });
 
@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::{InputImage, MemeOptions},
     encoder::make_png_or_gif,
     image::{Fit, ImageExt},
+    random::rng,
     tools::{load_image, local_date, new_surface},
 };
 
@@ -24,7 +25,7 @@ fn keep_your_money(
     options: Character,
 ) -> Result<Vec<u8>, Error> {
     let character = options.character.as_deref().unwrap_or({
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         ["arona", "plana"].choose(&mut rng).unwrap()
     });
     let frame = load_image(format!("keep_your_money/{character}.png"))?;
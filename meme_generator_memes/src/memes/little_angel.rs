@@ -41,7 +41,10 @@ fn little_angel(
             70.0,
             text_params.clone(),
         )
-        .map_err(|_| Error::TextOverLength(name.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     canvas
         .draw_text_area(
             IRect::from_ltrb(10, img_h + 115, 590, img_h + 190),
@@ -33,7 +33,7 @@ fn make_friend(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<
     let text2image =
         Text2Image::from_text(name, 20.0, text_params!(paint = new_paint(Color::WHITE)));
     if text2image.longest_line() > 230.0 {
-        return Err(Error::TextOverLength(name.to_string()));
+        return Err(Error::image_name_over_length(0, name));
     }
     canvas.draw_image(&frame, (0, 0), None);
     canvas.rotate(angle, Some(Point::new(710.0, 710.0)));
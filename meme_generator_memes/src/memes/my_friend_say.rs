@@ -27,7 +27,7 @@ fn my_friend_say(
     let name_w = name_img.longest_line() as i32;
     let name_h = name_img.height() as i32;
     if name_w >= 600 {
-        return Err(Error::TextOverLength(name.to_string()));
+        return Err(Error::image_name_over_length(0, name));
     }
 
     let corner1 = load_image("my_friend/corner1.png")?;
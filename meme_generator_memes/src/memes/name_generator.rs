@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::make_png_or_gif,
     image::{Fit, ImageExt},
+    random::rng,
     text_params,
     tools::{color_from_hex_code, load_image, local_date, new_paint, new_surface},
 };
@@ -234,7 +235,7 @@ fn name_generator(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Resu
         "糖",
     ];
 
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     let color = colors.choose(&mut rng).unwrap();
     let name = format!(
         "{}{}{}",
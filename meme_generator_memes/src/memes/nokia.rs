@@ -36,7 +36,7 @@ fn nokia(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8>
     );
     text_img.layout(730.0);
     if text_img.height() > 450.0 {
-        return Err(Error::TextOverLength(text.to_string()));
+        return Err(Error::text_over_length_at(0, text));
     }
     text_img.draw_on_canvas(canvas, (270, 320));
     canvas.reset_matrix();
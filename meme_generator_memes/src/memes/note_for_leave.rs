@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::make_png_or_gif,
     image::{Fit, ImageExt},
+    text::escape_bbcode,
     text_params,
     tools::{local_date, new_paint, new_surface},
 };
@@ -59,11 +60,14 @@ fn note_for_leave(
     canvas
         .draw_bbcode_text_area(
             IRect::from_ltrb(40, 180, 760, 270),
-            format!("本人[u] {name} [/u]因"),
+            format!("本人[u] {} [/u]因", escape_bbcode(name)),
             50.0,
             text_params!(text_align = TextAlign::Left),
         )
-        .map_err(|_| Error::TextOverLength(name.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
     canvas.draw_text_area_auto_font_size(
         IRect::from_ltrb(40, 300, 285, 700),
         text,
@@ -77,7 +81,7 @@ fn note_for_leave(
         time.month(),
         time.day()
     );
-    canvas.draw_bbcode_text((40, 720), &leave_text, 50.0, None);
+    canvas.draw_bbcode_text((40, 720), &leave_text, 50.0, None)?;
     canvas.draw_text(
         (40, 800),
         "望领导批准！！！",
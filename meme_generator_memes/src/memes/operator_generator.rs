@@ -7,6 +7,7 @@ use meme_generator_utils::{
     canvas::CanvasExt,
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     text_params,
     tools::{load_image, local_date, new_paint, new_stroke_paint, new_surface},
 };
@@ -37,9 +38,12 @@ fn operator_generator(
                 font_style = FontStyle::bold(),
             ),
         )
-        .map_err(|_| Error::TextOverLength(name.clone()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::image_name_over_length(0, name),
+            err => err,
+        })?;
 
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
     let range = load_image(&format!(
         "operator_generator/range/{:02}.jpg",
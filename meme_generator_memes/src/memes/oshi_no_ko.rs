@@ -32,7 +32,7 @@ fn oshi_no_ko(images: Vec<InputImage>, _: Vec<String>, options: Name) -> Result<
         ),
     );
     if name_img.longest_line() > 800.0 {
-        return Err(Error::TextOverLength(name.to_string()));
+        return Err(Error::text_over_length(name.to_string()));
     }
     let text_h = name_img.height().ceil() as i32;
     let left = load_image("oshi_no_ko/text1.png")?.resize_height(text_h);
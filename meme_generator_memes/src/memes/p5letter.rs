@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::InputImage,
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     text::Text2Image,
     text_params,
     tools::{color_from_hex_code, load_image, local_date, new_paint, new_surface},
@@ -33,7 +34,7 @@ const COLOR_BLACK: &str = "#0F0F0F";
 
 impl BoxChar {
     fn new(char: char, mode: CharMode, font_size: f32) -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         let angle = rng.gen_range(-10.0..0.0);
         let angle = match mode {
             CharMode::First => angle,
@@ -173,7 +174,7 @@ fn p5letter(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<
     let text = &texts[0];
     let lines: Vec<&str> = text.lines().collect();
     if lines.len() > 5 {
-        return Err(Error::TextOverLength(text.to_string()));
+        return Err(Error::text_over_length_at(0, text));
     }
 
     let mut box_lines: Vec<BoxLine> = Vec::new();
@@ -186,7 +187,7 @@ fn p5letter(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<
             let mode = if box_chars.is_empty() {
                 CharMode::First
             } else {
-                if rand::thread_rng().gen_range(0.0..1.0) < 0.4 {
+                if rng().gen_range(0.0..1.0) < 0.4 {
                     CharMode::Red
                 } else {
                     CharMode::White
@@ -200,7 +201,7 @@ fn p5letter(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<
         }
         let line = BoxLine::new(box_chars);
         if line.width > 1700 {
-            return Err(Error::TextOverLength(text.to_string()));
+            return Err(Error::text_over_length_at(0, text));
         }
         box_lines.push(line);
     }
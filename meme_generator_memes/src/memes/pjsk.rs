@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::{InputImage, MemeOptions},
     encoder::encode_png,
     image::ImageExt,
+    random::rng,
     shortcut,
     text::Text2Image,
     text_params,
@@ -212,14 +213,14 @@ fn pjsk(_: Vec<InputImage>, texts: Vec<String>, options: Options) -> Result<Vec<
 
     let character = match options.character {
         None => {
-            let mut rng = rand::thread_rng();
+            let mut rng = rng();
             CHARACTERS.choose(&mut rng).unwrap()
         }
         Some(name) => CHARACTERS.iter().find(|c| c.name_en == name).unwrap(),
     };
 
     let num = match options.number {
-        None => rand::thread_rng().gen_range(1..=character.img_num),
+        None => rng().gen_range(1..=character.img_num),
         Some(n) => {
             if n < 1 || n > character.img_num {
                 return Err(Error::MemeFeedback(format!(
@@ -251,9 +252,7 @@ fn pjsk(_: Vec<InputImage>, texts: Vec<String>, options: Options) -> Result<Vec<
     let text_w = text2image.longest_line().ceil() as i32;
     let text_h = text2image.height().ceil() as i32;
 
-    let angle = options
-        .rotate
-        .unwrap_or_else(|| rand::thread_rng().gen_range(-40..40)) as f32;
+    let angle = options.rotate.unwrap_or_else(|| rng().gen_range(-40..40)) as f32;
     let x_offset = options.x_offset.unwrap();
     let y_offset = options.y_offset.unwrap();
 
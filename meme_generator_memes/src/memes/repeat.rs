@@ -4,7 +4,7 @@ use skia_safe::{Color, Image, textlayout::TextAlign};
 use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
-    encoder::GifEncoder,
+    encoder::AnimationEncoder,
     image::ImageExt,
     text::Text2Image,
     text_params,
@@ -17,7 +17,7 @@ fn repeat(images: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<V
     let text = &texts[0];
     let text_img = Text2Image::from_text(text, 50.0, text_params!(text_align = TextAlign::Left));
     if text_img.longest_line() > 900.0 {
-        return Err(Error::TextOverLength(text.to_string()));
+        return Err(Error::text_over_length_at(0, text));
     }
 
     let time = Local::now().format("%H:%M").to_string();
@@ -64,7 +64,7 @@ fn repeat(images: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<V
     canvas.draw_image(&self_img, (15, 40), None);
     let input_img = surface.image_snapshot();
 
-    let mut encoder = GifEncoder::new();
+    let mut encoder = AnimationEncoder::new();
     for i in 0..50 {
         let mut surface = new_surface((1079, 1192));
         let canvas = surface.canvas();
@@ -72,7 +72,7 @@ fn repeat(images: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<V
         canvas.draw_image(&input_img, (0, 1000), None);
         encoder.add_frame(surface.image_snapshot(), 0.08)?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 register_meme!(
@@ -3,7 +3,7 @@ use skia_safe::{Color, IRect, textlayout::TextAlign};
 use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::InputImage,
-    encoder::GifEncoder,
+    encoder::AnimationEncoder,
     text::Text2Image,
     text_params,
     tools::{color_from_hex_code, load_image, local_date, new_paint, new_surface},
@@ -20,7 +20,7 @@ fn scroll(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
         text2image.layout(600.0);
     }
     if text2image.height() > 200.0 {
-        return Err(Error::TextOverLength(text.to_string()));
+        return Err(Error::text_over_length_at(0, text));
     }
     let text_w = text2image.longest_line().ceil() as i32;
     let text_h = text2image.height().ceil() as i32;
@@ -57,7 +57,7 @@ fn scroll(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
     }
     let dialog = dialog_surface.image_snapshot();
 
-    let mut encoder = GifEncoder::new();
+    let mut encoder = AnimationEncoder::new();
     let num = 30;
     let dy = dialog.height() / num;
     for i in 0..num {
@@ -67,7 +67,7 @@ fn scroll(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
         frame_canvas.draw_image(&dialog, (0, dialog.height() - dy * i), None);
         encoder.add_frame(frame.image_snapshot(), 0.05)?;
     }
-    Ok(encoder.finish())
+    encoder.finish()
 }
 
 register_meme!(
@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::InputImage,
     encoder::{FrameAlign, GifInfo, make_gif_or_combined_gif},
     image::ImageExt,
+    random::rng,
     tools::{local_date, new_surface},
 };
 
@@ -36,7 +37,7 @@ fn shock(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<Vec<u8
 
     let func = |_: usize, images: Vec<Image>| {
         let img = images[0].square().resize_exact((300, 300));
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         let angle = rng.gen_range(-90..=90);
         let angle = (angle as f32).to_radians();
         let direction = (angle.cos(), angle.sin());
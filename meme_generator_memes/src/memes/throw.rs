@@ -6,6 +6,7 @@ use meme_generator_utils::{
     builder::InputImage,
     encoder::make_png_or_gif,
     image::ImageExt,
+    random::rng,
     tools::{load_image, local_date},
 };
 
@@ -13,7 +14,7 @@ use crate::{options::NoOptions, register_meme};
 
 fn throw(images: Vec<InputImage>, _: Vec<String>, _: NoOptions) -> Result<Vec<u8>, Error> {
     let func = |images: Vec<Image>| {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         let angle = rng.gen_range(1..=360);
         let img = images[0]
             .circle()
@@ -1,9 +1,9 @@
-use skia_safe::{Color, FontStyle, Image, Point, textlayout::TextAlign};
+use skia_safe::{Color, FontStyle, Image, Matrix, Point, textlayout::TextAlign};
 
 use meme_generator_core::error::Error;
 use meme_generator_utils::{
     builder::{InputImage, MemeOptions},
-    encoder::make_png_or_gif,
+    encoder::make_png_or_gif_parallel,
     image::ImageExt,
     text::Text2Image,
     text_params,
@@ -125,9 +125,10 @@ fn universal(
     let text2image = Text2Image::from_text(text, font_size as f32, text_params);
     let text_w = text2image.longest_line() as i32;
     let text_h = text2image.height() as i32;
+    let text_picture = text2image.to_picture()?;
     let padding = 10;
 
-    let func = |imgs: Vec<Image>| {
+    let func = |_, imgs: Vec<Image>| {
         let img_w = 500;
         let img = imgs[0].resize_width(img_w);
         let img_h = img.height();
@@ -153,13 +154,17 @@ fn universal(
             rotate as f32,
             Some(Point::new(center_x as f32, center_y as f32)),
         );
-        text2image.draw_on_canvas(&canvas, (center_x - text_w / 2, center_y - text_h / 2));
+        let text_origin = (
+            (center_x - text_w / 2) as f32,
+            (center_y - text_h / 2) as f32,
+        );
+        canvas.draw_picture(&text_picture, Some(&Matrix::translate(text_origin)), None);
         canvas.reset_matrix();
 
         Ok(surface.image_snapshot())
     };
 
-    make_png_or_gif(images, func)
+    make_png_or_gif_parallel(images, func)
 }
 
 register_meme!(
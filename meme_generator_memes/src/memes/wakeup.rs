@@ -32,7 +32,10 @@ fn wakeup(_: Vec<InputImage>, texts: Vec<String>, _: NoOptions) -> Result<Vec<u8
             110.0,
             None,
         )
-        .map_err(|_| Error::TextOverLength(text.to_string()))?;
+        .map_err(|err| match err {
+            Error::TextOverLength { .. } => Error::text_over_length_at(0, text),
+            err => err,
+        })?;
 
     encode_png(surface.image_snapshot())
 }
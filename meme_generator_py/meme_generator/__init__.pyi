@@ -1,5 +1,8 @@
 from datetime import datetime
-from typing import Optional, Union
+from typing import Literal, Optional, Union
+
+OutputFormat = Literal["png", "jpeg", "webp", "gif", "apng"]
+FrameMode = Literal["auto", "static", "animated"]
 
 class ParserFlags:
     short: bool
@@ -53,6 +56,7 @@ class MemeShortcut:
 
 class MemeInfo:
     key: str
+    pack: str
     params: MemeParams
     keywords: list[str]
     shortcuts: list[MemeShortcut]
@@ -64,33 +68,92 @@ class Image:
     def __new__(cls, name: str, data: bytes): ...
 
 class ImageDecodeError:
+    code: int
+    error: str
+
+class InvalidImage:
+    code: int
+    index: int
+    name: str
+    error: str
+
+class ImageTooLarge:
+    code: int
+    index: int
+    name: str
     error: str
 
 class ImageEncodeError:
+    code: int
     error: str
 
 class ImageAssetMissing:
+    code: int
     path: str
 
 class DeserializeError:
+    code: int
     error: str
 
+class InvalidOptionValue:
+    code: int
+    name: str
+    value: str
+    expected: str
+
 class ImageNumberMismatch:
+    code: int
     min: int
     max: int
     actual: int
 
 class TextNumberMismatch:
+    code: int
     min: int
     max: int
     actual: int
 
 class TextOverLength:
+    code: int
+    text: str
+    index: Optional[int]
+
+class ImageNameOverLength:
+    code: int
+    name: str
+    index: int
+
+class TextGlyphMissing:
+    code: int
     text: str
+    index: int
+    chars: str
+
+class InvalidBBCode:
+    code: int
+    text: str
+    index: Optional[int]
+    error: str
+
+class InvalidTextPath:
+    code: int
+    error: str
 
 class MemeFeedback:
+    code: int
     feedback: str
 
+class GeneratedImage:
+    data: bytes
+    format: str
+    mime_type: str
+    width: int
+    height: int
+    frame_count: int
+    durations: list[float]
+    seed: Optional[int]
+    degradations: list[str]
+
 class Meme:
     @property
     def key(self) -> str: ...
@@ -101,26 +164,91 @@ class Meme:
         images: list[Image],
         texts: list[str],
         options: dict[str, Union[bool, str, int, float]],
+        format: Optional[OutputFormat] = None,
+        quality: Optional[int] = None,
+        frames: Optional[FrameMode] = None,
+        max_output_bytes: Optional[int] = None,
     ) -> Union[
         bytes,
         ImageDecodeError,
+        InvalidImage,
+        ImageTooLarge,
         ImageEncodeError,
         ImageAssetMissing,
         DeserializeError,
+        InvalidOptionValue,
         ImageNumberMismatch,
         TextNumberMismatch,
         TextOverLength,
+        ImageNameOverLength,
+        TextGlyphMissing,
+        InvalidBBCode,
+        InvalidTextPath,
         MemeFeedback,
     ]: ...
     def generate_preview(
         self,
         options: dict[str, Union[bool, str, int, float]] = {},
+        format: Optional[OutputFormat] = None,
+        quality: Optional[int] = None,
+        frames: Optional[FrameMode] = None,
+        max_output_bytes: Optional[int] = None,
     ) -> Union[
         bytes,
         ImageEncodeError,
         ImageAssetMissing,
         DeserializeError,
+        InvalidOptionValue,
         TextOverLength,
+        TextGlyphMissing,
+        InvalidBBCode,
+        InvalidTextPath,
+        MemeFeedback,
+    ]: ...
+    def generate_image(
+        self,
+        images: list[Image],
+        texts: list[str],
+        options: dict[str, Union[bool, str, int, float]],
+        format: Optional[OutputFormat] = None,
+        quality: Optional[int] = None,
+        frames: Optional[FrameMode] = None,
+        max_output_bytes: Optional[int] = None,
+    ) -> Union[
+        GeneratedImage,
+        ImageDecodeError,
+        InvalidImage,
+        ImageTooLarge,
+        ImageEncodeError,
+        ImageAssetMissing,
+        DeserializeError,
+        InvalidOptionValue,
+        ImageNumberMismatch,
+        TextNumberMismatch,
+        TextOverLength,
+        ImageNameOverLength,
+        TextGlyphMissing,
+        InvalidBBCode,
+        InvalidTextPath,
+        MemeFeedback,
+    ]: ...
+    def generate_preview_image(
+        self,
+        options: dict[str, Union[bool, str, int, float]] = {},
+        format: Optional[OutputFormat] = None,
+        quality: Optional[int] = None,
+        frames: Optional[FrameMode] = None,
+        max_output_bytes: Optional[int] = None,
+    ) -> Union[
+        GeneratedImage,
+        ImageEncodeError,
+        ImageAssetMissing,
+        DeserializeError,
+        InvalidOptionValue,
+        TextOverLength,
+        TextGlyphMissing,
+        InvalidBBCode,
+        InvalidTextPath,
         MemeFeedback,
     ]: ...
 
@@ -129,3 +257,26 @@ def get_meme(key: str) -> Meme: ...
 def get_memes() -> list[Meme]: ...
 def get_meme_keys() -> list[str]: ...
 def search_memes(query: str, include_tags: bool = False) -> list[str]: ...
+
+
+class ReloadResult:
+    added: list[str]
+    removed: list[str]
+    total: int
+    restart_required: list[str]
+
+def reload_memes() -> Union[ReloadResult, DeserializeError]: ...
+
+class ParsedCommand:
+    key: str
+    texts: list[str]
+    options: dict[str, Union[bool, str, int, float]]
+    images: list[str]
+
+class CommandParseError:
+    code: int
+    message: str
+
+def parse_command(
+    command: str, args: str = ""
+) -> Union[ParsedCommand, CommandParseError]: ...
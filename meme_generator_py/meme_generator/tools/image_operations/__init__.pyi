@@ -1,6 +1,6 @@
 from typing import Optional, Union
 
-from ... import ImageDecodeError, ImageEncodeError
+from ... import ImageDecodeError, ImageEncodeError, ImageTooLarge
 
 class ImageInfo:
     width: int
@@ -14,49 +14,49 @@ def inspect(
 ) -> Union[ImageInfo, ImageDecodeError]: ...
 def flip_horizontal(
     image: bytes,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def flip_vertical(
     image: bytes,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def rotate(
     image: bytes,
     degrees: Optional[float] = 90.0,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def resize(
     image: bytes,
     width: Optional[int] = None,
     height: Optional[int] = None,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def crop(
     image: bytes,
     left: Optional[int] = None,
     top: Optional[int] = None,
     right: Optional[int] = None,
     bottom: Optional[int] = None,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def grayscale(
     image: bytes,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def invert(
     image: bytes,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def merge_horizontal(
     images: list[bytes],
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def merge_vertical(
     images: list[bytes],
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def gif_split(
     image: bytes,
-) -> Union[list[bytes], ImageDecodeError, ImageEncodeError]: ...
+) -> Union[list[bytes], ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def gif_merge(
     images: list[bytes],
     duration: Optional[float] = 0.1,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def gif_reverse(
     image: bytes,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
 def gif_change_duration(
     image: bytes,
     duration: float,
-) -> Union[bytes, ImageDecodeError, ImageEncodeError]: ...
+) -> Union[bytes, ImageDecodeError, ImageTooLarge, ImageEncodeError]: ...
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use chrono::{DateTime, Local};
 use pyo3::prelude::*;
@@ -29,19 +32,32 @@ fn meme_generator_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<MemeInfo>()?;
     m.add_class::<Image>()?;
     m.add_class::<ImageDecodeError>()?;
+    m.add_class::<InvalidImage>()?;
+    m.add_class::<ImageTooLarge>()?;
     m.add_class::<ImageEncodeError>()?;
     m.add_class::<ImageAssetMissing>()?;
     m.add_class::<DeserializeError>()?;
+    m.add_class::<InvalidOptionValue>()?;
     m.add_class::<ImageNumberMismatch>()?;
     m.add_class::<TextNumberMismatch>()?;
     m.add_class::<TextOverLength>()?;
+    m.add_class::<ImageNameOverLength>()?;
+    m.add_class::<TextGlyphMissing>()?;
+    m.add_class::<InvalidBBCode>()?;
+    m.add_class::<InvalidTextPath>()?;
     m.add_class::<MemeFeedback>()?;
+    m.add_class::<GeneratedImage>()?;
     m.add_class::<Meme>()?;
+    m.add_class::<ReloadResult>()?;
+    m.add_class::<ParsedCommand>()?;
+    m.add_class::<CommandParseError>()?;
     m.add_function(wrap_pyfunction!(get_version, m)?)?;
     m.add_function(wrap_pyfunction!(get_meme, m)?)?;
     m.add_function(wrap_pyfunction!(get_memes, m)?)?;
     m.add_function(wrap_pyfunction!(get_meme_keys, m)?)?;
     m.add_function(wrap_pyfunction!(search_memes, m)?)?;
+    m.add_function(wrap_pyfunction!(reload_memes, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_command, m)?)?;
     register_resources_module(m)?;
     register_tools_module(m)?;
     Ok(())
@@ -168,6 +184,8 @@ struct MemeInfo {
     #[pyo3(get)]
     key: String,
     #[pyo3(get)]
+    pack: String,
+    #[pyo3(get)]
     params: MemeParams,
     #[pyo3(get)]
     keywords: Vec<String>,
@@ -235,6 +253,34 @@ impl Into<meme::OptionValue> for OptionValue {
 #[pyclass]
 #[derive(Clone)]
 struct ImageDecodeError {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    error: String,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct InvalidImage {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    index: usize,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    error: String,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct ImageTooLarge {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    index: usize,
+    #[pyo3(get)]
+    name: String,
     #[pyo3(get)]
     error: String,
 }
@@ -242,6 +288,8 @@ struct ImageDecodeError {
 #[pyclass]
 #[derive(Clone)]
 struct ImageEncodeError {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     error: String,
 }
@@ -249,6 +297,8 @@ struct ImageEncodeError {
 #[pyclass]
 #[derive(Clone)]
 struct ImageAssetMissing {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     path: String,
 }
@@ -256,13 +306,30 @@ struct ImageAssetMissing {
 #[pyclass]
 #[derive(Clone)]
 struct DeserializeError {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     error: String,
 }
 
+#[pyclass]
+#[derive(Clone)]
+struct InvalidOptionValue {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    value: String,
+    #[pyo3(get)]
+    expected: String,
+}
+
 #[pyclass]
 #[derive(Clone)]
 struct ImageNumberMismatch {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     min: u8,
     #[pyo3(get)]
@@ -274,6 +341,8 @@ struct ImageNumberMismatch {
 #[pyclass]
 #[derive(Clone)]
 struct TextNumberMismatch {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     min: u8,
     #[pyo3(get)]
@@ -285,13 +354,65 @@ struct TextNumberMismatch {
 #[pyclass]
 #[derive(Clone)]
 struct TextOverLength {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     text: String,
+    #[pyo3(get)]
+    index: Option<usize>,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct ImageNameOverLength {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    index: usize,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct TextGlyphMissing {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    text: String,
+    #[pyo3(get)]
+    index: usize,
+    #[pyo3(get)]
+    chars: String,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct InvalidBBCode {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    text: String,
+    #[pyo3(get)]
+    index: Option<usize>,
+    #[pyo3(get)]
+    error: String,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct InvalidTextPath {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    error: String,
 }
 
 #[pyclass]
 #[derive(Clone)]
 struct MemeFeedback {
+    #[pyo3(get)]
+    code: u16,
     #[pyo3(get)]
     feedback: String,
 }
@@ -299,24 +420,170 @@ struct MemeFeedback {
 #[derive(IntoPyObject, Clone)]
 enum Error {
     ImageDecodeError(ImageDecodeError),
+    InvalidImage(InvalidImage),
+    ImageTooLarge(ImageTooLarge),
     ImageEncodeError(ImageEncodeError),
     ImageAssetMissing(ImageAssetMissing),
     DeserializeError(DeserializeError),
+    InvalidOptionValue(InvalidOptionValue),
     ImageNumberMismatch(ImageNumberMismatch),
     TextNumberMismatch(TextNumberMismatch),
     TextOverLength(TextOverLength),
+    ImageNameOverLength(ImageNameOverLength),
+    TextGlyphMissing(TextGlyphMissing),
+    InvalidBBCode(InvalidBBCode),
+    InvalidTextPath(InvalidTextPath),
     MemeFeedback(MemeFeedback),
 }
 
+impl From<error::Error> for Error {
+    fn from(error: error::Error) -> Self {
+        let code = error.code();
+        match error {
+            error::Error::ImageDecodeError(error) => {
+                Error::ImageDecodeError(ImageDecodeError { code, error })
+            }
+            error::Error::InvalidImage { index, name, error } => {
+                Error::InvalidImage(InvalidImage {
+                    code,
+                    index,
+                    name,
+                    error,
+                })
+            }
+            error::Error::ImageTooLarge { index, name, error } => {
+                Error::ImageTooLarge(ImageTooLarge {
+                    code,
+                    index,
+                    name,
+                    error,
+                })
+            }
+            error::Error::ImageEncodeError(error) => {
+                Error::ImageEncodeError(ImageEncodeError { code, error })
+            }
+            error::Error::ImageAssetMissing(path) => {
+                Error::ImageAssetMissing(ImageAssetMissing { code, path })
+            }
+            error::Error::DeserializeError(error) => {
+                Error::DeserializeError(DeserializeError { code, error })
+            }
+            error::Error::InvalidOptionValue {
+                name,
+                value,
+                expected,
+            } => Error::InvalidOptionValue(InvalidOptionValue {
+                code,
+                name,
+                value,
+                expected,
+            }),
+            error::Error::ImageNumberMismatch(min, max, actual) => {
+                Error::ImageNumberMismatch(ImageNumberMismatch {
+                    code,
+                    min,
+                    max,
+                    actual,
+                })
+            }
+            error::Error::TextNumberMismatch(min, max, actual) => {
+                Error::TextNumberMismatch(TextNumberMismatch {
+                    code,
+                    min,
+                    max,
+                    actual,
+                })
+            }
+            error::Error::TextOverLength { text, index } => {
+                Error::TextOverLength(TextOverLength { code, text, index })
+            }
+            error::Error::ImageNameOverLength { name, index } => {
+                Error::ImageNameOverLength(ImageNameOverLength { code, name, index })
+            }
+            error::Error::TextGlyphMissing { text, index, chars } => {
+                Error::TextGlyphMissing(TextGlyphMissing {
+                    code,
+                    text,
+                    index,
+                    chars,
+                })
+            }
+            error::Error::InvalidBBCode { text, index, error } => {
+                Error::InvalidBBCode(InvalidBBCode {
+                    code,
+                    text,
+                    index,
+                    error,
+                })
+            }
+            error::Error::InvalidTextPath(error) => {
+                Error::InvalidTextPath(InvalidTextPath { code, error })
+            }
+            error::Error::MemeFeedback(feedback) => {
+                Error::MemeFeedback(MemeFeedback { code, feedback })
+            }
+        }
+    }
+}
+
 #[derive(IntoPyObject, Clone)]
 enum MemeResult {
     Ok(Vec<u8>),
     Err(Error),
 }
 
+#[pyclass]
+#[derive(Clone)]
+struct GeneratedImage {
+    #[pyo3(get)]
+    data: Vec<u8>,
+    #[pyo3(get)]
+    format: String,
+    #[pyo3(get)]
+    mime_type: String,
+    #[pyo3(get)]
+    width: u32,
+    #[pyo3(get)]
+    height: u32,
+    #[pyo3(get)]
+    frame_count: u32,
+    #[pyo3(get)]
+    durations: Vec<f32>,
+    #[pyo3(get)]
+    seed: Option<i32>,
+    #[pyo3(get)]
+    degradations: Vec<String>,
+}
+
+impl From<meme::GeneratedImage> for GeneratedImage {
+    fn from(image: meme::GeneratedImage) -> Self {
+        GeneratedImage {
+            format: image.format.to_string(),
+            mime_type: image.mime_type().to_string(),
+            width: image.width,
+            height: image.height,
+            frame_count: image.frame_count,
+            durations: image.durations,
+            seed: image.seed,
+            degradations: image
+                .degradations
+                .iter()
+                .map(|degradation| degradation.to_string())
+                .collect(),
+            data: image.data,
+        }
+    }
+}
+
+#[derive(IntoPyObject, Clone)]
+enum GeneratedImageResult {
+    Ok(GeneratedImage),
+    Err(Error),
+}
+
 #[pyclass]
 struct Meme {
-    meme: &'static Box<dyn meme::Meme>,
+    meme: Arc<dyn meme::Meme>,
 }
 
 #[pymethods]
@@ -331,6 +598,7 @@ impl Meme {
         let info = self.meme.info();
         MemeInfo {
             key: info.key,
+            pack: info.pack,
             params: MemeParams {
                 min_images: info.params.min_images,
                 max_images: info.params.max_images,
@@ -441,75 +709,133 @@ impl Meme {
         }
     }
 
+    #[pyo3(signature = (images, texts, options, format=None, quality=None, frames=None, max_output_bytes=None))]
     fn generate(
         &self,
         images: Vec<Image>,
         texts: Vec<String>,
         options: HashMap<String, OptionValue>,
+        format: Option<String>,
+        quality: Option<i32>,
+        frames: Option<String>,
+        max_output_bytes: Option<i32>,
     ) -> MemeResult {
         let images = images
             .into_iter()
             .map(|Image { name, data }| meme::Image { name, data })
             .collect::<Vec<_>>();
 
-        let options = options
-            .into_iter()
-            .map(|(name, value)| (name, value.into()))
-            .collect::<HashMap<_, _>>();
+        let options = convert_options(options, format, quality, frames, max_output_bytes);
 
         let result = self.meme.generate(images, texts, options);
         handle_result(result)
     }
 
-    #[pyo3(signature = (options=HashMap::new()))]
-    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> MemeResult {
-        let options = options
-            .into_iter()
-            .map(|(name, value)| (name, value.into()))
-            .collect::<HashMap<_, _>>();
+    #[pyo3(signature = (options=HashMap::new(), format=None, quality=None, frames=None, max_output_bytes=None))]
+    fn generate_preview(
+        &self,
+        options: HashMap<String, OptionValue>,
+        format: Option<String>,
+        quality: Option<i32>,
+        frames: Option<String>,
+        max_output_bytes: Option<i32>,
+    ) -> MemeResult {
+        let options = convert_options(options, format, quality, frames, max_output_bytes);
 
         let result = self.meme.generate_preview(options);
         handle_result(result)
     }
+
+    #[pyo3(signature = (images, texts, options, format=None, quality=None, frames=None, max_output_bytes=None))]
+    fn generate_image(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+        format: Option<String>,
+        quality: Option<i32>,
+        frames: Option<String>,
+        max_output_bytes: Option<i32>,
+    ) -> GeneratedImageResult {
+        let images = images
+            .into_iter()
+            .map(|Image { name, data }| meme::Image { name, data })
+            .collect::<Vec<_>>();
+
+        let options = convert_options(options, format, quality, frames, max_output_bytes);
+
+        let result = self.meme.generate_image(images, texts, options);
+        handle_generated_result(result)
+    }
+
+    #[pyo3(signature = (options=HashMap::new(), format=None, quality=None, frames=None, max_output_bytes=None))]
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+        format: Option<String>,
+        quality: Option<i32>,
+        frames: Option<String>,
+        max_output_bytes: Option<i32>,
+    ) -> GeneratedImageResult {
+        let options = convert_options(options, format, quality, frames, max_output_bytes);
+
+        let result = self.meme.generate_preview_image(options);
+        handle_generated_result(result)
+    }
+}
+
+/// 转换选项，并将输出格式作为保留选项传给表情，取值在生成时检查
+fn convert_options(
+    options: HashMap<String, OptionValue>,
+    format: Option<String>,
+    quality: Option<i32>,
+    frames: Option<String>,
+    max_output_bytes: Option<i32>,
+) -> HashMap<String, meme::OptionValue> {
+    let mut options = options
+        .into_iter()
+        .map(|(name, value)| (name, value.into()))
+        .collect::<HashMap<_, _>>();
+    if let Some(format) = format {
+        options.insert(
+            meme::FORMAT_OPTION.to_string(),
+            meme::OptionValue::String(format),
+        );
+    }
+    if let Some(quality) = quality {
+        options.insert(
+            meme::QUALITY_OPTION.to_string(),
+            meme::OptionValue::Integer(quality),
+        );
+    }
+    if let Some(frames) = frames {
+        options.insert(
+            meme::FRAMES_OPTION.to_string(),
+            meme::OptionValue::String(frames),
+        );
+    }
+    if let Some(max_output_bytes) = max_output_bytes {
+        options.insert(
+            meme::MAX_OUTPUT_BYTES_OPTION.to_string(),
+            meme::OptionValue::Integer(max_output_bytes),
+        );
+    }
+    options
+}
+
+fn handle_generated_result(
+    result: Result<meme::GeneratedImage, error::Error>,
+) -> GeneratedImageResult {
+    match result {
+        Ok(image) => GeneratedImageResult::Ok(image.into()),
+        Err(error) => GeneratedImageResult::Err(error.into()),
+    }
 }
 
 fn handle_result(result: Result<Vec<u8>, error::Error>) -> MemeResult {
     match result {
         Ok(data) => MemeResult::Ok(data),
-        Err(error) => match error {
-            error::Error::ImageDecodeError(error) => {
-                MemeResult::Err(Error::ImageDecodeError(ImageDecodeError { error }))
-            }
-            error::Error::ImageEncodeError(error) => {
-                MemeResult::Err(Error::ImageEncodeError(ImageEncodeError { error }))
-            }
-            error::Error::ImageAssetMissing(path) => {
-                MemeResult::Err(Error::ImageAssetMissing(ImageAssetMissing { path }))
-            }
-            error::Error::DeserializeError(error) => {
-                MemeResult::Err(Error::DeserializeError(DeserializeError { error }))
-            }
-            error::Error::ImageNumberMismatch(min, max, actual) => {
-                MemeResult::Err(Error::ImageNumberMismatch(ImageNumberMismatch {
-                    min,
-                    max,
-                    actual,
-                }))
-            }
-            error::Error::TextNumberMismatch(min, max, actual) => {
-                MemeResult::Err(Error::TextNumberMismatch(TextNumberMismatch {
-                    min,
-                    max,
-                    actual,
-                }))
-            }
-            error::Error::TextOverLength(text) => {
-                MemeResult::Err(Error::TextOverLength(TextOverLength { text }))
-            }
-            error::Error::MemeFeedback(feedback) => {
-                MemeResult::Err(Error::MemeFeedback(MemeFeedback { feedback }))
-            }
-        },
+        Err(error) => MemeResult::Err(error.into()),
     }
 }
 
@@ -535,7 +861,7 @@ fn get_memes() -> Vec<Meme> {
 }
 
 #[pyfunction]
-fn get_meme_keys() -> Vec<&'static str> {
+fn get_meme_keys() -> Vec<String> {
     meme_generator::get_meme_keys()
 }
 
@@ -544,3 +870,85 @@ fn get_meme_keys() -> Vec<&'static str> {
 fn search_memes(query: &str, include_tags: bool) -> Vec<String> {
     meme_generator::search_memes(query, include_tags)
 }
+
+#[pyclass]
+#[derive(Clone)]
+struct ReloadResult {
+    #[pyo3(get)]
+    added: Vec<String>,
+    #[pyo3(get)]
+    removed: Vec<String>,
+    #[pyo3(get)]
+    total: usize,
+    #[pyo3(get)]
+    restart_required: Vec<String>,
+}
+
+#[derive(IntoPyObject, Clone)]
+enum ReloadMemesResult {
+    Ok(ReloadResult),
+    Err(Error),
+}
+
+#[pyfunction]
+fn reload_memes(py: Python<'_>) -> ReloadMemesResult {
+    match py.allow_threads(meme_generator::reload_memes) {
+        Ok(result) => ReloadMemesResult::Ok(ReloadResult {
+            added: result.added,
+            removed: result.removed,
+            total: result.total,
+            restart_required: result.restart_required,
+        }),
+        Err(error) => ReloadMemesResult::Err(error.into()),
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct ParsedCommand {
+    #[pyo3(get)]
+    key: String,
+    #[pyo3(get)]
+    texts: Vec<String>,
+    #[pyo3(get)]
+    options: HashMap<String, OptionValue>,
+    #[pyo3(get)]
+    images: Vec<String>,
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct CommandParseError {
+    #[pyo3(get)]
+    code: u16,
+    #[pyo3(get)]
+    message: String,
+}
+
+#[derive(IntoPyObject, Clone)]
+enum ParseResult {
+    Ok(ParsedCommand),
+    Err(CommandParseError),
+}
+
+#[pyfunction]
+#[pyo3(signature = (command, args=""))]
+fn parse_command(command: &str, args: &str) -> ParseResult {
+    match meme_generator::parser::parse_command(command, args) {
+        Ok(parsed) => ParseResult::Ok(ParsedCommand {
+            key: parsed.key,
+            texts: parsed.args.texts,
+            options: parsed
+                .args
+                .options
+                .into_iter()
+                .map(|(name, value)| (name, value.into()))
+                .collect(),
+            images: parsed.args.images,
+        }),
+        Err(err) => ParseResult::Err(CommandParseError {
+            code: err.code(),
+            message: err.to_string(),
+        }),
+    }
+}
@@ -1,9 +1,9 @@
 use pyo3::prelude::*;
 
-use meme_generator::{error, tools::image_operations};
+use meme_generator::tools::image_operations;
 
 use crate::{
-    Error, ImageDecodeError,
+    Error,
     tools::{ImageResult, ImagesResult, handle_image_result, handle_images_result},
 };
 
@@ -67,12 +67,7 @@ fn inspect(image: Vec<u8>) -> ImageInfoResult {
             frame_count: info.frame_count,
             average_duration: info.average_duration,
         }),
-        Err(error) => match error {
-            error::Error::ImageDecodeError(error) => {
-                ImageInfoResult::Err(Error::ImageDecodeError(ImageDecodeError { error }))
-            }
-            _ => unreachable!(),
-        },
+        Err(error) => ImageInfoResult::Err(error.into()),
     }
 }
 
@@ -4,7 +4,7 @@ use pyo3::prelude::*;
 
 use meme_generator::{error, tools};
 
-use crate::{Error, ImageDecodeError, ImageEncodeError};
+use crate::Error;
 
 mod image_operations;
 
@@ -36,15 +36,7 @@ enum ImageResult {
 fn handle_image_result(result: Result<Vec<u8>, error::Error>) -> ImageResult {
     match result {
         Ok(data) => ImageResult::Ok(data),
-        Err(error) => match error {
-            error::Error::ImageDecodeError(error) => {
-                ImageResult::Err(Error::ImageDecodeError(ImageDecodeError { error }))
-            }
-            error::Error::ImageEncodeError(error) => {
-                ImageResult::Err(Error::ImageEncodeError(ImageEncodeError { error }))
-            }
-            _ => unreachable!(),
-        },
+        Err(error) => ImageResult::Err(error.into()),
     }
 }
 
@@ -57,15 +49,7 @@ enum ImagesResult {
 fn handle_images_result(result: Result<Vec<Vec<u8>>, error::Error>) -> ImagesResult {
     match result {
         Ok(data) => ImagesResult::Ok(data),
-        Err(error) => match error {
-            error::Error::ImageDecodeError(error) => {
-                ImagesResult::Err(Error::ImageDecodeError(ImageDecodeError { error }))
-            }
-            error::Error::ImageEncodeError(error) => {
-                ImagesResult::Err(Error::ImageEncodeError(ImageEncodeError { error }))
-            }
-            _ => unreachable!(),
-        },
+        Err(error) => ImagesResult::Err(error.into()),
     }
 }
 
@@ -27,6 +27,8 @@ impl Default for Config {
 pub struct ServerConfig {
     pub host: IpAddr,
     pub port: u16,
+    /// 管理接口的令牌，未设置时禁用管理接口
+    pub admin_token: Option<String>,
 }
 
 impl Default for ServerConfig {
@@ -34,6 +36,7 @@ impl Default for ServerConfig {
         ServerConfig {
             host: Ipv4Addr::new(0, 0, 0, 0).into(),
             port: 2233,
+            admin_token: None,
         }
     }
 }
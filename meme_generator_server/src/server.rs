@@ -10,7 +10,7 @@ use axum::{
     Router,
     body::Body,
     extract::{Json, Path, Query},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
     response::{IntoResponse, Response},
     routing::{get, post},
 };
@@ -30,9 +30,11 @@ use tracing::{Level, info};
 use meme_generator::{
     MEME_HOME, VERSION,
     error::Error,
+    format::{Degradation, ImageFormat, OutputFormat, probe},
     get_meme, get_meme_keys, get_memes,
-    meme::{self, OptionValue},
-    search_memes,
+    meme::{self, GeneratedImage, OptionValue},
+    parser::{ParseError, parse_command},
+    reload_memes, search_memes,
 };
 
 use crate::{
@@ -176,10 +178,14 @@ pub(crate) async fn upload_image(Json(image_data): Json<ImageData>) -> Response
 pub(crate) async fn get_image(Path(id): Path<String>) -> Response {
     match get_temp_file(&id).await {
         Ok(data) => {
-            let kind = infer::get(&data).unwrap();
+            // 上传的图片可能是任意格式，无法识别时再按文件头猜测
+            let mime_type = probe(&data)
+                .map(|probe| probe.format.mime_type())
+                .or_else(|| infer::get(&data).map(|kind| kind.mime_type()))
+                .unwrap_or("application/octet-stream");
             Response::builder()
                 .status(StatusCode::OK)
-                .header("Content-Type", kind.mime_type())
+                .header("Content-Type", mime_type)
                 .body(Body::from(data))
                 .unwrap()
         }
@@ -198,6 +204,9 @@ struct MemeRequest {
     images: Vec<Image>,
     texts: Vec<String>,
     options: HashMap<String, OptionValue>,
+    /// 输出格式，包括 `format`、`quality`、`frames` 和 `max_output_bytes` 字段
+    #[serde(flatten)]
+    output: OutputFormat,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -205,6 +214,19 @@ pub(crate) struct ImageResponse {
     image_id: String,
 }
 
+/// 表情生成结果，除图片 id 外还包含图片的格式和帧信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GeneratedImageResponse {
+    image_id: String,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    frame_count: u32,
+    durations: Vec<f32>,
+    seed: Option<i32>,
+    degradations: Vec<Degradation>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ErrorResponse {
     code: u16,
@@ -250,16 +272,60 @@ async fn meme_search(Query(query): Query<SearchQuery>) -> Response {
     Json(keys).into_response()
 }
 
-async fn meme_preview(Path(key): Path<String>) -> Response {
+#[derive(Deserialize)]
+struct ParseRequest {
+    command: String,
+    #[serde(default)]
+    args: String,
+}
+
+async fn meme_parse(Json(payload): Json<ParseRequest>) -> Response {
+    match parse_command(&payload.command, &payload.args) {
+        Ok(parsed) => Json(parsed).into_response(),
+        Err(err) => {
+            let status = match err {
+                ParseError::MemeNotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            let code = err.code();
+            let message = format!("{err}");
+            let response = match err {
+                ParseError::MemeNotFound(command) => ErrorResponse {
+                    code,
+                    message,
+                    data: json!({ "command": command }),
+                },
+                ParseError::UnclosedQuote => ErrorResponse {
+                    code,
+                    message,
+                    data: json!({}),
+                },
+                ParseError::UnknownOption(option) | ParseError::MissingValue(option) => {
+                    ErrorResponse {
+                        code,
+                        message,
+                        data: json!({ "option": option }),
+                    }
+                }
+                ParseError::InvalidOption(err) => handle_error(err),
+            };
+            (status, Json(response)).into_response()
+        }
+    }
+}
+
+async fn meme_preview(Path(key): Path<String>, Query(output): Query<OutputFormat>) -> Response {
     let meme = match get_meme(&key) {
         Some(meme) => meme,
         None => return (StatusCode::NOT_FOUND, "Meme not found").into_response(),
     };
 
-    let result = spawn_blocking(move || meme.generate_preview(HashMap::new()))
+    let mut options = HashMap::new();
+    output.apply_to(&mut options);
+    let result = spawn_blocking(move || meme.generate_preview_image(options))
         .await
         .unwrap();
-    handle_image_result(result).await
+    handle_generated_result(result).await
 }
 
 async fn meme_generate(Path(key): Path<String>, Json(payload): Json<MemeRequest>) -> Response {
@@ -276,12 +342,31 @@ async fn meme_generate(Path(key): Path<String>, Json(payload): Json<MemeRequest>
         }
     }
     let texts = payload.texts;
-    let options = payload.options;
+    let mut options = payload.options;
+    payload.output.apply_to(&mut options);
 
-    let result = spawn_blocking(move || meme.generate(images, texts, options))
+    let result = spawn_blocking(move || meme.generate_image(images, texts, options))
         .await
         .unwrap();
-    handle_image_result(result).await
+    handle_generated_result(result).await
+}
+
+async fn admin_reload(headers: HeaderMap) -> Response {
+    let Some(admin_token) = &CONFIG.server.admin_token else {
+        return (StatusCode::FORBIDDEN, "Admin endpoints are disabled").into_response();
+    };
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if token != Some(admin_token.as_str()) {
+        return (StatusCode::UNAUTHORIZED, "Invalid admin token").into_response();
+    }
+
+    match spawn_blocking(reload_memes).await.unwrap() {
+        Ok(result) => Json(result).into_response(),
+        Err(error) => handle_error(error).into_response(),
+    }
 }
 
 pub(crate) async fn handle_image_result(result: Result<Vec<u8>, Error>) -> Response {
@@ -298,6 +383,29 @@ pub(crate) async fn handle_image_result(result: Result<Vec<u8>, Error>) -> Respo
     }
 }
 
+async fn handle_generated_result(result: Result<GeneratedImage, Error>) -> Response {
+    match result {
+        Ok(image) => {
+            let id = match create_temp_file(image.data).await {
+                Ok(id) => id,
+                Err(err) => return handle_server_error(err).into_response(),
+            };
+            let response = GeneratedImageResponse {
+                image_id: id,
+                format: image.format,
+                width: image.width,
+                height: image.height,
+                frame_count: image.frame_count,
+                durations: image.durations,
+                seed: image.seed,
+                degradations: image.degradations,
+            };
+            Json(response).into_response()
+        }
+        Err(error) => handle_error(error).into_response(),
+    }
+}
+
 pub(crate) fn handle_server_error(error: ServerError) -> ErrorResponse {
     let message = format!("{error}");
     match error {
@@ -316,48 +424,45 @@ pub(crate) fn handle_server_error(error: ServerError) -> ErrorResponse {
 }
 
 pub(crate) fn handle_error(error: Error) -> ErrorResponse {
+    let code = error.code();
     let message = format!("{error}");
-    match error {
-        Error::ImageDecodeError(err) => ErrorResponse {
-            code: 510,
-            message,
-            data: json!({ "error": err }),
-        },
-        Error::ImageEncodeError(err) => ErrorResponse {
-            code: 520,
-            message,
-            data: json!({ "error": err }),
-        },
-        Error::ImageAssetMissing(path) => ErrorResponse {
-            code: 530,
-            message,
-            data: json!({ "path": path }),
-        },
-        Error::DeserializeError(err) => ErrorResponse {
-            code: 540,
-            message,
-            data: json!({ "error": err }),
-        },
-        Error::ImageNumberMismatch(min, max, actual) => ErrorResponse {
-            code: 550,
-            message,
-            data: json!({ "min": min, "max": max, "actual": actual }),
-        },
-        Error::TextNumberMismatch(min, max, actual) => ErrorResponse {
-            code: 551,
-            message,
-            data: json!({ "min": min, "max": max, "actual": actual }),
-        },
-        Error::TextOverLength(text) => ErrorResponse {
-            code: 560,
-            message,
-            data: json!({ "text": text }),
-        },
-        Error::MemeFeedback(feedback) => ErrorResponse {
-            code: 570,
-            message,
-            data: json!({ "feedback": feedback }),
-        },
+    let data = match error {
+        Error::ImageDecodeError(err) => json!({ "error": err }),
+        Error::InvalidImage { index, name, error } => {
+            json!({ "index": index, "name": name, "error": error })
+        }
+        Error::ImageTooLarge { index, name, error } => {
+            json!({ "index": index, "name": name, "error": error })
+        }
+        Error::ImageEncodeError(err) => json!({ "error": err }),
+        Error::ImageAssetMissing(path) => json!({ "path": path }),
+        Error::DeserializeError(err) => json!({ "error": err }),
+        Error::InvalidOptionValue {
+            name,
+            value,
+            expected,
+        } => json!({ "name": name, "value": value, "expected": expected }),
+        Error::ImageNumberMismatch(min, max, actual) => {
+            json!({ "min": min, "max": max, "actual": actual })
+        }
+        Error::TextNumberMismatch(min, max, actual) => {
+            json!({ "min": min, "max": max, "actual": actual })
+        }
+        Error::TextOverLength { text, index } => json!({ "text": text, "index": index }),
+        Error::ImageNameOverLength { name, index } => json!({ "name": name, "index": index }),
+        Error::TextGlyphMissing { text, index, chars } => {
+            json!({ "text": text, "index": index, "chars": chars })
+        }
+        Error::InvalidBBCode { text, index, error } => {
+            json!({ "text": text, "index": index, "error": error })
+        }
+        Error::InvalidTextPath(err) => json!({ "error": err }),
+        Error::MemeFeedback(feedback) => json!({ "feedback": feedback }),
+    };
+    ErrorResponse {
+        code,
+        message,
+        data,
     }
 }
 
@@ -370,9 +475,11 @@ pub async fn run_server(host: Option<IpAddr>, port: Option<u16>) {
         .route("/meme/keys", get(meme_keys))
         .route("/meme/infos", get(meme_infos))
         .route("/meme/search", get(meme_search))
+        .route("/meme/parse", post(meme_parse))
         .route("/memes/:key/info", get(meme_info))
         .route("/memes/:key/preview", get(meme_preview))
         .route("/memes/:key", post(meme_generate))
+        .route("/admin/reload", post(admin_reload))
         .route("/tools/render_list", post(render_list))
         .route("/tools/render_statistics", post(render_statistics))
         .route("/tools/image_operations/inspect", post(inspect))
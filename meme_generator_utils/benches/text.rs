@@ -0,0 +1,74 @@
+//! 多个线程同时生成文字时的吞吐量
+//!
+//! 运行：`cargo bench -p meme_generator_utils --bench text`
+
+use std::{
+    hint::black_box,
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+use meme_generator_utils::text::Text2Image;
+
+const TEXTS: &[&str] = &[
+    "我就是饿死，死外边，从这里跳下去，不会吃你们一点东西",
+    "The quick brown fox jumps over the lazy dog",
+    "[b]有钱[/b]真的[color=#ff0000]了不起[/color]",
+];
+
+/// 每个线程生成的文字数
+const ITERATIONS: usize = 300;
+
+fn render(index: usize) {
+    let text = TEXTS[index % TEXTS.len()];
+    let text2image = if index % 2 == 0 {
+        Text2Image::from_text(text, 32.0, None)
+    } else {
+        Text2Image::from_bbcode_text(text, 32.0, None).unwrap()
+    };
+    black_box(text2image.longest_line());
+}
+
+/// 各线程先生成一次文字以创建各自的字体集合，再同时开始计时
+fn run(threads: usize) -> Duration {
+    let barrier = Barrier::new(threads);
+    thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    render(0);
+                    barrier.wait();
+                    let start = Instant::now();
+                    for index in 0..ITERATIONS {
+                        render(index);
+                    }
+                    start.elapsed()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .max()
+            .unwrap()
+    })
+}
+
+fn main() {
+    let max_threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+    let mut threads = 1;
+    let mut baseline = None;
+    println!("threads  elapsed     texts/s   speedup");
+    while threads <= max_threads {
+        let elapsed = run(threads);
+        let throughput = (threads * ITERATIONS) as f64 / elapsed.as_secs_f64();
+        let baseline = *baseline.get_or_insert(throughput);
+        println!(
+            "{threads:>7}  {:>7.1}ms  {throughput:>8.0}  {:>7.2}x",
+            elapsed.as_secs_f64() * 1000.0,
+            throughput / baseline
+        );
+        threads *= 2;
+    }
+}
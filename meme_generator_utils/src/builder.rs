@@ -3,14 +3,26 @@ use std::collections::{HashMap, HashSet};
 use chrono::{DateTime, Local};
 use serde::Deserialize;
 use serde_json::{Number, Value};
-use skia_safe::{Codec, Data, Image};
+use skia_safe::Image;
+use tracing::warn;
 
 use meme_generator_core::{
     error::Error,
-    meme::{self, Meme, MemeInfo, MemeOption, MemeParams, MemeShortcut, OptionValue},
+    format::OutputFormat,
+    meme::{
+        self, GeneratedImage, Meme, MemeInfo, MemeOption, MemeParams, MemeShortcut, OptionValue,
+        RESERVED_OPTIONS, SEED_OPTION,
+    },
 };
 
-use crate::{decoder::CodecExt, encoder::encode_png, tools::grid_pattern_image};
+use crate::{
+    config::CONFIG,
+    decoder::{CodecExt, DecodeBudget, FrameDecoder, InputError},
+    encoder::{capture_encoded, convert_output, encode_png, fit_output_size, with_output_format},
+    random::{random_seed, with_seed},
+    text::check_glyph_coverage,
+    tools::grid_pattern_image,
+};
 
 pub use meme_options_derive::MemeOptions;
 
@@ -60,21 +72,39 @@ pub mod shortcut_setters {
 pub struct InputImage<'a> {
     pub name: String,
     pub image: Image,
-    pub(crate) codec: Codec<'a>,
+    pub(crate) codec: FrameDecoder<'a>,
 }
 
 impl<'a> InputImage<'a> {
-    pub fn from(input: &meme::Image) -> Result<InputImage<'static>, Error> {
-        let data = Data::new_copy(&input.data);
-        let mut codec = Codec::from_data(data)
-            .ok_or(Error::ImageDecodeError("Skia decode error".to_string()))?;
-        let image = codec.first_frame()?;
+    /// 解码第 `index` 张输入图片，解码后的字节数从 `budget` 中扣除
+    ///
+    /// 无法解码时返回 [`Error::InvalidImage`]，
+    /// 超出 [`DecoderConfig`](crate::config::DecoderConfig) 中的限制时返回 [`Error::ImageTooLarge`]
+    pub fn from(
+        input: &meme::Image,
+        index: usize,
+        budget: &mut DecodeBudget,
+    ) -> Result<InputImage<'static>, Error> {
+        let into_error = |err: InputError| err.into_error(index, &input.name);
+        let mut codec = FrameDecoder::from_input(&input.data, budget).map_err(into_error)?;
+        let image = codec.first_frame().map_err(|err| into_error(err.into()))?;
         Ok(InputImage {
             name: input.name.clone(),
             image,
             codec,
         })
     }
+
+    /// 表情自带的图片资源，不受输入限制
+    pub fn from_asset(name: impl Into<String>, data: &[u8]) -> Result<InputImage<'static>, Error> {
+        let mut codec = FrameDecoder::from_data(data)?;
+        let image = codec.first_frame()?;
+        Ok(InputImage {
+            name: name.into(),
+            image,
+            codec,
+        })
+    }
 }
 
 type MemeFunction<T> = fn(Vec<InputImage>, Vec<String>, T) -> Result<Vec<u8>, Error>;
@@ -178,6 +208,7 @@ where
     fn info(&self) -> MemeInfo {
         MemeInfo {
             key: self.key.clone(),
+            pack: String::new(),
             params: MemeParams {
                 min_images: self.min_images,
                 max_images: self.max_images,
@@ -200,28 +231,30 @@ where
         texts: Vec<String>,
         options: HashMap<String, OptionValue>,
     ) -> Result<Vec<u8>, Error> {
+        self.generate_image(images, texts, options).map(Into::into)
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        self.generate_preview_image(options).map(Into::into)
+    }
+
+    fn generate_image(
+        &self,
+        images: Vec<meme::Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
         let info = self.info();
-        if images.len() < info.params.min_images as usize
-            || images.len() > info.params.max_images as usize
-        {
-            return Err(Error::ImageNumberMismatch(
-                info.params.min_images,
-                info.params.max_images,
-                images.len() as u8,
-            ));
-        }
-        if texts.len() < info.params.min_texts as usize
-            || texts.len() > info.params.max_texts as usize
-        {
-            return Err(Error::TextNumberMismatch(
-                info.params.min_texts,
-                info.params.max_texts,
-                texts.len() as u8,
-            ));
-        }
+        let input_images = decode_inputs(&info.params, &images, &texts, &options)?;
+        let seed = match options.get(SEED_OPTION) {
+            Some(OptionValue::Integer(seed)) => *seed,
+            _ => random_seed(),
+        };
+        let output = OutputFormat::from_options(&options)?;
 
         let options = options
             .iter()
+            .filter(|(key, _)| !RESERVED_OPTIONS.contains(&key.as_str()))
             .map(|(key, value)| {
                 let value = match value {
                     OptionValue::Boolean(value) => Value::Bool(*value),
@@ -237,46 +270,161 @@ where
 
         let options = serde_json::from_value(Value::Object(options))
             .map_err(|err| Error::DeserializeError(err.to_string()))?;
-        let images = images
+        let names = images
             .iter()
-            .map(|image| InputImage::from(image))
-            .collect::<Result<Vec<InputImage>, Error>>()?;
-        (self.function)(images, texts, options)
+            .map(|image| image.name.clone())
+            .collect::<Vec<_>>();
+        with_output_format(output, || {
+            let ((result, used_rng), encoded) = capture_encoded(|| {
+                with_seed(seed, || {
+                    (self.function)(input_images, texts.clone(), options)
+                        .map_err(|err| locate_text_error(err, &texts, &names))
+                        .and_then(convert_output)
+                })
+            });
+            let mut image = fit_output_size(result?, encoded)?;
+            // 只有使用了随机数的表情才在结果中记录种子
+            image.seed = used_rng.then_some(seed);
+            Ok(image)
+        })
     }
 
-    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
-        let mut images = Vec::new();
-        if self.min_images > 0 {
-            let image = encode_png(grid_pattern_image())?;
-            for i in 0..self.min_images {
-                let name = if self.min_images == 1 {
-                    "{name}".to_string()
-                } else {
-                    format!("{{name{}}}", i + 1)
-                };
-                images.push(meme::Image {
-                    name: name,
-                    data: image.clone(),
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        let (images, texts) = preview_inputs(&self.info().params)?;
+        self.generate_image(images, texts, options)
+    }
+}
+
+/// 检查图片、文字数量和选项是否符合表情参数，并解码图片
+pub(crate) fn decode_inputs(
+    params: &MemeParams,
+    images: &[meme::Image],
+    texts: &[String],
+    options: &HashMap<String, OptionValue>,
+) -> Result<Vec<InputImage<'static>>, Error> {
+    if images.len() < params.min_images as usize || images.len() > params.max_images as usize {
+        return Err(Error::ImageNumberMismatch(
+            params.min_images,
+            params.max_images,
+            images.len() as u8,
+        ));
+    }
+    if texts.len() < params.min_texts as usize || texts.len() > params.max_texts as usize {
+        return Err(Error::TextNumberMismatch(
+            params.min_texts,
+            params.max_texts,
+            texts.len() as u8,
+        ));
+    }
+    params.validate_options(options)?;
+    check_texts(texts)?;
+
+    let mut budget = DecodeBudget::default();
+    images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| InputImage::from(image, index, &mut budget))
+        .collect()
+}
+
+/// 检查文字中的字符是否有字形，严格模式下有缺失字形的字符时报错
+///
+/// 缺失的字符在所有字体中都没有字形，与表情使用的字体无关；
+/// 回退字体的使用情况取决于表情实际使用的字体，在排版文字时检查
+fn check_texts(texts: &[String]) -> Result<(), Error> {
+    for (index, text) in texts.iter().enumerate() {
+        let coverage = check_glyph_coverage(text, &[]);
+        if !coverage.missing.is_empty() {
+            let chars = coverage.missing.iter().collect::<String>();
+            if CONFIG.font.strict_glyph_coverage {
+                return Err(Error::TextGlyphMissing {
+                    text: text.clone(),
+                    index,
+                    chars,
                 });
             }
+            warn!("Text {index} contains characters without glyphs: {chars}");
         }
-        let texts = if self.default_texts.len() >= self.min_texts as usize
-            && self.default_texts.len() <= self.max_texts as usize
-        {
-            self.default_texts.clone()
-        } else {
-            let mut texts = Vec::new();
-            for i in 0..self.min_texts {
-                let text = if self.min_texts == 1 {
-                    "{text}".to_string()
-                } else {
-                    format!("{{text{}}}", i + 1)
-                };
-                texts.push(text);
+    }
+    Ok(())
+}
+
+/// 生成预览用的图片和文字
+pub(crate) fn preview_inputs(
+    params: &MemeParams,
+) -> Result<(Vec<meme::Image>, Vec<String>), Error> {
+    let mut images = Vec::new();
+    if params.min_images > 0 {
+        let image = encode_png(grid_pattern_image())?;
+        for i in 0..params.min_images {
+            let name = if params.min_images == 1 {
+                "{name}".to_string()
+            } else {
+                format!("{{name{}}}", i + 1)
+            };
+            images.push(meme::Image {
+                name: name,
+                data: image.clone(),
+            });
+        }
+    }
+    let texts = if params.default_texts.len() >= params.min_texts as usize
+        && params.default_texts.len() <= params.max_texts as usize
+    {
+        params.default_texts.clone()
+    } else {
+        let mut texts = Vec::new();
+        for i in 0..params.min_texts {
+            let text = if params.min_texts == 1 {
+                "{text}".to_string()
+            } else {
+                format!("{{text{}}}", i + 1)
+            };
+            texts.push(text);
+        }
+        texts
+    };
+    Ok((images, texts))
+}
+
+/// 在 `items` 中只出现一次的 `text` 的位置，重复出现时无法确定对应哪一个
+fn unique_position(items: &[String], text: &str) -> Option<usize> {
+    let mut positions = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| *item == text)
+        .map(|(index, _)| index);
+    match (positions.next(), positions.next()) {
+        (Some(index), None) => Some(index),
+        _ => None,
+    }
+}
+
+/// 为未指明位置的文字错误补充位置
+///
+/// 表情函数知道位置时应直接使用 [`Error::text_over_length_at`] 等方法；
+/// 这里只处理原样绘制且唯一匹配的文字，经过格式化或重复的文字不做猜测
+pub(crate) fn locate_text_error(err: Error, texts: &[String], names: &[String]) -> Error {
+    match err {
+        Error::InvalidBBCode {
+            text,
+            index: None,
+            error,
+        } => {
+            let index = unique_position(texts, &text);
+            Error::InvalidBBCode { text, index, error }
+        }
+        Error::TextOverLength { text, index: None } => {
+            match (unique_position(texts, &text), unique_position(names, &text)) {
+                (Some(index), None) => Error::text_over_length_at(index, text),
+                (None, Some(index)) => Error::image_name_over_length(index, text),
+                _ => Error::TextOverLength { text, index: None },
             }
-            texts
-        };
-        self.generate(images, texts, options)
+        }
+        err => err,
     }
 }
 
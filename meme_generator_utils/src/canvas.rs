@@ -1,8 +1,11 @@
-use skia_safe::{Canvas, Point, Rect, scalar};
+use skia_safe::{Canvas, ContourMeasureIter, Path, Point, Rect, scalar};
 
 use meme_generator_core::error::Error;
 
-use crate::text::{Text2Image, TextParams};
+use crate::{
+    text::{PathTextParams, Text2Image, TextParams},
+    text_path::PathText,
+};
 
 pub trait CanvasExt {
     fn draw_text(
@@ -36,7 +39,7 @@ pub trait CanvasExt {
         text: impl Into<String>,
         font_size: scalar,
         text_params: impl Into<Option<TextParams>>,
-    );
+    ) -> Result<(), Error>;
 
     fn draw_bbcode_text_area(
         &self,
@@ -54,23 +57,63 @@ pub trait CanvasExt {
         max_font_size: scalar,
         text_params: impl Into<Option<TextParams>>,
     ) -> Result<(), Error>;
+
+    /// 沿路径的第一段轮廓绘制文字，文字的基线落在路径上
+    ///
+    /// 只使用第一段轮廓，之后的轮廓被忽略；路径没有轮廓时返回 [`Error::InvalidTextPath`]
+    fn draw_text_on_path(
+        &self,
+        path: &Path,
+        text: impl Into<String>,
+        font_size: scalar,
+        path_params: PathTextParams,
+        text_params: impl Into<Option<TextParams>>,
+    ) -> Result<(), Error>;
+
+    /// 沿路径的第一段轮廓绘制文字，文字过长时缩小字号直到放得下
+    fn draw_text_on_path_auto_font_size(
+        &self,
+        path: &Path,
+        text: impl Into<String>,
+        min_font_size: scalar,
+        max_font_size: scalar,
+        path_params: PathTextParams,
+        text_params: impl Into<Option<TextParams>>,
+    ) -> Result<(), Error>;
 }
 
-fn draw_text(
-    canvas: &Canvas,
-    origin: impl Into<Point>,
+fn new_text2image(
     text: impl Into<String>,
     font_size: scalar,
     text_params: impl Into<Option<TextParams>>,
     use_bbcode: bool,
-) {
-    let origin: Point = origin.into();
-    let text2image = if use_bbcode {
+) -> Result<Text2Image, Error> {
+    if use_bbcode {
         Text2Image::from_bbcode_text(text, font_size, text_params)
     } else {
-        Text2Image::from_text(text, font_size, text_params)
-    };
-    text2image.draw_on_canvas(canvas, origin);
+        Ok(Text2Image::from_text(text, font_size, text_params))
+    }
+}
+
+/// 在 `rect` 中排版文字，放得下时返回绘制的位置
+///
+/// 横排时按宽度换行并竖直居中；竖排时按高度换列并水平居中
+fn fit_in_rect(text2image: &mut Text2Image, rect: &Rect) -> Option<Point> {
+    if text2image.is_vertical() {
+        text2image.layout(rect.height());
+        if text2image.longest_line() > rect.width() || text2image.height() > rect.height() {
+            return None;
+        }
+        let left = rect.left() + (rect.width() - text2image.longest_line()) / 2.0;
+        Some(Point::new(left, rect.top()))
+    } else {
+        text2image.layout(rect.width());
+        if text2image.height() > rect.height() {
+            return None;
+        }
+        let top = rect.top() + (rect.height() - text2image.height()) / 2.0;
+        Some(Point::new(rect.left(), top))
+    }
 }
 
 fn draw_text_area(
@@ -83,17 +126,11 @@ fn draw_text_area(
 ) -> Result<(), Error> {
     let rect: Rect = rect.into();
     let text: String = text.into();
-    let mut text2image = if use_bbcode {
-        Text2Image::from_bbcode_text(text.clone(), font_size, text_params)
-    } else {
-        Text2Image::from_text(text.clone(), font_size, text_params)
+    let mut text2image = new_text2image(text.clone(), font_size, text_params, use_bbcode)?;
+    let Some(origin) = fit_in_rect(&mut text2image, &rect) else {
+        return Err(Error::text_over_length(text));
     };
-    text2image.layout(rect.width());
-    if text2image.height() > rect.height() {
-        return Err(Error::TextOverLength(text));
-    }
-    let top = rect.top() + (rect.height() - text2image.height()) / 2.0;
-    text2image.draw_on_canvas(canvas, (rect.left(), top));
+    text2image.draw_on_canvas(canvas, origin);
     Ok(())
 }
 
@@ -111,15 +148,9 @@ fn draw_text_area_auto_font_size(
     let mut text_params: TextParams = text_params.into().unwrap_or_default();
     let mut font_size = max_font_size;
     while font_size >= min_font_size {
-        let mut text2image = if use_bbcode {
-            Text2Image::from_bbcode_text(&text, font_size, text_params.clone())
-        } else {
-            Text2Image::from_text(&text, font_size, text_params.clone())
-        };
-        text2image.layout(rect.width());
-        if text2image.height() <= rect.height() {
-            let top = rect.top() + (rect.height() - text2image.height()) / 2.0;
-            text2image.draw_on_canvas(canvas, (rect.left(), top));
+        let mut text2image = new_text2image(&text, font_size, text_params.clone(), use_bbcode)?;
+        if let Some(origin) = fit_in_rect(&mut text2image, &rect) {
+            text2image.draw_on_canvas(canvas, origin);
             return Ok(());
         }
         if let Some(stroke_paint) = &mut text_params.stroke_paint {
@@ -129,7 +160,39 @@ fn draw_text_area_auto_font_size(
         }
         font_size -= 1.0;
     }
-    Err(Error::TextOverLength(text))
+    Err(Error::text_over_length(text))
+}
+
+fn draw_text_on_path(
+    canvas: &Canvas,
+    path: &Path,
+    text: impl Into<String>,
+    min_font_size: scalar,
+    max_font_size: scalar,
+    path_params: PathTextParams,
+    text_params: impl Into<Option<TextParams>>,
+) -> Result<(), Error> {
+    let text: String = text.into();
+    let mut text_params: TextParams = text_params.into().unwrap_or_default();
+    let Some(contour) = ContourMeasureIter::new(path, false, None).next() else {
+        return Err(Error::InvalidTextPath(
+            "the path has no contour".to_string(),
+        ));
+    };
+    let mut font_size = max_font_size;
+    while font_size >= min_font_size {
+        let path_text = PathText::new(&text, font_size, &text_params, path_params);
+        if path_text.draw_on_contour(canvas, &contour) {
+            return Ok(());
+        }
+        if let Some(stroke_paint) = &mut text_params.stroke_paint {
+            let mut stroke_width = stroke_paint.stroke_width();
+            stroke_width -= 1.0 * stroke_width / font_size;
+            stroke_paint.set_stroke_width(stroke_width);
+        }
+        font_size -= 1.0;
+    }
+    Err(Error::text_over_length(text))
 }
 
 impl CanvasExt for Canvas {
@@ -140,7 +203,7 @@ impl CanvasExt for Canvas {
         font_size: scalar,
         text_params: impl Into<Option<TextParams>>,
     ) {
-        draw_text(self, origin, text, font_size, text_params, false);
+        Text2Image::from_text(text, font_size, text_params).draw_on_canvas(self, origin);
     }
 
     fn draw_text_area(
@@ -178,8 +241,9 @@ impl CanvasExt for Canvas {
         text: impl Into<String>,
         font_size: scalar,
         text_params: impl Into<Option<TextParams>>,
-    ) {
-        draw_text(self, origin, text, font_size, text_params, true);
+    ) -> Result<(), Error> {
+        Text2Image::from_bbcode_text(text, font_size, text_params)?.draw_on_canvas(self, origin);
+        Ok(())
     }
 
     fn draw_bbcode_text_area(
@@ -210,4 +274,43 @@ impl CanvasExt for Canvas {
             true,
         )
     }
+
+    fn draw_text_on_path(
+        &self,
+        path: &Path,
+        text: impl Into<String>,
+        font_size: scalar,
+        path_params: PathTextParams,
+        text_params: impl Into<Option<TextParams>>,
+    ) -> Result<(), Error> {
+        draw_text_on_path(
+            self,
+            path,
+            text,
+            font_size,
+            font_size,
+            path_params,
+            text_params,
+        )
+    }
+
+    fn draw_text_on_path_auto_font_size(
+        &self,
+        path: &Path,
+        text: impl Into<String>,
+        min_font_size: scalar,
+        max_font_size: scalar,
+        path_params: PathTextParams,
+        text_params: impl Into<Option<TextParams>>,
+    ) -> Result<(), Error> {
+        draw_text_on_path(
+            self,
+            path,
+            text,
+            min_font_size,
+            max_font_size,
+            path_params,
+            text_params,
+        )
+    }
 }
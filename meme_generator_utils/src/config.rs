@@ -9,6 +9,7 @@ use meme_generator_core::config::{MEME_HOME, read_config_file};
 #[serde(default)]
 pub struct Config {
     pub api: ApiConfig,
+    pub decoder: DecoderConfig,
     pub encoder: EncoderConfig,
     pub font: FontConfig,
 }
@@ -17,6 +18,7 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             api: ApiConfig::default(),
+            decoder: DecoderConfig::default(),
             encoder: EncoderConfig::default(),
             font: FontConfig::default(),
         }
@@ -39,11 +41,50 @@ impl Default for ApiConfig {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DecoderConfig {
+    /// 输入图片的最大像素数
+    pub max_input_pixels: u64,
+    /// 输入动图的最大帧数
+    pub max_input_frames: usize,
+    /// 一次生成中所有输入图片的所有帧解码后的最大字节数
+    pub max_decoded_bytes: u64,
+    /// 超出限制时缩小静图、抽取动图的部分帧，而不是拒绝该图片
+    pub downsample_oversized: bool,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        DecoderConfig {
+            max_input_pixels: 40_000_000,
+            max_input_frames: 1000,
+            max_decoded_bytes: 1 << 30,
+            downsample_oversized: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct EncoderConfig {
     pub gif_max_frames: u16,
     pub gif_encode_speed: u8,
+    /// 所有帧共用一个调色板，颜色在帧间保持一致，但颜色丰富的动图会损失细节
+    pub gif_global_palette: bool,
+    /// 使用 Floyd–Steinberg 抖动减少色带，会增大文件体积
+    pub gif_dithering: bool,
+    /// 只编码与上一帧不同的区域，未变化的像素使用透明色
+    pub gif_frame_delta: bool,
+    /// webp 的压缩质量，0 到 100，无损压缩时表示压缩力度
+    pub webp_quality: f32,
+    pub webp_lossless: bool,
+    /// jpeg 的压缩质量，1 到 100
+    pub jpeg_quality: u8,
+    /// 生成结果的最大字节数，超出时降低颜色数、帧数或分辨率重新编码
+    pub max_output_bytes: Option<usize>,
+    /// 并行渲染动图时使用的线程数，默认为 1 即不并行，为 0 时使用 CPU 核数
+    pub render_threads: usize,
 }
 
 impl Default for EncoderConfig {
@@ -51,6 +92,14 @@ impl Default for EncoderConfig {
         EncoderConfig {
             gif_max_frames: 200,
             gif_encode_speed: 10,
+            gif_global_palette: false,
+            gif_dithering: false,
+            gif_frame_delta: false,
+            webp_quality: 90.0,
+            webp_lossless: false,
+            jpeg_quality: 90,
+            max_output_bytes: None,
+            render_threads: 1,
         }
     }
 }
@@ -60,6 +109,8 @@ impl Default for EncoderConfig {
 pub struct FontConfig {
     pub use_local_fonts: bool,
     pub default_font_families: Vec<String>,
+    /// 文字中有所有字体都没有字形的字符时报错，而不是绘制为方框
+    pub strict_glyph_coverage: bool,
 }
 
 impl Default for FontConfig {
@@ -70,6 +121,7 @@ impl Default for FontConfig {
                 .into_iter()
                 .map(|s| s.to_string())
                 .collect(),
+            strict_glyph_coverage: false,
         }
     }
 }
@@ -86,6 +138,7 @@ fn parse_config() -> Config {
     }
 }
 
+/// 只在首次使用时读取，`reload_memes` 不会重新加载，修改后需要重启
 pub static CONFIG: LazyLock<Config> = LazyLock::new(parse_config);
 
 pub static FONTS_DIR: LazyLock<PathBuf> = LazyLock::new(|| match option_env!("MEME_FONTS_DIR") {
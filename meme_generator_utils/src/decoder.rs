@@ -1,32 +1,236 @@
-use skia_safe::{AlphaType, Codec, ColorType, Image, ImageInfo, codec};
+use std::{
+    collections::VecDeque,
+    ops::{Deref, DerefMut},
+};
+
+use skia_safe::{
+    AlphaType, Codec, ColorType, Data, ISize, Image, ImageInfo,
+    codec::{self, ScanlineOrder},
+    images,
+};
 
 use meme_generator_core::error::Error;
 
+use crate::config::{CONFIG, DecoderConfig};
+
 pub trait CodecExt {
     fn is_multi_frame(&mut self) -> bool;
 
     fn get_average_duration(&mut self) -> Result<f32, Error>;
 
+    /// 每一帧的时长，单位为秒
+    ///
+    /// 与浏览器一致，不超过 0.01 秒的帧间隔按 0.1 秒处理
+    fn get_durations(&mut self) -> Result<Vec<f32>, Error>;
+
     fn first_frame(&mut self) -> Result<Image, Error>;
 
     fn get_frame(&mut self, index: usize) -> Result<Image, Error>;
 }
 
+/// 最多缓存的已合成帧数
+const FRAME_CACHE_SIZE: usize = 4;
+
+/// 已合成的帧的像素，最近使用的排在前面
+#[derive(Default)]
+struct FrameCache {
+    frames: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl FrameCache {
+    fn contains(&self, index: usize) -> bool {
+        self.frames.iter().any(|(i, _)| *i == index)
+    }
+
+    fn get(&mut self, index: usize) -> Option<&Vec<u8>> {
+        let position = self.frames.iter().position(|(i, _)| *i == index)?;
+        let frame = self.frames.remove(position)?;
+        self.frames.push_front(frame);
+        self.frames.front().map(|(_, pixels)| pixels)
+    }
+
+    fn insert(&mut self, index: usize, pixels: Vec<u8>) {
+        self.frames.push_front((index, pixels));
+        self.frames.truncate(FRAME_CACHE_SIZE);
+    }
+}
+
+fn decode_error(err: impl std::fmt::Debug) -> Error {
+    Error::ImageDecodeError(format!("Skia decode error: {err:?}"))
+}
+
+/// 解码输入图片时的错误，由知道图片位置的调用方转换为 [`Error`]
+#[derive(Debug)]
+pub enum InputError {
+    /// 无法解码
+    Decode(String),
+    /// 尺寸、帧数或解码后的大小超出限制
+    TooLarge(String),
+}
+
+impl InputError {
+    /// 转换为第 `index` 张图片的错误，`name` 为图片名
+    pub fn into_error(self, index: usize, name: impl Into<String>) -> Error {
+        let name = name.into();
+        match self {
+            InputError::Decode(error) => Error::InvalidImage { index, name, error },
+            InputError::TooLarge(error) => Error::ImageTooLarge { index, name, error },
+        }
+    }
+}
+
+impl From<Error> for InputError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::ImageDecodeError(error) => InputError::Decode(error),
+            err => InputError::Decode(err.to_string()),
+        }
+    }
+}
+
+/// 一次生成中所有输入图片共用的解码后字节数限制
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeBudget {
+    remaining: u64,
+}
+
+impl Default for DecodeBudget {
+    fn default() -> Self {
+        DecodeBudget {
+            remaining: CONFIG.decoder.max_decoded_bytes,
+        }
+    }
+}
+
+/// 解码第 `index` 帧
+///
+/// 只记录与前一帧差异的帧需要在所依赖的帧上绘制，依次合成所依赖的帧，并由 skia 按照依赖帧的处置方式清除对应区域
+fn decode_frame(codec: &mut Codec, index: usize, cache: &mut FrameCache) -> Result<Image, Error> {
+    let image_info = ImageInfo::new(
+        codec.dimensions(),
+        ColorType::RGBA8888,
+        AlphaType::Unpremul,
+        None,
+    );
+    let row_bytes = image_info.min_row_bytes();
+
+    // 向前查找依赖的帧，直到已缓存或不依赖其他帧的帧
+    let mut chain = vec![index];
+    loop {
+        let last = *chain.last().unwrap();
+        if cache.contains(last) {
+            break;
+        }
+        let required_frame = codec
+            .get_frame_info(last)
+            .and_then(|frame_info| usize::try_from(frame_info.required_frame).ok());
+        match required_frame {
+            Some(required_frame) => chain.push(required_frame),
+            None => break,
+        }
+    }
+
+    let mut pixels = Vec::new();
+    let mut prior_frame = None;
+    for &frame_index in chain.iter().rev() {
+        if let Some(cached) = cache.get(frame_index) {
+            pixels = cached.clone();
+            prior_frame = Some(frame_index);
+            continue;
+        }
+        if prior_frame.is_none() {
+            pixels = vec![0; image_info.compute_min_byte_size()];
+        }
+        let options = codec::Options {
+            zero_initialized: match prior_frame {
+                Some(_) => codec::ZeroInitialized::No,
+                None => codec::ZeroInitialized::Yes,
+            },
+            subset: None,
+            frame_index,
+            prior_frame,
+        };
+        match codec.get_pixels_with_options(&image_info, &mut pixels, row_bytes, Some(&options)) {
+            codec::Result::Success | codec::Result::IncompleteInput => {}
+            err => return Err(decode_error(err)),
+        }
+        cache.insert(frame_index, pixels.clone());
+        prior_frame = Some(frame_index);
+    }
+
+    images::raster_from_data(&image_info, Data::new_copy(&pixels), row_bytes)
+        .ok_or_else(|| decode_error("invalid pixels"))
+}
+
+/// 逐行解码静图并按最近邻采样缩小到 `size`，不需要完整解码原图
+///
+/// 支持缩放解码的格式（如 jpeg、webp）先以最接近的较大尺寸解码
+fn decode_downscaled(codec: &mut Codec, size: ISize) -> Result<Image, InputError> {
+    let dimensions = codec.dimensions();
+    let source = codec.get_scaled_dimensions(size.width as f32 / dimensions.width as f32);
+    let source_info = ImageInfo::new(source, ColorType::RGBA8888, AlphaType::Unpremul, None);
+    if codec.start_scanline_decode(&source_info, None) != codec::Result::Success
+        || codec.scanline_order() != ScanlineOrder::TopDown
+    {
+        return Err(InputError::TooLarge(format!(
+            "{}x{} {:?} image cannot be downsampled",
+            dimensions.width,
+            dimensions.height,
+            codec.encoded_format()
+        )));
+    }
+
+    let image_info = ImageInfo::new(size, ColorType::RGBA8888, AlphaType::Unpremul, None);
+    let mut row = vec![0; source_info.min_row_bytes()];
+    let mut pixels = Vec::with_capacity(image_info.compute_min_byte_size());
+    // 取每个目标像素中心对应的原图像素
+    let sample =
+        |i: i32, from: i32, to: i32| ((2 * i + 1) as i64 * from as i64 / (2 * to) as i64) as i32;
+    let mut next_row = 0;
+    for y in 0..size.height {
+        let source_y = sample(y, source.height, size.height);
+        if source_y >= next_row {
+            codec.skip_scanlines((source_y - next_row) as usize);
+            codec.get_scanlines(&mut row, 1, source_info.min_row_bytes());
+            next_row = source_y + 1;
+        }
+        for x in 0..size.width {
+            let source_x = sample(x, source.width, size.width) as usize;
+            pixels.extend_from_slice(&row[source_x * 4..][..4]);
+        }
+    }
+
+    images::raster_from_data(
+        &image_info,
+        Data::new_copy(&pixels),
+        image_info.min_row_bytes(),
+    )
+    .ok_or_else(|| decode_error("invalid pixels").into())
+}
+
+/// 不缓存已合成的帧，每次都从不依赖其他帧的帧开始合成，按顺序读取动图时应使用 [`FrameDecoder`]
 impl<'a> CodecExt for Codec<'a> {
     fn is_multi_frame(&mut self) -> bool {
         self.get_frame_count() > 1
     }
 
     fn get_average_duration(&mut self) -> Result<f32, Error> {
-        let count = self.get_frame_count();
-        let mut total_duration = 0.0;
-        for i in 0..count {
-            let frame_info = self
-                .get_frame_info(i)
-                .ok_or(Error::ImageDecodeError("Skia decode error".to_string()))?;
-            total_duration += frame_info.duration as f32 / 1000.0;
-        }
-        Ok(total_duration / count as f32)
+        let durations = self.get_durations()?;
+        Ok(durations.iter().sum::<f32>() / durations.len() as f32)
+    }
+
+    fn get_durations(&mut self) -> Result<Vec<f32>, Error> {
+        (0..self.get_frame_count())
+            .map(|i| {
+                let frame_info = self
+                    .get_frame_info(i)
+                    .ok_or(Error::ImageDecodeError("Skia decode error".to_string()))?;
+                Ok(match frame_info.duration {
+                    ..=10 => 0.1,
+                    duration => duration as f32 / 1000.0,
+                })
+            })
+            .collect()
     }
 
     fn first_frame(&mut self) -> Result<Image, Error> {
@@ -34,19 +238,198 @@ impl<'a> CodecExt for Codec<'a> {
     }
 
     fn get_frame(&mut self, index: usize) -> Result<Image, Error> {
-        let image_info = ImageInfo::new(
-            self.dimensions(),
-            ColorType::RGBA8888,
-            AlphaType::Unpremul,
-            None,
-        );
-        let options = codec::Options {
-            zero_initialized: codec::ZeroInitialized::No,
-            subset: None,
-            frame_index: index,
-            prior_frame: None,
+        decode_frame(self, index, &mut FrameCache::default())
+    }
+}
+
+/// 缓存最近合成的帧的解码器，依赖前一帧的帧直接在缓存的帧上合成
+pub struct FrameDecoder<'a> {
+    codec: Codec<'a>,
+    cache: FrameCache,
+    data: Option<Data>,
+    /// 抽取的帧在原图中的序号，为 `None` 时使用所有帧
+    frames: Option<Vec<usize>>,
+    /// 超出像素限制时缩小后的静图
+    downscaled: Option<Image>,
+}
+
+impl<'a> FrameDecoder<'a> {
+    pub fn new(codec: Codec<'a>) -> Self {
+        Self {
+            codec,
+            cache: FrameCache::default(),
+            data: None,
+            frames: None,
+            downscaled: None,
+        }
+    }
+
+    pub fn from_data(data: &[u8]) -> Result<FrameDecoder<'static>, Error> {
+        Self::from_skia_data(Data::new_copy(data))
+    }
+
+    /// `Data` 可以在线程间共享，在每个线程中分别创建解码器即可同时读取不同的帧
+    pub fn from_skia_data(data: Data) -> Result<FrameDecoder<'static>, Error> {
+        let codec = Codec::from_data(data.clone())
+            .ok_or(Error::ImageDecodeError("Skia decode error".to_string()))?;
+        Ok(FrameDecoder {
+            codec,
+            cache: FrameCache::default(),
+            data: Some(data),
+            frames: None,
+            downscaled: None,
+        })
+    }
+
+    /// 按照配置中的输入限制创建解码器，在完整解码之前根据图片头部检查尺寸和帧数
+    ///
+    /// 解码后的字节数不能超过 `budget` 中剩余的部分，成功时从中扣除
+    pub fn from_input(
+        data: &[u8],
+        budget: &mut DecodeBudget,
+    ) -> Result<FrameDecoder<'static>, InputError> {
+        let mut decoder = Self::from_data(data)?;
+        decoder.apply_limits(&CONFIG.decoder, budget.remaining)?;
+        budget.remaining = budget.remaining.saturating_sub(decoder.decoded_bytes());
+        Ok(decoder)
+    }
+
+    /// 超出限制时返回 [`InputError::TooLarge`]，开启 `downsample_oversized` 时改为缩小静图或均匀抽取动图的部分帧
+    ///
+    /// 动图的每一帧都需要完整解码，超出像素限制时无法缩小
+    fn apply_limits(
+        &mut self,
+        config: &DecoderConfig,
+        max_decoded_bytes: u64,
+    ) -> Result<(), InputError> {
+        let dimensions = self.codec.dimensions();
+        let frame_count = self.codec.get_frame_count();
+        let pixels = dimensions.width as u64 * dimensions.height as u64;
+        let frame_bytes = pixels * 4;
+        let too_large = |reason: String| {
+            InputError::TooLarge(format!(
+                "{}x{} image with {frame_count} frames: {reason}",
+                dimensions.width, dimensions.height
+            ))
+        };
+
+        let max_pixels = config.max_input_pixels.min(max_decoded_bytes / 4);
+        if pixels > max_pixels {
+            let reason = format!("more than {max_pixels} pixels");
+            if !config.downsample_oversized || frame_count > 1 {
+                return Err(too_large(reason));
+            }
+            let scale = (max_pixels as f64 / pixels as f64).sqrt();
+            let size = ISize::new(
+                ((dimensions.width as f64 * scale) as i32).max(1),
+                ((dimensions.height as f64 * scale) as i32).max(1),
+            );
+            self.downscaled = Some(decode_downscaled(&mut self.codec, size)?);
+            return Ok(());
+        }
+
+        let max_frames = config
+            .max_input_frames
+            .min((max_decoded_bytes / frame_bytes.max(1)) as usize);
+        if frame_count > max_frames {
+            let reason = format!("more than {max_frames} frames");
+            if !config.downsample_oversized || max_frames == 0 {
+                return Err(too_large(reason));
+            }
+            self.frames = Some(
+                (0..max_frames)
+                    .map(|i| i * frame_count / max_frames)
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
+
+    /// 图片的原始数据，通过 [`FrameDecoder::new`] 创建时为 `None`
+    pub fn data(&self) -> Option<&Data> {
+        self.data.as_ref()
+    }
+
+    /// 帧数，抽取部分帧时为抽取后的帧数
+    pub fn get_frame_count(&mut self) -> usize {
+        match &self.frames {
+            Some(frames) => frames.len(),
+            None => self.codec.get_frame_count(),
+        }
+    }
+
+    /// 尺寸，缩小后为缩小后的尺寸
+    pub fn dimensions(&self) -> ISize {
+        match &self.downscaled {
+            Some(image) => image.dimensions(),
+            None => self.codec.dimensions(),
+        }
+    }
+
+    /// 所有帧解码后的字节数，缩小或抽取部分帧时按处理后的尺寸和帧数计算
+    pub fn decoded_bytes(&mut self) -> u64 {
+        let size = self.dimensions();
+        size.width as u64 * size.height as u64 * 4 * self.get_frame_count() as u64
+    }
+
+    /// 第 `index` 帧在原图中的序号
+    pub fn source_frame(&self, index: usize) -> usize {
+        match &self.frames {
+            Some(frames) => frames[index],
+            None => index,
+        }
+    }
+}
+
+impl<'a> Deref for FrameDecoder<'a> {
+    type Target = Codec<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.codec
+    }
+}
+
+impl DerefMut for FrameDecoder<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.codec
+    }
+}
+
+impl CodecExt for FrameDecoder<'_> {
+    fn is_multi_frame(&mut self) -> bool {
+        self.get_frame_count() > 1
+    }
+
+    fn get_average_duration(&mut self) -> Result<f32, Error> {
+        let durations = self.get_durations()?;
+        Ok(durations.iter().sum::<f32>() / durations.len() as f32)
+    }
+
+    /// 抽取部分帧时，每一帧的时长包括之后未抽取的帧，总时长不变
+    fn get_durations(&mut self) -> Result<Vec<f32>, Error> {
+        let durations = self.codec.get_durations()?;
+        let Some(frames) = &self.frames else {
+            return Ok(durations);
         };
-        self.get_image(image_info, &options)
-            .map_err(|err| Error::ImageDecodeError(format!("Skia decode error: {err:?}")))
+        Ok(frames
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                let end = frames.get(i + 1).copied().unwrap_or(durations.len());
+                durations[*start..end].iter().sum()
+            })
+            .collect())
+    }
+
+    fn first_frame(&mut self) -> Result<Image, Error> {
+        self.get_frame(0)
+    }
+
+    fn get_frame(&mut self, index: usize) -> Result<Image, Error> {
+        if let Some(image) = &self.downscaled {
+            return Ok(image.clone());
+        }
+        let index = self.source_frame(index);
+        decode_frame(&mut self.codec, index, &mut self.cache)
     }
 }
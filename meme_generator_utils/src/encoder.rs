@@ -1,20 +1,243 @@
+use std::{
+    cell::{Cell, RefCell},
+    ffi::CStr,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem::MaybeUninit,
+    ops::Range,
+    panic, ptr,
+    ptr::NonNull,
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
 use gif::{DisposalMethod, Encoder, Frame, Repeat};
-use skia_safe::{AlphaType, ColorType, EncodedImageFormat, Image, ImageInfo, image::CachingHint};
+use libwebp_sys::{
+    WEBP_MUX_ABI_VERSION, WebPAnimEncoder, WebPAnimEncoderAdd, WebPAnimEncoderAssemble,
+    WebPAnimEncoderDelete, WebPAnimEncoderGetError, WebPAnimEncoderNewInternal,
+    WebPAnimEncoderOptions, WebPAnimEncoderOptionsInitInternal, WebPConfig, WebPData,
+    WebPDataClear, WebPEncodeLosslessRGBA, WebPEncodeRGBA, WebPFree, WebPPicture, WebPPictureFree,
+    WebPPictureImportRGBA, WebPPreset,
+};
+use serde::Deserialize;
+use skia_safe::{
+    AlphaType, Color, ColorType, Data, EncodedImageFormat, ISize, Image, ImageInfo, Pixmap,
+    image::CachingHint, png_encoder,
+};
+use tracing::warn;
+
+use meme_generator_core::{
+    error::Error,
+    format::{Degradation, FrameMode, ImageFormat, OutputFormat, probe},
+    meme::GeneratedImage,
+};
+
+use crate::{
+    builder::InputImage,
+    config::CONFIG,
+    decoder::{CodecExt, FrameDecoder},
+    gif_optimizer::{GifFrame, GifOptions, encode_gif},
+    image::ImageExt,
+    random::{current_seed, frame_seed, mark_used, with_seed},
+    tools::new_surface,
+};
+
+thread_local! {
+    static OUTPUT_FORMAT: Cell<OutputFormat> = Cell::new(OutputFormat::default());
+    static ENCODED_INFO: RefCell<Option<EncodedInfo>> = const { RefCell::new(None) };
+}
+
+/// 以 `output` 作为输出格式执行 `func`，期间 [`encode_static`] 和 [`AnimationEncoder`] 按照它编码
+pub fn with_output_format<T>(output: OutputFormat, func: impl FnOnce() -> T) -> T {
+    struct Restore(OutputFormat);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            OUTPUT_FORMAT.set(self.0);
+        }
+    }
+
+    let _restore = Restore(OUTPUT_FORMAT.replace(output));
+    func()
+}
+
+/// 当前的输出格式
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get()
+}
+
+/// 编码函数记录的结果信息，生成结果时无需再从编码后的数据中读取
+#[derive(Debug, Clone)]
+pub(crate) struct EncodedInfo {
+    format: ImageFormat,
+    size: ISize,
+    /// 结果中每一帧的时长，单位为秒，静图只有一帧且时长为 0
+    durations: Vec<f32>,
+    /// 传给编码器的各帧及其时长，超出大小限制时用于重新编码，动图只在设置了大小限制时保留
+    frames: Vec<(Image, f32)>,
+    /// 结果的长度和哈希值，用于确认表情返回的正是这次编码的结果
+    len: usize,
+    hash: u64,
+}
+
+impl EncodedInfo {
+    /// `data` 是否为这次编码的结果
+    fn matches(&self, data: &[u8]) -> bool {
+        self.len == data.len() && self.hash == hash_data(data)
+    }
+}
+
+fn hash_data(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn record_encoded(
+    format: ImageFormat,
+    size: ISize,
+    durations: Vec<f32>,
+    frames: Vec<(Image, f32)>,
+    data: &[u8],
+) {
+    ENCODED_INFO.set(Some(EncodedInfo {
+        format,
+        size,
+        durations,
+        frames,
+        len: data.len(),
+        hash: hash_data(data),
+    }));
+}
 
-use meme_generator_core::error::Error;
+/// 执行 `func` 并返回其中最后一次编码的结果信息
+pub(crate) fn capture_encoded<T>(func: impl FnOnce() -> T) -> (T, Option<EncodedInfo>) {
+    ENCODED_INFO.take();
+    let result = func();
+    (result, ENCODED_INFO.take())
+}
+
+/// 由编码时记录的信息生成结果，同时返回保留的各帧，数据不是由这次编码生成时从数据中读取
+fn generated_image(
+    data: Vec<u8>,
+    encoded: Option<EncodedInfo>,
+) -> Result<(GeneratedImage, Vec<(Image, f32)>), Error> {
+    match encoded {
+        Some(info) if info.matches(&data) => Ok((
+            GeneratedImage {
+                format: info.format,
+                width: info.size.width as u32,
+                height: info.size.height as u32,
+                frame_count: info.durations.len() as u32,
+                durations: info.durations,
+                seed: None,
+                degradations: Vec::new(),
+                data,
+            },
+            info.frames,
+        )),
+        _ => Ok((GeneratedImage::from_data(data)?, Vec::new())),
+    }
+}
+
+/// 读取图片的 RGBA 像素，不预乘透明度
+fn read_rgba(image: &Image) -> Vec<u8> {
+    let image_info = ImageInfo::new(
+        image.dimensions(),
+        ColorType::RGBA8888,
+        AlphaType::Unpremul,
+        None,
+    );
+    let row_bytes = image_info.min_row_bytes();
+    let data_size = image_info.compute_min_byte_size();
+    let mut data = vec![0u8; data_size];
+    image.read_pixels(
+        &image_info,
+        &mut data,
+        row_bytes,
+        (0, 0),
+        CachingHint::Allow,
+    );
+    data
+}
 
-use crate::{builder::InputImage, config::CONFIG, decoder::CodecExt};
+/// 配置中开启的 gif 优化，未开启任何优化时返回 `None`
+fn gif_options() -> Option<GifOptions> {
+    let config = &CONFIG.encoder;
+    (config.gif_global_palette || config.gif_dithering || config.gif_frame_delta).then(|| {
+        GifOptions {
+            global_palette: config.gif_global_palette,
+            dithering: config.gif_dithering,
+            frame_delta: config.gif_frame_delta,
+            speed: config.gif_encode_speed as i32,
+            colors: 256,
+        }
+    })
+}
 
 pub struct GifEncoder {
     encoder: Option<Encoder<Vec<u8>>>,
+    /// 开启优化时缓存所有帧，在结束时统一编码
+    frames: Vec<GifFrame>,
+    size: Option<(u16, u16)>,
+    /// 为 `None` 时不缓存，逐帧直接编码
+    options: Option<GifOptions>,
+    /// 已添加的帧的总时长，单位为秒
+    elapsed: f32,
+    /// 逐帧直接编码时已写入的各帧的帧间隔
+    delays: Vec<u16>,
 }
 
 impl GifEncoder {
     pub fn new() -> Self {
-        Self { encoder: None }
+        Self {
+            encoder: None,
+            frames: Vec::new(),
+            size: None,
+            options: gif_options(),
+            elapsed: 0.0,
+            delays: Vec::new(),
+        }
+    }
+
+    /// gif 的帧间隔以 0.01 秒为单位，按照累计时长取整，避免误差累积改变节奏
+    fn next_delay(&mut self, duration: f32) -> u16 {
+        let start = (self.elapsed * 100.0).round();
+        self.elapsed += duration;
+        ((self.elapsed * 100.0).round() - start) as u16
+    }
+
+    /// 使用 `colors` 个颜色（包括透明色）的调色板编码
+    pub fn with_palette_size(colors: u16) -> Self {
+        let options = gif_options().unwrap_or(GifOptions {
+            global_palette: false,
+            dithering: false,
+            frame_delta: false,
+            speed: CONFIG.encoder.gif_encode_speed as i32,
+            colors,
+        });
+        Self {
+            options: Some(GifOptions { colors, ..options }),
+            ..Self::new()
+        }
     }
 
     pub fn add_frame(&mut self, image: Image, duration: f32) -> Result<(), Error> {
+        let size = (image.width() as u16, image.height() as u16);
+        if *self.size.get_or_insert(size) != size {
+            return Err(Error::ImageEncodeError(
+                "Gif encode error: frame size mismatch".to_string(),
+            ));
+        }
+        let delay = self.next_delay(duration);
+        if self.options.is_some() {
+            self.frames.push(GifFrame {
+                data: read_rgba(&image),
+                delay,
+            });
+            return Ok(());
+        }
+
         if let None = self.encoder {
             let bytes = Vec::new();
             let mut encoder = Encoder::new(bytes, image.width() as u16, image.height() as u16, &[])
@@ -26,22 +249,7 @@ impl GifEncoder {
         }
         let encoder = self.encoder.as_mut().unwrap();
 
-        let image_info = ImageInfo::new(
-            image.dimensions(),
-            ColorType::RGBA8888,
-            AlphaType::Unpremul,
-            None,
-        );
-        let row_bytes = image_info.min_row_bytes();
-        let data_size = image_info.compute_min_byte_size();
-        let mut data = vec![0u8; data_size];
-        image.read_pixels(
-            &image_info,
-            &mut data,
-            row_bytes,
-            (0, 0),
-            CachingHint::Allow,
-        );
+        let mut data = read_rgba(&image);
         let speed = CONFIG.encoder.gif_encode_speed;
         let mut frame = Frame::from_rgba_speed(
             image.width() as u16,
@@ -49,17 +257,435 @@ impl GifEncoder {
             &mut data,
             speed as i32,
         );
-        let delay = (duration * 100.0) as u16;
         frame.delay = delay;
         frame.dispose = DisposalMethod::Background;
 
         encoder
             .write_frame(&frame)
-            .map_err(|err| Error::ImageEncodeError(format!("Gif encode error: {err}")))
+            .map_err(|err| Error::ImageEncodeError(format!("Gif encode error: {err}")))?;
+        self.delays.push(delay);
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        self.finish_with_durations().map(|(data, _)| data)
+    }
+
+    /// 结束编码，同时返回结果中每一帧的时长，开启 `gif_frame_delta` 时相同的帧会被合并
+    pub(crate) fn finish_with_durations(&mut self) -> Result<(Vec<u8>, Vec<f32>), Error> {
+        let (width, height) = self
+            .size
+            .take()
+            .ok_or_else(|| Error::ImageEncodeError("Gif encode error: no frames".to_string()))?;
+        let result = match &self.options {
+            Some(options) => encode_gif(&std::mem::take(&mut self.frames), width, height, options),
+            None => {
+                let data = self.encoder.take().unwrap().into_inner();
+                data.map(|data| (data, std::mem::take(&mut self.delays)))
+                    .map_err(Into::into)
+            }
+        };
+        let (data, delays) =
+            result.map_err(|err| Error::ImageEncodeError(format!("Gif encode error: {err}")))?;
+        let durations = delays.iter().map(|delay| *delay as f32 / 100.0).collect();
+        Ok((data, durations))
+    }
+}
+
+fn webp_error(err: impl AsRef<str>) -> Error {
+    Error::ImageEncodeError(format!("Webp encode error: {}", err.as_ref()))
+}
+
+fn webp_quality() -> f32 {
+    match output_format().quality {
+        Some(quality) => quality as f32,
+        None => CONFIG.encoder.webp_quality,
+    }
+}
+
+struct WebpAnimEncoder {
+    encoder: NonNull<WebPAnimEncoder>,
+    config: WebPConfig,
+}
+
+impl WebpAnimEncoder {
+    fn new(width: i32, height: i32) -> Result<Self, Error> {
+        let mut config =
+            WebPConfig::new_with_preset(WebPPreset::WEBP_PRESET_DEFAULT, webp_quality())
+                .map_err(|_| webp_error("Invalid config"))?;
+        config.lossless = CONFIG.encoder.webp_lossless as i32;
+
+        let mut options = MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        let encoder = unsafe {
+            // 默认选项为无限循环
+            if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WEBP_MUX_ABI_VERSION as i32)
+                == 0
+            {
+                return Err(webp_error("Invalid options"));
+            }
+            WebPAnimEncoderNewInternal(width, height, options.as_ptr(), WEBP_MUX_ABI_VERSION as i32)
+        };
+        let encoder = NonNull::new(encoder).ok_or_else(|| webp_error("Invalid image size"))?;
+        Ok(Self { encoder, config })
+    }
+
+    fn error(&self) -> Error {
+        let err = unsafe { WebPAnimEncoderGetError(self.encoder.as_ptr()) };
+        if err.is_null() {
+            return webp_error("Unknown error");
+        }
+        webp_error(unsafe { CStr::from_ptr(err) }.to_string_lossy())
+    }
+}
+
+impl Drop for WebpAnimEncoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.encoder.as_ptr()) }
+    }
+}
+
+/// 动态 webp 编码器，与 gif 相比保留完整的透明度和颜色
+pub struct WebpEncoder {
+    encoder: Option<WebpAnimEncoder>,
+    /// 已添加的帧的总时长，单位为秒
+    elapsed: f32,
+}
+
+impl WebpEncoder {
+    pub fn new() -> Self {
+        Self {
+            encoder: None,
+            elapsed: 0.0,
+        }
+    }
+
+    /// 下一帧的开始时间，单位为毫秒，按照累计时长取整，避免误差累积改变节奏
+    fn timestamp(&self) -> i32 {
+        (self.elapsed * 1000.0).round() as i32
+    }
+
+    pub fn add_frame(&mut self, image: Image, duration: f32) -> Result<(), Error> {
+        if self.encoder.is_none() {
+            self.encoder = Some(WebpAnimEncoder::new(image.width(), image.height())?);
+        }
+        let encoder = self.encoder.as_ref().unwrap();
+
+        let data = read_rgba(&image);
+        let mut picture = WebPPicture::new().map_err(|_| webp_error("Invalid picture"))?;
+        picture.use_argb = 1;
+        picture.width = image.width();
+        picture.height = image.height();
+        let ok = unsafe {
+            let ok = WebPPictureImportRGBA(&mut picture, data.as_ptr(), image.width() * 4) != 0
+                && WebPAnimEncoderAdd(
+                    encoder.encoder.as_ptr(),
+                    &mut picture,
+                    self.timestamp(),
+                    &encoder.config,
+                ) != 0;
+            WebPPictureFree(&mut picture);
+            ok
+        };
+        if !ok {
+            return Err(encoder.error());
+        }
+        self.elapsed += duration;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        let encoder = self
+            .encoder
+            .take()
+            .ok_or_else(|| webp_error("No frames to encode"))?;
+        let mut data = WebPData::default();
+        unsafe {
+            // 以结束时间作为最后一帧的时长
+            if WebPAnimEncoderAdd(
+                encoder.encoder.as_ptr(),
+                ptr::null_mut(),
+                self.timestamp(),
+                ptr::null(),
+            ) == 0
+                || WebPAnimEncoderAssemble(encoder.encoder.as_ptr(), &mut data) == 0
+            {
+                return Err(encoder.error());
+            }
+            let result = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPDataClear(&mut data);
+            Ok(result)
+        }
+    }
+}
+
+fn apng_error(err: impl AsRef<str>) -> Error {
+    Error::ImageEncodeError(format!("Apng encode error: {}", err.as_ref()))
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn write_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+    output.extend((data.len() as u32).to_be_bytes());
+    output.extend(chunk_type);
+    output.extend(data);
+    output.extend(hasher.finalize().to_be_bytes());
+}
+
+/// apng 帧的处置方式，即绘制下一帧之前如何处理当前帧的区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApngDisposal {
+    /// 保留当前帧
+    None,
+
+    /// 清除为透明
+    Background,
+
+    /// 恢复为当前帧绘制之前的内容
+    Previous,
+}
+
+struct ApngFrame {
+    /// 压缩后的图像数据，即 PNG 中 IDAT 块的内容
+    data: Vec<Vec<u8>>,
+    /// 帧时长的分子和分母，单位为秒
+    delay: (u16, u16),
+    disposal: ApngDisposal,
+}
+
+/// apng 编码器，无损保留颜色和透明度
+pub struct ApngEncoder {
+    /// 第一帧的 IHDR 块，所有帧的尺寸和像素格式都与第一帧相同
+    header: Option<Vec<u8>>,
+    frames: Vec<ApngFrame>,
+    /// 已添加的帧的总时长，单位为秒
+    elapsed: f32,
+}
+
+impl ApngEncoder {
+    pub fn new() -> Self {
+        Self {
+            header: None,
+            frames: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    /// 帧时长以毫秒为单位，按照累计时长取整，避免误差累积改变节奏
+    ///
+    /// 超过 65.535 秒时依次改用 10 毫秒、100 毫秒和 1 秒为单位，超过 65535 秒时截断
+    fn next_delay(&mut self, duration: f32) -> (u16, u16) {
+        let start = (self.elapsed * 1000.0).round();
+        self.elapsed += duration;
+        let millis = (self.elapsed * 1000.0).round() - start;
+        for den in [1000u16, 100, 10] {
+            let num = (millis * den as f32 / 1000.0).round();
+            if num <= u16::MAX as f32 {
+                return (num as u16, den);
+            }
+        }
+        warn!(
+            "Apng frame duration {duration}s is too long, truncated to {}s",
+            u16::MAX
+        );
+        ((millis / 1000.0).round().min(u16::MAX as f32) as u16, 1)
     }
 
-    pub fn finish(&mut self) -> Vec<u8> {
-        self.encoder.take().unwrap().into_inner().unwrap()
+    pub fn add_frame(&mut self, image: Image, duration: f32) -> Result<(), Error> {
+        self.add_frame_with_disposal(image, duration, ApngDisposal::Background)
+    }
+
+    pub fn add_frame_with_disposal(
+        &mut self,
+        image: Image,
+        duration: f32,
+        disposal: ApngDisposal,
+    ) -> Result<(), Error> {
+        // 统一使用非预乘的 RGBA 编码，保证每一帧的像素格式相同
+        let image_info = ImageInfo::new(
+            image.dimensions(),
+            ColorType::RGBA8888,
+            AlphaType::Unpremul,
+            None,
+        );
+        let mut pixels = read_rgba(&image);
+        let pixmap = Pixmap::new(&image_info, &mut pixels, image_info.min_row_bytes())
+            .ok_or_else(|| apng_error("Invalid image"))?;
+        let mut png = Vec::new();
+        if !png_encoder::encode(&pixmap, &mut png, &png_encoder::Options::default()) {
+            return Err(apng_error("Skia encode error"));
+        }
+
+        let mut header = None;
+        let mut data = Vec::new();
+        let mut offset = PNG_SIGNATURE.len();
+        while offset + 8 <= png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let chunk_type = &png[offset + 4..offset + 8];
+            let chunk_data = png
+                .get(offset + 8..offset + 8 + len)
+                .ok_or_else(|| apng_error("Invalid png data"))?;
+            match chunk_type {
+                b"IHDR" => header = Some(chunk_data.to_vec()),
+                b"IDAT" => data.push(chunk_data.to_vec()),
+                _ => {}
+            }
+            offset += len + 12;
+        }
+        let header = header.ok_or_else(|| apng_error("Invalid png data"))?;
+        match &self.header {
+            None => self.header = Some(header),
+            Some(first) if *first != header => return Err(apng_error("Frame size mismatch")),
+            _ => {}
+        }
+
+        let delay = self.next_delay(duration);
+        self.frames.push(ApngFrame {
+            data,
+            delay,
+            disposal,
+        });
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        let header = self
+            .header
+            .take()
+            .ok_or_else(|| apng_error("No frames to encode"))?;
+        let frames = std::mem::take(&mut self.frames);
+
+        let mut output = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut output, b"IHDR", &header);
+        // 帧数和循环次数，0 表示无限循环
+        let mut actl = (frames.len() as u32).to_be_bytes().to_vec();
+        actl.extend(0u32.to_be_bytes());
+        write_png_chunk(&mut output, b"acTL", &actl);
+
+        let mut sequence = 0u32;
+        for (index, frame) in frames.into_iter().enumerate() {
+            let mut fctl = sequence.to_be_bytes().to_vec();
+            fctl.extend(&header[0..8]);
+            fctl.extend(0u32.to_be_bytes());
+            fctl.extend(0u32.to_be_bytes());
+            let (delay_num, delay_den) = frame.delay;
+            fctl.extend(delay_num.to_be_bytes());
+            fctl.extend(delay_den.to_be_bytes());
+            fctl.push(match frame.disposal {
+                ApngDisposal::None => 0,
+                ApngDisposal::Background => 1,
+                ApngDisposal::Previous => 2,
+            });
+            // 直接覆盖而不是混合，透明的像素也会替换之前的内容
+            fctl.push(0);
+            write_png_chunk(&mut output, b"fcTL", &fctl);
+            sequence += 1;
+
+            for data in frame.data {
+                if index == 0 {
+                    write_png_chunk(&mut output, b"IDAT", &data);
+                } else {
+                    let mut fdat = sequence.to_be_bytes().to_vec();
+                    fdat.extend(data);
+                    write_png_chunk(&mut output, b"fdAT", &fdat);
+                    sequence += 1;
+                }
+            }
+        }
+        write_png_chunk(&mut output, b"IEND", &[]);
+        Ok(output)
+    }
+}
+
+enum EncoderKind {
+    Gif(GifEncoder),
+    Webp(WebpEncoder),
+    Apng(ApngEncoder),
+    Static(Option<Image>),
+}
+
+/// 按照 [`with_output_format`] 指定的格式编码动图，默认为 gif
+///
+/// 要求输出静图或输出格式不支持动图时，只保留第一帧并按静图编码
+pub struct AnimationEncoder {
+    kind: EncoderKind,
+    size: Option<ISize>,
+    /// 已添加的各帧的时长
+    durations: Vec<f32>,
+    /// 已添加的各帧及其时长，只在设置了大小限制时保留
+    frames: Option<Vec<(Image, f32)>>,
+}
+
+impl AnimationEncoder {
+    pub fn new() -> Self {
+        let output = output_format();
+        let kind = if output.frames == FrameMode::Static {
+            EncoderKind::Static(None)
+        } else {
+            match output.animated_format() {
+                Some(ImageFormat::Webp) => EncoderKind::Webp(WebpEncoder::new()),
+                Some(ImageFormat::Apng) => EncoderKind::Apng(ApngEncoder::new()),
+                Some(_) => EncoderKind::Gif(GifEncoder::new()),
+                None => EncoderKind::Static(None),
+            }
+        };
+        Self::with_kind(kind)
+    }
+
+    fn with_kind(kind: EncoderKind) -> Self {
+        Self {
+            kind,
+            size: None,
+            durations: Vec::new(),
+            frames: max_output_bytes().map(|_| Vec::new()),
+        }
+    }
+
+    /// 是否只保留第一帧，此时无需再生成之后的帧
+    pub fn is_static(&self) -> bool {
+        matches!(self.kind, EncoderKind::Static(_))
+    }
+
+    pub fn add_frame(&mut self, image: Image, duration: f32) -> Result<(), Error> {
+        self.size.get_or_insert(image.dimensions());
+        self.durations.push(duration);
+        if let Some(frames) = self.frames.as_mut() {
+            frames.push((image.clone(), duration));
+        }
+        match &mut self.kind {
+            EncoderKind::Gif(encoder) => encoder.add_frame(image, duration),
+            EncoderKind::Webp(encoder) => encoder.add_frame(image, duration),
+            EncoderKind::Apng(encoder) => encoder.add_frame(image, duration),
+            EncoderKind::Static(first) => {
+                first.get_or_insert(image);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, Error> {
+        let mut durations = std::mem::take(&mut self.durations);
+        let (format, data) = match &mut self.kind {
+            EncoderKind::Gif(encoder) => {
+                let (data, gif_durations) = encoder.finish_with_durations()?;
+                durations = gif_durations;
+                (ImageFormat::Gif, data)
+            }
+            EncoderKind::Webp(encoder) => (ImageFormat::Webp, encoder.finish()?),
+            EncoderKind::Apng(encoder) => (ImageFormat::Apng, encoder.finish()?),
+            EncoderKind::Static(first) => {
+                let image = first
+                    .take()
+                    .ok_or(Error::ImageEncodeError("No frames to encode".to_string()))?;
+                return encode_static(image);
+            }
+        };
+        let size = self.size.take().unwrap_or_default();
+        let frames = self.frames.take().unwrap_or_default();
+        record_encoded(format, size, durations, frames, &data);
+        Ok(data)
     }
 }
 
@@ -75,11 +701,261 @@ fn encode_image(
 }
 
 pub fn encode_png(image: Image) -> Result<Vec<u8>, Error> {
-    encode_image(image, EncodedImageFormat::PNG, None)
+    let data = encode_image(image.clone(), EncodedImageFormat::PNG, None)?;
+    record_encoded(
+        ImageFormat::Png,
+        image.dimensions(),
+        vec![0.0],
+        vec![(image, 0.0)],
+        &data,
+    );
+    Ok(data)
+}
+
+/// jpeg 不支持透明度，透明的部分以白色填充
+pub fn encode_jpeg(image: Image, quality: u8) -> Result<Vec<u8>, Error> {
+    let mut surface = new_surface(image.dimensions());
+    let canvas = surface.canvas();
+    canvas.clear(Color::WHITE);
+    canvas.draw_image(&image, (0, 0), None);
+    let data = encode_image(
+        surface.image_snapshot(),
+        EncodedImageFormat::JPEG,
+        quality as u32,
+    )?;
+    record_encoded(
+        ImageFormat::Jpeg,
+        image.dimensions(),
+        vec![0.0],
+        vec![(image, 0.0)],
+        &data,
+    );
+    Ok(data)
+}
+
+pub fn encode_webp(image: Image, quality: f32, lossless: bool) -> Result<Vec<u8>, Error> {
+    let data = read_rgba(&image);
+    let (width, height) = (image.width(), image.height());
+    let mut output = ptr::null_mut();
+    unsafe {
+        let size = if lossless {
+            WebPEncodeLosslessRGBA(data.as_ptr(), width, height, width * 4, &mut output)
+        } else {
+            WebPEncodeRGBA(
+                data.as_ptr(),
+                width,
+                height,
+                width * 4,
+                quality,
+                &mut output,
+            )
+        };
+        if size == 0 {
+            return Err(webp_error("Invalid image"));
+        }
+        let result = slice::from_raw_parts(output, size).to_vec();
+        WebPFree(output as *mut _);
+        record_encoded(
+            ImageFormat::Webp,
+            image.dimensions(),
+            vec![0.0],
+            vec![(image, 0.0)],
+            &result,
+        );
+        Ok(result)
+    }
+}
+
+/// 按照 [`with_output_format`] 指定的格式编码静图，默认为 png
+///
+/// 要求输出动图时编码为只有一帧的动图
+pub fn encode_static(image: Image) -> Result<Vec<u8>, Error> {
+    let output = output_format();
+    if output.frames == FrameMode::Animated && output.animated_format().is_some() {
+        let mut encoder = AnimationEncoder::new();
+        encoder.add_frame(image, 0.1)?;
+        return encoder.finish();
+    }
+    match output.static_format() {
+        ImageFormat::Jpeg => {
+            encode_jpeg(image, output.quality.unwrap_or(CONFIG.encoder.jpeg_quality))
+        }
+        ImageFormat::Webp => encode_webp(image, webp_quality(), CONFIG.encoder.webp_lossless),
+        ImageFormat::Gif => {
+            let mut encoder = GifEncoder::new();
+            encoder.add_frame(image.clone(), 0.0)?;
+            let data = encoder.finish()?;
+            record_encoded(
+                ImageFormat::Gif,
+                image.dimensions(),
+                vec![0.0],
+                vec![(image, 0.0)],
+                &data,
+            );
+            Ok(data)
+        }
+        _ => encode_png(image),
+    }
+}
+
+/// 将表情直接编码的 png 静图转换为 [`with_output_format`] 指定的格式
+///
+/// png 是无损的，转换不会损失画质；其他格式的结果已经由编码器按照输出格式生成，原样返回
+pub(crate) fn convert_output(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let output = output_format();
+    let is_png = probe(&data).is_some_and(|probe| probe.format == ImageFormat::Png);
+    if !is_png
+        || (output.static_format() == ImageFormat::Png && output.frames != FrameMode::Animated)
+    {
+        return Ok(data);
+    }
+    let image = Image::from_encoded(Data::new_copy(&data))
+        .ok_or(Error::ImageEncodeError("Invalid png data".to_string()))?;
+    encode_static(image)
+}
+
+/// 降级处理的最多次数
+const MAX_DEGRADATIONS: usize = 8;
+/// 降级时保留的最少帧数
+const MIN_DEGRADED_FRAMES: usize = 5;
+/// 降级时图片边长的下限
+const MIN_DEGRADED_SIZE: i32 = 16;
+
+/// 结果的最大字节数，调用方指定的限制优先于配置
+fn max_output_bytes() -> Option<usize> {
+    output_format()
+        .max_output_bytes
+        .map(|max_bytes| max_bytes as usize)
+        .or(CONFIG.encoder.max_output_bytes)
+}
+
+/// 以原来的格式重新编码降级后的各帧
+fn encode_degraded(
+    format: ImageFormat,
+    animated: bool,
+    frames: Vec<(Image, f32)>,
+    colors: u16,
+) -> Result<Vec<u8>, Error> {
+    let kind = match format {
+        ImageFormat::Gif => EncoderKind::Gif(GifEncoder::with_palette_size(colors)),
+        ImageFormat::Apng => EncoderKind::Apng(ApngEncoder::new()),
+        ImageFormat::Webp if animated => EncoderKind::Webp(WebpEncoder::new()),
+        _ => {
+            let (image, _) = frames.into_iter().next().unwrap();
+            return match format {
+                ImageFormat::Jpeg => encode_jpeg(
+                    image,
+                    output_format()
+                        .quality
+                        .unwrap_or(CONFIG.encoder.jpeg_quality),
+                ),
+                ImageFormat::Webp => {
+                    encode_webp(image, webp_quality(), CONFIG.encoder.webp_lossless)
+                }
+                _ => encode_png(image),
+            };
+        }
+    };
+    let mut encoder = AnimationEncoder::with_kind(kind);
+    for (image, duration) in frames {
+        encoder.add_frame(image, duration)?;
+    }
+    encoder.finish()
+}
+
+/// 生成结果，超出大小限制时依次尝试减少 gif 的颜色数、减少帧数和降低分辨率后重新编码，直到满足限制
+///
+/// `encoded` 为生成 `data` 时由 [`capture_encoded`] 记录的信息，重新编码时使用其中保留的各帧，
+/// 所做的降级处理记录在结果中
+pub(crate) fn fit_output_size(
+    data: Vec<u8>,
+    encoded: Option<EncodedInfo>,
+) -> Result<GeneratedImage, Error> {
+    let (mut result, kept_frames) = generated_image(data, encoded)?;
+    let Some(max_bytes) = max_output_bytes() else {
+        return Ok(result);
+    };
+    if result.data.len() <= max_bytes {
+        return Ok(result);
+    }
+
+    let (format, width, height) = (result.format, result.width, result.height);
+    let animated =
+        result.durations.len() > 1 || result.durations.iter().any(|duration| *duration > 0.0);
+    // 表情返回的数据不是由编码函数生成时才重新解码
+    let mut frames = if !kept_frames.is_empty() {
+        kept_frames
+    } else {
+        let mut codec = FrameDecoder::from_data(&result.data)?;
+        (0..codec.get_frame_count())
+            .map(|index| codec.get_frame(index))
+            .zip(
+                result
+                    .durations
+                    .iter()
+                    .copied()
+                    .chain(std::iter::repeat(0.0)),
+            )
+            .map(|(image, duration)| image.map(|image| (image, duration)))
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    let mut degradations = Vec::new();
+    let mut colors = 256;
+    let mut scale = 1.0;
+    let mut size = ISize::new(width as i32, height as i32);
+    while result.data.len() > max_bytes {
+        if degradations.len() >= MAX_DEGRADATIONS {
+            return Err(Error::ImageEncodeError(format!(
+                "Output exceeds {max_bytes} bytes"
+            )));
+        }
+        let ratio = max_bytes as f32 / result.data.len() as f32;
+        // 接近限制时先减少颜色数，对画质影响最小
+        let degradation = if format == ImageFormat::Gif && colors > 64 && ratio > 0.75 {
+            colors /= 2;
+            Degradation::ReducePalette { colors }
+        } else if frames.len() >= MIN_DEGRADED_FRAMES * 2 {
+            frames = frames
+                .chunks(2)
+                .map(|pair| (pair[0].0.clone(), pair.iter().map(|frame| frame.1).sum()))
+                .collect();
+            Degradation::DropFrames {
+                frame_count: frames.len() as u32,
+            }
+        } else {
+            scale *= (ratio.sqrt() * 0.9).clamp(0.5, 0.9);
+            size = ISize::new(
+                (width as f32 * scale).round() as i32,
+                (height as f32 * scale).round() as i32,
+            );
+            if size.width < MIN_DEGRADED_SIZE || size.height < MIN_DEGRADED_SIZE {
+                return Err(Error::ImageEncodeError(format!(
+                    "Output exceeds {max_bytes} bytes"
+                )));
+            }
+            Degradation::Downscale {
+                width: size.width as u32,
+                height: size.height as u32,
+            }
+        };
+        degradations.push(degradation);
+
+        let degraded = frames
+            .iter()
+            .map(|(image, duration)| (image.resize_exact(size), *duration))
+            .collect();
+        let (data, encoded) =
+            capture_encoded(|| encode_degraded(format, animated, degraded, colors));
+        (result, _) = generated_image(data?, encoded)?;
+    }
+    result.degradations = degradations;
+    Ok(result)
 }
 
 /// gif 对齐方式
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FrameAlign {
     /// 不延长
     NoExtend,
@@ -107,89 +983,95 @@ impl GifInfo {
     pub fn total_duration(&self) -> f32 {
         self.frame_num as f32 * self.duration
     }
+
+    /// 每一帧的时长
+    pub fn durations(&self) -> Vec<f32> {
+        vec![self.duration; self.frame_num as usize]
+    }
+}
+
+/// 时间轴上 `time` 所在的帧，超出总时长时循环
+fn frame_at(durations: &[f32], time: f32) -> usize {
+    let total_duration = durations.iter().sum::<f32>();
+    // 累加时长的浮点误差可能使时间落在帧边界之前
+    let mut time = (time + 1e-4) % total_duration;
+    for (index, duration) in durations.iter().enumerate() {
+        if time < *duration {
+            return index;
+        }
+        time -= duration;
+    }
+    durations.len() - 1
 }
 
-/// 将多个 gif 按照目标帧数和帧间隔对齐
+/// 将多个 gif 按照目标 gif 的时间轴对齐
 ///
-/// - `gif_infos` 每个 gif 的帧数和帧间隔
-/// - `target_gif_info` 目标 gif 的帧数和帧间隔
+/// - `gif_durations` 每个 gif 每一帧的时长
+/// - `target_durations` 目标 gif 每一帧的时长
 /// - `frame_align` gif 对齐方式
 ///
-/// 返回值：每个 gif 的帧索引列表和目标 gif 的帧索引列表
+/// 返回值：每个 gif 的帧索引列表和目标 gif 的帧索引列表，目标 gif 的每一帧保持原来的时长
 pub fn get_aligned_gif_indexes(
-    gif_infos: &Vec<GifInfo>,
-    target_gif_info: &GifInfo,
+    gif_durations: &[Vec<f32>],
+    target_durations: &[f32],
     frame_align: impl Into<Option<FrameAlign>>,
 ) -> (Vec<Vec<usize>>, Vec<usize>) {
-    let mut target_frame_indexes: Vec<usize> = (0..target_gif_info.frame_num as usize).collect();
+    let target_frame_num = target_durations.len();
+    let mut target_frame_indexes: Vec<usize> = (0..target_frame_num).collect();
 
-    let max_total_duration = gif_infos
+    let total_duration = |durations: &[f32]| durations.iter().sum::<f32>();
+    let max_total_duration = gif_durations
         .iter()
-        .map(|gif_info| gif_info.total_duration())
+        .map(|durations| total_duration(durations))
         .max_by(|a, b| a.partial_cmp(b).unwrap())
         .unwrap();
-    let target_total_duration = target_gif_info.total_duration();
+    let target_total_duration = total_duration(target_durations);
+    let average_duration = target_total_duration / target_frame_num as f32;
 
     let diff_duration = max_total_duration - target_total_duration;
-    if diff_duration >= target_gif_info.duration {
-        let diff_num = (diff_duration / target_gif_info.duration).ceil() as i32;
-        let frame_align = frame_align.into().unwrap_or(FrameAlign::NoExtend);
-        match frame_align {
-            FrameAlign::ExtendFirst => {
-                let mut origin_frame_indexes = target_frame_indexes.clone();
-                target_frame_indexes = vec![0; diff_num as usize];
-                target_frame_indexes.append(&mut origin_frame_indexes);
-            }
-            FrameAlign::ExtendLast => {
-                let mut append_frame_indexes =
-                    vec![target_gif_info.frame_num as usize - 1; diff_num as usize];
+    let frame_align = frame_align.into().unwrap_or(FrameAlign::NoExtend);
+    let extend_num = |duration: f32| (diff_duration / duration).ceil() as usize;
+    match frame_align {
+        FrameAlign::ExtendFirst if diff_duration >= target_durations[0] => {
+            let mut origin_frame_indexes = target_frame_indexes.clone();
+            target_frame_indexes = vec![0; extend_num(target_durations[0])];
+            target_frame_indexes.append(&mut origin_frame_indexes);
+        }
+        FrameAlign::ExtendLast if diff_duration >= target_durations[target_frame_num - 1] => {
+            let mut append_frame_indexes =
+                vec![target_frame_num - 1; extend_num(target_durations[target_frame_num - 1])];
+            target_frame_indexes.append(&mut append_frame_indexes);
+        }
+        FrameAlign::ExtendLoop if diff_duration >= average_duration => {
+            let mut total_frame_num = target_frame_num;
+            let max_frame_num = CONFIG.encoder.gif_max_frames as usize;
+            while total_frame_num + target_frame_num <= max_frame_num {
+                total_frame_num += target_frame_num;
+                let mut append_frame_indexes = (0..target_frame_num).collect();
                 target_frame_indexes.append(&mut append_frame_indexes);
-            }
-            FrameAlign::ExtendLoop => {
-                let mut total_frame_num = target_gif_info.frame_num;
-                let max_frame_num = CONFIG.encoder.gif_max_frames;
-                while total_frame_num + target_gif_info.frame_num <= max_frame_num as u32 {
-                    total_frame_num += target_gif_info.frame_num;
-                    let mut append_frame_indexes =
-                        (0..target_gif_info.frame_num as usize).collect();
-                    target_frame_indexes.append(&mut append_frame_indexes);
-                    let total_duration = total_frame_num as f32 * target_gif_info.duration;
-                    if gif_infos.iter().all(|gif_info| {
-                        ((total_duration / gif_info.total_duration() as f32).round()
-                            * gif_info.total_duration()
-                            - total_duration)
-                            .abs()
-                            <= target_gif_info.duration
-                    }) {
-                        break;
-                    }
+                let total_duration =
+                    target_total_duration * (total_frame_num / target_frame_num) as f32;
+                if gif_durations.iter().all(|durations| {
+                    let gif_total_duration = durations.iter().sum::<f32>();
+                    ((total_duration / gif_total_duration).round() * gif_total_duration
+                        - total_duration)
+                        .abs()
+                        <= average_duration
+                }) {
+                    break;
                 }
             }
-            _ => {}
         }
+        _ => {}
     }
 
     let mut frame_indexes: Vec<Vec<usize>> = Vec::new();
-    for gif_info in gif_infos {
-        let mut frame_index = 0;
-        let mut time_start = 0.0;
+    for durations in gif_durations {
+        let mut time = 0.0;
         let mut indexes: Vec<usize> = Vec::new();
-        for i in 0..target_frame_indexes.len() {
-            while frame_index < gif_info.frame_num {
-                let duration = i as f32 * target_gif_info.duration - time_start;
-                if duration >= frame_index as f32 * gif_info.duration
-                    && duration < (frame_index + 1) as f32 * gif_info.duration
-                {
-                    indexes.push(frame_index as usize);
-                    break;
-                } else {
-                    frame_index += 1;
-                    if frame_index >= gif_info.frame_num {
-                        frame_index = 0;
-                        time_start += gif_info.total_duration();
-                    }
-                }
-            }
+        for target_index in &target_frame_indexes {
+            indexes.push(frame_at(durations, time));
+            time += target_durations[*target_index];
         }
         frame_indexes.push(indexes);
     }
@@ -197,6 +1079,295 @@ pub fn get_aligned_gif_indexes(
     (frame_indexes, target_frame_indexes)
 }
 
+/// 每一输出帧所使用的各图片的帧
+struct FramePlan {
+    /// 每张图片在每一输出帧中的帧序号，静图为 `None`
+    inputs: Vec<Option<Vec<usize>>>,
+    /// 每一输出帧传给图片处理函数的帧序号
+    indexes: Vec<usize>,
+    /// 每一输出帧的时长
+    durations: Vec<f32>,
+}
+
+/// 每张动图每一帧的时长，静图为 `None`
+fn input_durations(images: &mut [FrameDecoder]) -> Result<Vec<Option<Vec<f32>>>, Error> {
+    images
+        .iter_mut()
+        .map(|image| {
+            if image.is_multi_frame() {
+                image.get_durations().map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+        .collect()
+}
+
+/// 将对齐后每张动图的帧序号分配给对应的图片
+fn assign_frames(
+    durations: &[Option<Vec<f32>>],
+    frame_indexes: Vec<Vec<usize>>,
+) -> Vec<Option<Vec<usize>>> {
+    let mut frame_indexes = frame_indexes.into_iter();
+    durations
+        .iter()
+        .map(|durations| durations.as_ref().and_then(|_| frame_indexes.next()))
+        .collect()
+}
+
+/// `make_png_or_gif` 的帧对齐，没有动图时返回 `None`
+fn png_or_gif_plan(images: &mut [FrameDecoder]) -> Result<Option<FramePlan>, Error> {
+    let durations = input_durations(images)?;
+    let mut gif_durations = durations.iter().flatten().cloned().collect::<Vec<_>>();
+
+    if gif_durations.len() == 0 {
+        return Ok(None);
+    } else if gif_durations.len() == 1 {
+        let target_durations = gif_durations.remove(0);
+        let indexes = (0..target_durations.len()).collect::<Vec<_>>();
+        return Ok(Some(FramePlan {
+            inputs: assign_frames(&durations, vec![indexes.clone()]),
+            indexes,
+            durations: target_durations,
+        }));
+    }
+
+    // 以平均帧间隔最短的 gif 为目标
+    let average_duration =
+        |durations: &Vec<f32>| durations.iter().sum::<f32>() / durations.len() as f32;
+    let mut target_gif_index = 0;
+    for (i, durations) in gif_durations.iter().enumerate() {
+        if average_duration(durations) < average_duration(&gif_durations[target_gif_index]) {
+            target_gif_index = i;
+        }
+    }
+    let target_durations = gif_durations.remove(target_gif_index);
+
+    let (mut frame_indexes, target_frame_indexes) =
+        get_aligned_gif_indexes(&gif_durations, &target_durations, FrameAlign::ExtendLoop);
+    let output_durations = target_frame_indexes
+        .iter()
+        .map(|index| target_durations[*index])
+        .collect::<Vec<_>>();
+    let indexes = (0..target_frame_indexes.len()).collect();
+    frame_indexes.insert(target_gif_index, target_frame_indexes);
+
+    Ok(Some(FramePlan {
+        inputs: assign_frames(&durations, frame_indexes),
+        indexes,
+        durations: output_durations,
+    }))
+}
+
+/// `make_gif_or_combined_gif` 的帧对齐
+fn combined_gif_plan(
+    images: &mut [FrameDecoder],
+    target_gif_info: &GifInfo,
+    frame_align: Option<FrameAlign>,
+) -> Result<FramePlan, Error> {
+    let durations = input_durations(images)?;
+    let gif_durations = durations.iter().flatten().cloned().collect::<Vec<_>>();
+
+    if gif_durations.len() == 0 {
+        return Ok(FramePlan {
+            inputs: vec![None; images.len()],
+            indexes: (0..target_gif_info.frame_num as usize).collect(),
+            durations: target_gif_info.durations(),
+        });
+    }
+
+    let (frame_indexes, target_frame_indexes) =
+        get_aligned_gif_indexes(&gif_durations, &target_gif_info.durations(), frame_align);
+    Ok(FramePlan {
+        inputs: assign_frames(&durations, frame_indexes),
+        durations: vec![target_gif_info.duration; target_frame_indexes.len()],
+        indexes: target_frame_indexes,
+    })
+}
+
+/// 按顺序逐帧生成并编码
+fn render_frames<F>(
+    images: &mut [FrameDecoder],
+    plan: &FramePlan,
+    mut func: F,
+) -> Result<Vec<u8>, Error>
+where
+    F: FnMut(usize, Vec<Image>) -> Result<Image, Error>,
+{
+    let mut encoder = AnimationEncoder::new();
+    for (i, (index, duration)) in plan.indexes.iter().zip(&plan.durations).enumerate() {
+        let frame_images = images
+            .iter_mut()
+            .zip(&plan.inputs)
+            .map(|(image, frames)| match frames {
+                Some(frames) => image.get_frame(frames[i]),
+                None => image.first_frame(),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let frame = func(*index, frame_images)?;
+        encoder.add_frame(frame, *duration)?;
+        if encoder.is_static() {
+            break;
+        }
+    }
+    encoder.finish()
+}
+
+/// 并行渲染时的图片来源，静图只解码一次，动图在每个线程中分别创建解码器
+enum FrameSource {
+    Static(Image),
+    /// 图片数据和每一输出帧在原图中的帧序号
+    Animated(Data, Vec<usize>),
+}
+
+/// 并行渲染使用的线程数
+fn render_threads() -> usize {
+    match CONFIG.encoder.render_threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    }
+}
+
+/// 在当前线程中生成 `range` 内的帧
+fn render_frame_range<F>(
+    sources: &[FrameSource],
+    plan: &FramePlan,
+    range: Range<usize>,
+    func: &F,
+    seed: Option<i32>,
+    seed_used: &AtomicBool,
+) -> Result<Vec<Image>, Error>
+where
+    F: Fn(usize, Vec<Image>) -> Result<Image, Error> + Sync,
+{
+    let mut decoders = sources
+        .iter()
+        .map(|source| match source {
+            FrameSource::Static(_) => Ok(None),
+            FrameSource::Animated(data, _) => FrameDecoder::from_skia_data(data.clone()).map(Some),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    range
+        .map(|i| {
+            let frame_images = sources
+                .iter()
+                .zip(decoders.iter_mut())
+                .map(|(source, decoder)| match (source, decoder) {
+                    (FrameSource::Animated(_, frames), Some(decoder)) => {
+                        decoder.get_frame(frames[i])
+                    }
+                    (FrameSource::Static(image), _) => Ok(image.clone()),
+                    _ => unreachable!(),
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            with_frame_seed(seed, i, seed_used, || func(plan.indexes[i], frame_images))
+        })
+        .collect()
+}
+
+/// 使用由种子和帧序号 `i` 派生的种子生成一帧，没有种子时直接生成
+fn with_frame_seed<T>(
+    seed: Option<i32>,
+    i: usize,
+    seed_used: &AtomicBool,
+    render: impl FnOnce() -> T,
+) -> T {
+    match seed {
+        Some(seed) => {
+            let (frame, used) = with_seed(frame_seed(seed, i), render);
+            if used {
+                seed_used.store(true, Ordering::Relaxed);
+            }
+            frame
+        }
+        None => render(),
+    }
+}
+
+/// 将所有帧分为连续的若干段，在多个线程中分别解码和生成，再按顺序编码
+///
+/// 输出静图、只有一帧或只有一个线程时按顺序生成，每一帧同样使用派生的种子，结果与线程数无关
+fn render_frames_parallel<F>(
+    images: &mut [FrameDecoder],
+    plan: &FramePlan,
+    func: &F,
+) -> Result<Vec<u8>, Error>
+where
+    F: Fn(usize, Vec<Image>) -> Result<Image, Error> + Sync,
+{
+    let frame_num = plan.indexes.len();
+    let threads = render_threads().min(frame_num);
+    let mut encoder = AnimationEncoder::new();
+    let shared = plan
+        .inputs
+        .iter()
+        .zip(images.iter())
+        .all(|(frames, image)| frames.is_none() || image.data().is_some());
+    let seed = current_seed();
+    let seed_used = AtomicBool::new(false);
+    if threads <= 1 || encoder.is_static() || !shared {
+        let mut positions = 0..;
+        let result = render_frames(images, plan, |index, images| {
+            let i = positions.next().unwrap();
+            with_frame_seed(seed, i, &seed_used, || func(index, images))
+        });
+        if seed_used.load(Ordering::Relaxed) {
+            mark_used();
+        }
+        return result;
+    }
+
+    let sources = images
+        .iter_mut()
+        .zip(&plan.inputs)
+        .map(|(image, frames)| match frames {
+            Some(frames) => Ok(FrameSource::Animated(
+                image.data().unwrap().clone(),
+                frames
+                    .iter()
+                    .map(|index| image.source_frame(*index))
+                    .collect(),
+            )),
+            None => image.first_frame().map(FrameSource::Static),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let output = output_format();
+    let chunk_size = frame_num.div_ceil(threads);
+    let chunks = thread::scope(|scope| {
+        let handles = (0..frame_num)
+            .step_by(chunk_size)
+            .map(|start| {
+                let range = start..(start + chunk_size).min(frame_num);
+                let (sources, seed_used) = (&sources, &seed_used);
+                scope.spawn(move || {
+                    with_output_format(output, || {
+                        render_frame_range(sources, plan, range, func, seed, seed_used)
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect::<Vec<_>>()
+    });
+    if seed_used.load(Ordering::Relaxed) {
+        mark_used();
+    }
+
+    let frames = chunks.into_iter().collect::<Result<Vec<_>, Error>>()?;
+    for (frame, duration) in frames.into_iter().flatten().zip(&plan.durations) {
+        encoder.add_frame(frame, *duration)?;
+    }
+    encoder.finish()
+}
+
 /// 制作 png 或 gif
 ///
 /// - `images` 图片列表
@@ -211,76 +1382,43 @@ where
         .map(|image| image.codec)
         .collect::<Vec<_>>();
 
-    let mut gif_flags: Vec<bool> = Vec::new();
-    let mut gif_infos: Vec<GifInfo> = Vec::new();
-    for image in images.iter_mut() {
-        if image.is_multi_frame() {
-            gif_flags.push(true);
-            gif_infos.push(GifInfo {
-                frame_num: image.get_frame_count() as u32,
-                duration: image.get_average_duration()?,
-            });
-        } else {
-            gif_flags.push(false);
-        }
-    }
-
-    if gif_infos.len() == 0 {
-        let images = images
-            .iter_mut()
-            .map(|image| image.first_frame())
-            .collect::<Result<Vec<_>, Error>>()?;
-        return Ok(encode_png(func(images)?)?);
-    } else if gif_infos.len() == 1 {
-        let mut encoder = GifEncoder::new();
-        let gif_info = &gif_infos[0];
-        for i in 0..gif_info.frame_num {
-            let mut frame_images: Vec<Image> = Vec::new();
-            for (j, image) in images.iter_mut().enumerate() {
-                if gif_flags[j] {
-                    frame_images.push(image.get_frame(i as usize)?);
-                } else {
-                    frame_images.push(image.first_frame()?);
-                }
-            }
-            let frame = func(frame_images)?;
-            encoder.add_frame(frame, gif_info.duration)?;
-        }
-        return Ok(encoder.finish());
-    }
-
-    let mut target_gif_index = 0;
-    let mut target_duration = gif_infos[0].duration;
-    for (i, gif_info) in gif_infos.iter().enumerate() {
-        if gif_info.duration < target_duration {
-            target_duration = gif_info.duration;
-            target_gif_index = i;
+    match png_or_gif_plan(&mut images)? {
+        Some(plan) => render_frames(&mut images, &plan, |_, images| func(images)),
+        None => {
+            let images = images
+                .iter_mut()
+                .map(|image| image.first_frame())
+                .collect::<Result<Vec<_>, Error>>()?;
+            encode_static(func(images)?)
         }
     }
-    let target_gif_info = gif_infos[target_gif_index].clone();
-    gif_infos.remove(target_gif_index);
+}
 
-    let (mut frame_indexes, target_frame_indexes) =
-        get_aligned_gif_indexes(&gif_infos, &target_gif_info, FrameAlign::ExtendLoop);
-    let target_frame_num = target_frame_indexes.len();
-    frame_indexes.insert(target_gif_index, target_frame_indexes);
+/// 并行制作 png 或 gif，帧的划分与 [`make_png_or_gif`] 相同
+///
+/// - `images` 图片列表
+/// - `func`: 图片处理函数，传入输出的第几帧和图片列表，返回处理后的图片，会在多个线程中同时调用
+///
+/// 每一帧使用由种子和帧序号派生的随机数
+pub fn make_png_or_gif_parallel<F>(images: Vec<InputImage>, func: F) -> Result<Vec<u8>, Error>
+where
+    F: Fn(usize, Vec<Image>) -> Result<Image, Error> + Sync,
+{
+    let mut images = images
+        .into_iter()
+        .map(|image| image.codec)
+        .collect::<Vec<_>>();
 
-    let mut encoder = GifEncoder::new();
-    for i in 0..target_frame_num {
-        let mut frame_images: Vec<Image> = Vec::new();
-        let mut gif_index = 0;
-        for (j, image) in images.iter_mut().enumerate() {
-            if gif_flags[j] {
-                frame_images.push(image.get_frame(frame_indexes[gif_index][i] as usize)?);
-                gif_index += 1;
-            } else {
-                frame_images.push(image.first_frame()?);
-            }
+    match png_or_gif_plan(&mut images)? {
+        Some(plan) => render_frames_parallel(&mut images, &plan, &func),
+        None => {
+            let images = images
+                .iter_mut()
+                .map(|image| image.first_frame())
+                .collect::<Result<Vec<_>, Error>>()?;
+            encode_static(func(0, images)?)
         }
-        let frame = func(frame_images)?;
-        encoder.add_frame(frame, target_duration)?;
     }
-    Ok(encoder.finish())
 }
 
 /// 使用静图或动图制作 gif
@@ -292,7 +1430,7 @@ where
 ///
 pub fn make_gif_or_combined_gif<F>(
     images: Vec<InputImage>,
-    mut func: F,
+    func: F,
     target_gif_info: GifInfo,
     frame_align: impl Into<Option<FrameAlign>>,
 ) -> Result<Vec<u8>, Error>
@@ -304,50 +1442,27 @@ where
         .map(|image| image.codec)
         .collect::<Vec<_>>();
 
-    let mut gif_flags: Vec<bool> = Vec::new();
-    let mut gif_infos: Vec<GifInfo> = Vec::new();
-    for image in images.iter_mut() {
-        if image.is_multi_frame() {
-            gif_flags.push(true);
-            gif_infos.push(GifInfo {
-                frame_num: image.get_frame_count() as u32,
-                duration: image.get_average_duration()?,
-            });
-        } else {
-            gif_flags.push(false);
-        }
-    }
+    let plan = combined_gif_plan(&mut images, &target_gif_info, frame_align.into())?;
+    render_frames(&mut images, &plan, func)
+}
 
-    if gif_infos.len() == 0 {
-        let mut encoder = GifEncoder::new();
-        for i in 0..target_gif_info.frame_num {
-            let frame_images = images
-                .iter_mut()
-                .map(|image| image.first_frame())
-                .collect::<Result<Vec<_>, Error>>()?;
-            let frame = func(i as usize, frame_images)?;
-            encoder.add_frame(frame, target_gif_info.duration)?;
-        }
-        return Ok(encoder.finish());
-    }
+/// 并行使用静图或动图制作 gif，帧的划分与 [`make_gif_or_combined_gif`] 相同
+///
+/// `func` 会在多个线程中同时调用，每一帧使用由种子和帧序号派生的随机数
+pub fn make_gif_or_combined_gif_parallel<F>(
+    images: Vec<InputImage>,
+    func: F,
+    target_gif_info: GifInfo,
+    frame_align: impl Into<Option<FrameAlign>>,
+) -> Result<Vec<u8>, Error>
+where
+    F: Fn(usize, Vec<Image>) -> Result<Image, Error> + Sync,
+{
+    let mut images = images
+        .into_iter()
+        .map(|image| image.codec)
+        .collect::<Vec<_>>();
 
-    let (frame_indexes, target_frame_indexes) =
-        get_aligned_gif_indexes(&gif_infos, &target_gif_info, frame_align);
-
-    let mut encoder = GifEncoder::new();
-    for (i, target_index) in target_frame_indexes.iter().enumerate() {
-        let mut frame_images: Vec<Image> = Vec::new();
-        let mut gif_index = 0;
-        for (j, image) in images.iter_mut().enumerate() {
-            if gif_flags[j] {
-                frame_images.push(image.get_frame(frame_indexes[gif_index][i] as usize)?);
-                gif_index += 1;
-            } else {
-                frame_images.push(image.first_frame()?);
-            }
-        }
-        let frame = func(*target_index, frame_images)?;
-        encoder.add_frame(frame, target_gif_info.duration)?;
-    }
-    Ok(encoder.finish())
+    let plan = combined_gif_plan(&mut images, &target_gif_info, frame_align.into())?;
+    render_frames_parallel(&mut images, &plan, &func)
 }
@@ -0,0 +1,279 @@
+//! gif 的优化编码：所有帧共用调色板、Floyd–Steinberg 抖动，以及只编码与上一帧不同的区域
+
+use std::borrow::Cow;
+
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Encoder, EncodingError, Frame, Repeat};
+
+/// 透明度低于该值的像素视为透明
+const ALPHA_THRESHOLD: u8 = 128;
+/// 构建调色板时最多采样的像素数
+const MAX_SAMPLE_PIXELS: usize = 500_000;
+
+pub(crate) struct GifOptions {
+    pub global_palette: bool,
+    pub dithering: bool,
+    pub frame_delta: bool,
+    pub speed: i32,
+    /// 调色板的颜色数，包括透明色，2 到 256
+    pub colors: u16,
+}
+
+/// 等待编码的一帧，`data` 为不预乘透明度的 RGBA 像素，`delay` 的单位为 0.01 秒
+pub(crate) struct GifFrame {
+    pub data: Vec<u8>,
+    pub delay: u16,
+}
+
+fn is_transparent(pixel: &[u8]) -> bool {
+    pixel[3] < ALPHA_THRESHOLD
+}
+
+fn same_pixel(a: &[u8], b: &[u8]) -> bool {
+    (is_transparent(a) && is_transparent(b)) || a == b
+}
+
+struct Palette {
+    quant: NeuQuant,
+    /// RGB 颜色表，最后一个颜色保留为透明色
+    colors: Vec<u8>,
+    transparent: u8,
+}
+
+impl Palette {
+    /// 从若干帧的不透明像素中采样构建调色板
+    fn new<'a>(frames: impl Iterator<Item = &'a [u8]> + Clone, options: &GifOptions) -> Self {
+        let total = frames.clone().map(|data| data.len() / 4).sum::<usize>();
+        let step = total.div_ceil(MAX_SAMPLE_PIXELS).max(1);
+        let mut samples = frames
+            .flat_map(|data| data.chunks_exact(4))
+            .step_by(step)
+            .filter(|pixel| !is_transparent(pixel))
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect::<Vec<_>>();
+        if samples.is_empty() {
+            samples = vec![0, 0, 0, 255];
+        }
+        let size = options.colors.clamp(2, 256) as usize;
+        let quant = NeuQuant::new(options.speed.clamp(1, 30), size - 1, &samples);
+        let mut colors = quant.color_map_rgb();
+        colors.resize(size * 3, 0);
+        Self {
+            quant,
+            colors,
+            transparent: (size - 1) as u8,
+        }
+    }
+
+    /// 将 RGBA 像素转换为调色板索引，透明像素使用透明色
+    fn quantize(&self, pixels: &[u8], width: usize, dithering: bool) -> Vec<u8> {
+        let height = pixels.len() / 4 / width;
+        let mut indices = Vec::with_capacity(width * height);
+        // 当前行和下一行累积的误差，两端各留一个位置
+        let mut errors = vec![[0.0f32; 3]; width + 2];
+        let mut next_errors = vec![[0.0f32; 3]; width + 2];
+        for row in pixels.chunks_exact(width * 4) {
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                if is_transparent(pixel) {
+                    indices.push(self.transparent);
+                    continue;
+                }
+                let mut color = [0, 0, 0, 255];
+                for c in 0..3 {
+                    color[c] = (pixel[c] as f32 + errors[x + 1][c])
+                        .round()
+                        .clamp(0.0, 255.0) as u8;
+                }
+                let index = self.quant.index_of(&color) as u8;
+                indices.push(index);
+                if !dithering {
+                    continue;
+                }
+                let mapped = &self.colors[index as usize * 3..][..3];
+                for c in 0..3 {
+                    let error = color[c] as f32 - mapped[c] as f32;
+                    errors[x + 2][c] += error * 7.0 / 16.0;
+                    next_errors[x][c] += error * 3.0 / 16.0;
+                    next_errors[x + 1][c] += error * 5.0 / 16.0;
+                    next_errors[x + 2][c] += error / 16.0;
+                }
+            }
+            if dithering {
+                std::mem::swap(&mut errors, &mut next_errors);
+                next_errors.fill([0.0; 3]);
+            }
+        }
+        indices
+    }
+}
+
+/// 当前帧相对于上一帧的变化
+enum Change {
+    /// 需要完整绘制，上一帧显示后清除
+    Full,
+    /// 与上一帧相同
+    None,
+    /// 只有该区域发生变化，上一帧显示后保留
+    Region {
+        left: usize,
+        top: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+fn diff(previous: &[u8], current: &[u8], width: usize) -> Change {
+    let (mut left, mut top, mut right, mut bottom) = (usize::MAX, usize::MAX, 0, 0);
+    for (i, (a, b)) in previous
+        .chunks_exact(4)
+        .zip(current.chunks_exact(4))
+        .enumerate()
+    {
+        if same_pixel(a, b) {
+            continue;
+        }
+        // 透明的像素无法覆盖上一帧的内容
+        if is_transparent(b) {
+            return Change::Full;
+        }
+        let (x, y) = (i % width, i / width);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    if left == usize::MAX {
+        return Change::None;
+    }
+    Change::Region {
+        left,
+        top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    }
+}
+
+/// 裁剪出变化的区域，未变化的像素设为透明
+fn crop_changed(
+    previous: &[u8],
+    current: &[u8],
+    width: usize,
+    (left, top, region_width, region_height): (usize, usize, usize, usize),
+) -> Vec<u8> {
+    let mut data = Vec::with_capacity(region_width * region_height * 4);
+    for y in top..top + region_height {
+        let start = (y * width + left) * 4;
+        let end = start + region_width * 4;
+        for (a, b) in previous[start..end]
+            .chunks_exact(4)
+            .zip(current[start..end].chunks_exact(4))
+        {
+            if same_pixel(a, b) {
+                data.extend([0, 0, 0, 0]);
+            } else {
+                data.extend(b);
+            }
+        }
+    }
+    data
+}
+
+/// 编码所有帧，返回 gif 数据和实际写入的各帧的帧间隔，与上一帧相同的帧合并到上一帧中
+pub(crate) fn encode_gif(
+    frames: &[GifFrame],
+    width: u16,
+    height: u16,
+    options: &GifOptions,
+) -> Result<(Vec<u8>, Vec<u16>), EncodingError> {
+    let global = options
+        .global_palette
+        .then(|| Palette::new(frames.iter().map(|frame| frame.data.as_slice()), options));
+    let global_colors = global.as_ref().map_or(&[][..], |palette| &palette.colors);
+    let mut encoder = Encoder::new(Vec::new(), width, height, global_colors)?;
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let make_frame = |data: &[u8], left: usize, top: usize, width: usize, height: usize| {
+        let local;
+        let palette = match &global {
+            Some(palette) => palette,
+            None => {
+                local = Palette::new(std::iter::once(data), options);
+                &local
+            }
+        };
+        Frame {
+            left: left as u16,
+            top: top as u16,
+            width: width as u16,
+            height: height as u16,
+            buffer: Cow::Owned(palette.quantize(data, width, options.dithering)),
+            palette: global.is_none().then(|| palette.colors.clone()),
+            transparent: Some(palette.transparent),
+            dispose: DisposalMethod::Background,
+            ..Frame::default()
+        }
+    };
+
+    let width = width as usize;
+    let height = height as usize;
+    // 上一帧的处置方式取决于当前帧，因此延后一帧写入
+    let mut pending: Option<Frame> = None;
+    let mut previous: Option<&[u8]> = None;
+    let mut delays = Vec::new();
+    for frame in frames {
+        let delay = frame.delay;
+        let change = match previous {
+            Some(previous) if options.frame_delta => diff(previous, &frame.data, width),
+            _ => Change::Full,
+        };
+        let mut next = match change {
+            Change::None => {
+                // 合并到上一帧的时长中
+                if let Some(pending) = pending.as_mut() {
+                    pending.delay = pending.delay.saturating_add(delay);
+                }
+                continue;
+            }
+            Change::Full => {
+                // 上一帧只记录了变化的区域时，处置只会清除该区域，改为完整的一帧以清除整个画布
+                let region = pending.as_mut().filter(|pending| {
+                    (pending.width as usize, pending.height as usize) != (width, height)
+                });
+                if let (Some(pending), Some(previous)) = (region, previous) {
+                    let delay = pending.delay;
+                    *pending = make_frame(previous, 0, 0, width, height);
+                    pending.delay = delay;
+                }
+                make_frame(&frame.data, 0, 0, width, height)
+            }
+            Change::Region {
+                left,
+                top,
+                width: region_width,
+                height: region_height,
+            } => {
+                let data = crop_changed(
+                    previous.unwrap_or_default(),
+                    &frame.data,
+                    width,
+                    (left, top, region_width, region_height),
+                );
+                if let Some(pending) = pending.as_mut() {
+                    pending.dispose = DisposalMethod::Keep;
+                }
+                make_frame(&data, left, top, region_width, region_height)
+            }
+        };
+        next.delay = delay;
+        if let Some(pending) = pending.replace(next) {
+            encoder.write_frame(&pending)?;
+            delays.push(pending.delay);
+        }
+        previous = Some(&frame.data);
+    }
+    if let Some(pending) = pending {
+        encoder.write_frame(&pending)?;
+        delays.push(pending.delay);
+    }
+    Ok((encoder.into_inner()?, delays))
+}
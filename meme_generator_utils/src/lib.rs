@@ -4,6 +4,11 @@ pub mod canvas;
 pub mod config;
 pub mod decoder;
 pub mod encoder;
+mod gif_optimizer;
 pub mod image;
+pub mod random;
+pub mod template;
 pub mod text;
+mod text_path;
 pub mod tools;
+mod vertical;
@@ -0,0 +1,103 @@
+//! 表情生成使用的随机数
+//!
+//! 表情中需要随机数时应使用 [`rng`] 而不是 `rand::thread_rng`，
+//! 生成时由 [`MemeBuilder`](crate::builder::MemeBuilder) 以传入的种子初始化，相同的种子得到相同的结果。
+//! 并行渲染动图时，每一帧使用由种子和帧序号派生的种子，结果与线程数无关。
+
+use std::cell::RefCell;
+
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+
+thread_local! {
+    static SEEDED_RNG: RefCell<Option<SeededRng>> = const { RefCell::new(None) };
+}
+
+struct SeededRng {
+    seed: i32,
+    rng: StdRng,
+    used: bool,
+}
+
+/// 当前表情的随机数生成器，在 [`with_seed`] 之外使用时等同于 `rand::thread_rng`
+#[derive(Debug, Clone, Copy)]
+pub struct MemeRng;
+
+impl MemeRng {
+    fn with<T>(&mut self, func: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        SEEDED_RNG.with_borrow_mut(|seeded| match seeded {
+            Some(seeded) => {
+                seeded.used = true;
+                func(&mut seeded.rng)
+            }
+            None => func(&mut rand::thread_rng()),
+        })
+    }
+}
+
+impl RngCore for MemeRng {
+    fn next_u32(&mut self) -> u32 {
+        self.with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+pub fn rng() -> MemeRng {
+    MemeRng
+}
+
+/// 随机选取一个种子
+pub fn random_seed() -> i32 {
+    rand::thread_rng().gen_range(0..=i32::MAX)
+}
+
+/// 以 `seed` 初始化当前线程的随机数生成器并执行 `func`，同时返回 `func` 是否使用了随机数
+pub fn with_seed<T>(seed: i32, func: impl FnOnce() -> T) -> (T, bool) {
+    struct Restore(Option<SeededRng>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SEEDED_RNG.set(self.0.take());
+        }
+    }
+
+    let seeded = SeededRng {
+        seed,
+        rng: StdRng::seed_from_u64(seed as u32 as u64),
+        used: false,
+    };
+    let _restore = Restore(SEEDED_RNG.replace(Some(seeded)));
+    let result = func();
+    let used = SEEDED_RNG.with_borrow(|seeded| seeded.as_ref().is_some_and(|seeded| seeded.used));
+    (result, used)
+}
+
+/// 当前的种子，在 [`with_seed`] 之外为 `None`
+pub(crate) fn current_seed() -> Option<i32> {
+    SEEDED_RNG.with_borrow(|seeded| seeded.as_ref().map(|seeded| seeded.seed))
+}
+
+/// 标记当前的种子已被使用，用于在其他线程中使用了派生的种子时
+pub(crate) fn mark_used() {
+    SEEDED_RNG.with_borrow_mut(|seeded| {
+        if let Some(seeded) = seeded {
+            seeded.used = true;
+        }
+    });
+}
+
+/// 由种子派生第 `index` 帧的种子
+pub(crate) fn frame_seed(seed: i32, index: usize) -> i32 {
+    let mut rng = StdRng::seed_from_u64(((seed as u32 as u64) << 32) | index as u32 as u64);
+    rng.gen_range(0..=i32::MAX)
+}
@@ -0,0 +1,728 @@
+//! 声明式模板表情
+//!
+//! 模板以 TOML 或 JSON 描述表情的参数、帧和图层，无需编写 Rust 代码。
+//! 每一帧按顺序绘制图层，图层的 `placements`、`rotations`、`areas` 按帧循环取值，
+//! 只有一项时对所有帧生效。素材路径中的 `{frame}` 会被替换为当前帧的序号。
+//!
+//! ```toml
+//! key = "petpet_template"
+//! min_images = 1
+//! max_images = 1
+//! keywords = ["摸摸"]
+//! date_created = "2021-08-01T00:00:00+08:00"
+//! options = [{ type = "boolean", name = "circle", default = false }]
+//!
+//! [frames]
+//! count = 5
+//! duration = 0.06
+//! align = "extend_loop"
+//!
+//! [[layers]]
+//! type = "image"
+//! index = 0
+//! circle = "circle"
+//! placements = [[14, 20, 98, 98], [12, 33, 101, 85], [8, 40, 110, 76], [10, 33, 102, 84], [12, 20, 98, 98]]
+//!
+//! [[layers]]
+//! type = "asset"
+//! path = "petpet/{frame}.png"
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use skia_safe::{Canvas, Data, ISize, Image, Matrix, Point, Rect, textlayout::TextAlign};
+
+use meme_generator_core::{
+    error::Error,
+    format::OutputFormat,
+    meme::{
+        self, GeneratedImage, Meme, MemeInfo, MemeOption, MemeParams, MemeShortcut, OptionValue,
+    },
+};
+
+use crate::{
+    builder::{InputImage, decode_inputs, locate_text_error, preview_inputs},
+    canvas::CanvasExt,
+    encoder::{
+        FrameAlign, GifInfo, capture_encoded, fit_output_size, make_gif_or_combined_gif,
+        make_png_or_gif, with_output_format,
+    },
+    image::{Fit, ImageExt},
+    text::{TextParams, WritingMode},
+    tools::{
+        color_from_str, default_sampling_options, load_image, new_paint, new_stroke_paint,
+        new_surface,
+    },
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MemeTemplate {
+    pub key: String,
+    pub min_images: u8,
+    pub max_images: u8,
+    pub min_texts: u8,
+    pub max_texts: u8,
+    pub default_texts: Vec<String>,
+    pub options: Vec<MemeOption>,
+    pub keywords: Vec<String>,
+    pub shortcuts: Vec<MemeShortcut>,
+    pub tags: HashSet<String>,
+    pub date_created: Option<DateTime<Local>>,
+    pub date_modified: Option<DateTime<Local>>,
+
+    /// 画布大小，未指定时使用第一个素材图层的大小
+    pub size: Option<(i32, i32)>,
+
+    /// 背景颜色，未指定时为透明
+    pub background: Option<String>,
+
+    pub frames: TemplateFrames,
+    pub layers: Vec<TemplateLayer>,
+}
+
+impl Default for MemeTemplate {
+    fn default() -> Self {
+        MemeTemplate {
+            key: String::new(),
+            min_images: 0,
+            max_images: 0,
+            min_texts: 0,
+            max_texts: 0,
+            default_texts: Vec::new(),
+            options: Vec::new(),
+            keywords: Vec::new(),
+            shortcuts: Vec::new(),
+            tags: HashSet::new(),
+            date_created: None,
+            date_modified: None,
+            size: None,
+            background: None,
+            frames: TemplateFrames::default(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TemplateFrames {
+    /// 帧数，为 1 时生成静图，传入动图时仍会生成 gif
+    pub count: u32,
+
+    /// 帧间隔，单位为秒
+    pub duration: f32,
+
+    /// 传入动图时的对齐方式
+    pub align: FrameAlign,
+}
+
+impl Default for TemplateFrames {
+    fn default() -> Self {
+        TemplateFrames {
+            count: 1,
+            duration: 0.1,
+            align: FrameAlign::ExtendLoop,
+        }
+    }
+}
+
+/// 图层条件，可以是固定的布尔值或布尔选项的名称
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateCondition {
+    Fixed(bool),
+    Option(String),
+}
+
+impl TemplateCondition {
+    fn evaluate(&self, options: &HashMap<String, OptionValue>) -> bool {
+        match self {
+            TemplateCondition::Fixed(value) => *value,
+            TemplateCondition::Option(name) => {
+                matches!(options.get(name), Some(OptionValue::Boolean(true)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TemplateLayer {
+    Asset(AssetLayer),
+    Image(ImageLayer),
+    Text(TextLayer),
+}
+
+/// 素材图片，从模板目录或图片资源目录加载
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AssetLayer {
+    pub path: String,
+    pub position: (f32, f32),
+    pub size: Option<(i32, i32)>,
+
+    /// 绘制该图层的帧，未指定时绘制所有帧
+    pub frames: Option<Vec<usize>>,
+    pub when: TemplateCondition,
+}
+
+impl Default for AssetLayer {
+    fn default() -> Self {
+        AssetLayer {
+            path: String::new(),
+            position: (0.0, 0.0),
+            size: None,
+            frames: None,
+            when: TemplateCondition::Fixed(true),
+        }
+    }
+}
+
+/// 图片位置，可以是矩形 `[x, y, w, h]` 或透视变换的四个顶点（左上、右上、右下、左下）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Placement {
+    Rect(f32, f32, f32, f32),
+    Quad([(f32, f32); 4]),
+}
+
+/// 用户传入的图片
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImageLayer {
+    /// 图片在传入图片列表中的位置，未传入时跳过该图层
+    pub index: usize,
+    pub placements: Vec<Placement>,
+
+    /// 以矩形中心为原点的旋转角度，仅对矩形位置生效
+    pub rotations: Vec<f32>,
+
+    /// 是否裁剪为圆形
+    pub circle: TemplateCondition,
+    pub frames: Option<Vec<usize>>,
+    pub when: TemplateCondition,
+}
+
+impl Default for ImageLayer {
+    fn default() -> Self {
+        ImageLayer {
+            index: 0,
+            placements: Vec::new(),
+            rotations: Vec::new(),
+            circle: TemplateCondition::Fixed(false),
+            frames: None,
+            when: TemplateCondition::Fixed(true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateTextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl From<TemplateTextAlign> for TextAlign {
+    fn from(align: TemplateTextAlign) -> Self {
+        match align {
+            TemplateTextAlign::Left => TextAlign::Left,
+            TemplateTextAlign::Center => TextAlign::Center,
+            TemplateTextAlign::Right => TextAlign::Right,
+        }
+    }
+}
+
+/// 用户传入的文字，在文字区域内自动调整字号
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TextLayer {
+    /// 文字在传入文字列表中的位置，未传入时跳过该图层
+    pub index: usize,
+
+    /// 文字区域 `[x, y, w, h]`
+    pub areas: Vec<(f32, f32, f32, f32)>,
+    pub min_font_size: f32,
+    pub max_font_size: f32,
+    pub color: String,
+    pub stroke_color: Option<String>,
+    pub stroke_width: f32,
+    pub font_families: Vec<String>,
+    pub align: TemplateTextAlign,
+    pub bbcode: bool,
+
+    /// 竖排，从上到下、从右到左
+    pub vertical: bool,
+    pub frames: Option<Vec<usize>>,
+    pub when: TemplateCondition,
+}
+
+impl Default for TextLayer {
+    fn default() -> Self {
+        TextLayer {
+            index: 0,
+            areas: Vec::new(),
+            min_font_size: 10.0,
+            max_font_size: 60.0,
+            color: "black".to_string(),
+            stroke_color: None,
+            stroke_width: 0.0,
+            font_families: Vec::new(),
+            align: TemplateTextAlign::Center,
+            bbcode: false,
+            vertical: false,
+            frames: None,
+            when: TemplateCondition::Fixed(true),
+        }
+    }
+}
+
+/// 按帧循环取值
+fn pick<T>(values: &[T], frame: usize) -> Option<&T> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(&values[frame % values.len()])
+    }
+}
+
+fn is_visible(
+    frames: &Option<Vec<usize>>,
+    when: &TemplateCondition,
+    frame: usize,
+    options: &HashMap<String, OptionValue>,
+) -> bool {
+    frames.as_ref().is_none_or(|frames| frames.contains(&frame)) && when.evaluate(options)
+}
+
+fn default_value(option: &MemeOption) -> Option<OptionValue> {
+    match option {
+        MemeOption::Boolean { default, .. } => default.map(OptionValue::Boolean),
+        MemeOption::String { default, .. } => default.clone().map(OptionValue::String),
+        MemeOption::Integer { default, .. } => default.map(OptionValue::Integer),
+        MemeOption::Float { default, .. } => default.map(OptionValue::Float),
+    }
+}
+
+/// 画布、素材和图片位置的最大边长
+const MAX_TEMPLATE_SIZE: f32 = 4096.0;
+
+fn is_valid_size(w: f32, h: f32) -> bool {
+    w > 0.0 && h > 0.0 && w <= MAX_TEMPLATE_SIZE && h <= MAX_TEMPLATE_SIZE
+}
+
+fn is_invalid_rect(placement: &Placement) -> bool {
+    matches!(placement, Placement::Rect(_, _, w, h) if !is_valid_size(*w, *h))
+}
+
+/// 一次生成中已解码的素材，以替换帧序号后的路径和缩放尺寸为键
+type AssetCache = HashMap<(String, Option<(i32, i32)>), Image>;
+
+pub struct TemplateMeme {
+    template: MemeTemplate,
+    asset_dir: Option<PathBuf>,
+    date_created: DateTime<Local>,
+    date_modified: DateTime<Local>,
+}
+
+impl TemplateMeme {
+    /// 检查模板并创建表情，`asset_dir` 为素材的查找目录，找不到时从图片资源目录加载
+    pub fn new(
+        template: MemeTemplate,
+        asset_dir: impl Into<Option<PathBuf>>,
+    ) -> Result<Self, Error> {
+        let invalid = |reason: String| {
+            Err(Error::DeserializeError(format!(
+                "Invalid template `{}`: {reason}",
+                template.key
+            )))
+        };
+        if template.key.is_empty() {
+            return invalid("key is empty".to_string());
+        }
+        if template.min_images > template.max_images || template.min_texts > template.max_texts {
+            return invalid("minimum number is greater than maximum".to_string());
+        }
+        if template.frames.count == 0
+            || template.frames.duration.is_nan()
+            || template.frames.duration <= 0.0
+        {
+            return invalid("frame count and duration must be positive".to_string());
+        }
+        if template
+            .size
+            .is_some_and(|(w, h)| !is_valid_size(w as f32, h as f32))
+        {
+            return invalid(format!(
+                "size must be positive and at most {MAX_TEMPLATE_SIZE}"
+            ));
+        }
+        if template.size.is_none()
+            && !template
+                .layers
+                .iter()
+                .any(|layer| matches!(layer, TemplateLayer::Asset(_)))
+        {
+            return invalid("either `size` or an asset layer is required".to_string());
+        }
+        for layer in &template.layers {
+            match layer {
+                TemplateLayer::Asset(layer)
+                    if layer
+                        .size
+                        .is_some_and(|(w, h)| !is_valid_size(w as f32, h as f32)) =>
+                {
+                    return invalid(format!("asset `{}` has an invalid size", layer.path));
+                }
+                TemplateLayer::Image(layer) if layer.placements.is_empty() => {
+                    return invalid(format!("image layer {} has no placements", layer.index));
+                }
+                TemplateLayer::Image(layer) if layer.index >= template.max_images as usize => {
+                    return invalid(format!("image index {} out of range", layer.index));
+                }
+                TemplateLayer::Image(layer) if layer.placements.iter().any(is_invalid_rect) => {
+                    let index = layer.index;
+                    return invalid(format!("image layer {index} has an invalid placement"));
+                }
+                TemplateLayer::Text(layer) if layer.areas.is_empty() => {
+                    return invalid(format!("text layer {} has no areas", layer.index));
+                }
+                TemplateLayer::Text(layer) if layer.index >= template.max_texts as usize => {
+                    return invalid(format!("text index {} out of range", layer.index));
+                }
+                TemplateLayer::Text(layer)
+                    if layer
+                        .areas
+                        .iter()
+                        .any(|(_, _, w, h)| !is_valid_size(*w, *h)) =>
+                {
+                    return invalid(format!("text layer {} has an invalid area", layer.index));
+                }
+                TemplateLayer::Text(layer)
+                    if !(layer.min_font_size > 0.0
+                        && layer.min_font_size <= layer.max_font_size) =>
+                {
+                    return invalid(format!("text layer {} has invalid font sizes", layer.index));
+                }
+                TemplateLayer::Text(layer)
+                    if !(0.0..=layer.max_font_size).contains(&layer.stroke_width) =>
+                {
+                    return invalid(format!(
+                        "stroke width of text layer {} must be between 0 and the maximum font size",
+                        layer.index
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let date_created = template.date_created.unwrap_or_else(Local::now);
+        let date_modified = template.date_modified.unwrap_or(date_created);
+        Ok(Self {
+            template,
+            asset_dir: asset_dir.into(),
+            date_created,
+            date_modified,
+        })
+    }
+
+    fn params(&self) -> MemeParams {
+        MemeParams {
+            min_images: self.template.min_images,
+            max_images: self.template.max_images,
+            min_texts: self.template.min_texts,
+            max_texts: self.template.max_texts,
+            default_texts: self.template.default_texts.clone(),
+            options: self.template.options.clone(),
+        }
+    }
+
+    fn load_asset(&self, path: String) -> Result<Image, Error> {
+        if let Some(asset_path) = self.asset_dir.as_ref().map(|dir| dir.join(&path)) {
+            if asset_path.is_file() {
+                let data =
+                    fs::read(&asset_path).map_err(|_| Error::ImageAssetMissing(path.clone()))?;
+                return Image::from_encoded(Data::new_copy(&data)).ok_or(Error::ImageDecodeError(
+                    format!("Failed to decode image: {path}"),
+                ));
+            }
+        }
+        load_image(path)
+    }
+
+    /// 素材在每次生成中只解码一次，之后的帧从 `assets` 中取用
+    fn load_asset_layer(
+        &self,
+        assets: &mut AssetCache,
+        layer: &AssetLayer,
+        frame: usize,
+    ) -> Result<Image, Error> {
+        let path = layer.path.replace("{frame}", &frame.to_string());
+        let key = (path, layer.size);
+        if let Some(image) = assets.get(&key) {
+            return Ok(image.clone());
+        }
+        let image = self.load_asset(key.0.clone())?;
+        let image = match layer.size {
+            Some(size) => image.resize_exact(size),
+            None => image,
+        };
+        assets.insert(key, image.clone());
+        Ok(image)
+    }
+
+    fn canvas_size(&self, assets: &mut AssetCache, frame: usize) -> Result<ISize, Error> {
+        if let Some(size) = self.template.size {
+            return Ok(ISize::from(size));
+        }
+        let layer = self
+            .template
+            .layers
+            .iter()
+            .find_map(|layer| match layer {
+                TemplateLayer::Asset(layer) => Some(layer),
+                _ => None,
+            })
+            .ok_or(Error::ImageAssetMissing(self.template.key.clone()))?;
+        Ok(self.load_asset_layer(assets, layer, frame)?.dimensions())
+    }
+
+    fn draw_image_layer(
+        &self,
+        canvas: &Canvas,
+        layer: &ImageLayer,
+        frame: usize,
+        images: &[Image],
+        options: &HashMap<String, OptionValue>,
+    ) {
+        let Some(image) = images.get(layer.index) else {
+            return;
+        };
+        let image = if layer.circle.evaluate(options) {
+            image.circle()
+        } else {
+            image.clone()
+        };
+        match pick(&layer.placements, frame) {
+            Some(Placement::Rect(x, y, w, h)) => {
+                let image = image.resize_fit((w.round() as i32, h.round() as i32), Fit::Cover);
+                match pick(&layer.rotations, frame) {
+                    Some(degrees) if *degrees != 0.0 => {
+                        let rotated = image.rotate(*degrees);
+                        let left = x + (w - rotated.width() as f32) / 2.0;
+                        let top = y + (h - rotated.height() as f32) / 2.0;
+                        canvas.draw_image(&rotated, (left, top), None);
+                    }
+                    _ => {
+                        canvas.draw_image(&image, (*x, *y), None);
+                    }
+                }
+            }
+            Some(Placement::Quad(points)) => {
+                let (w, h) = (image.width() as f32, image.height() as f32);
+                let src = [
+                    Point::new(0.0, 0.0),
+                    Point::new(w, 0.0),
+                    Point::new(w, h),
+                    Point::new(0.0, h),
+                ];
+                let dst = points.map(Point::from);
+                // 顶点共线时无法变换，跳过该图层
+                if let Some(matrix) = Matrix::from_poly_to_poly(&src, &dst) {
+                    canvas.save();
+                    canvas.concat(&matrix);
+                    canvas.draw_image_with_sampling_options(
+                        &image,
+                        (0, 0),
+                        default_sampling_options(),
+                        None,
+                    );
+                    canvas.restore();
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn draw_text_layer(
+        &self,
+        canvas: &Canvas,
+        layer: &TextLayer,
+        frame: usize,
+        texts: &[String],
+    ) -> Result<(), Error> {
+        let (Some(text), Some((x, y, w, h))) = (texts.get(layer.index), pick(&layer.areas, frame))
+        else {
+            return Ok(());
+        };
+        let text_params = TextParams {
+            font_families: layer.font_families.clone(),
+            text_align: layer.align.into(),
+            paint: new_paint(color_from_str(&layer.color)),
+            stroke_paint: layer
+                .stroke_color
+                .as_ref()
+                .map(|color| new_stroke_paint(color_from_str(color), layer.stroke_width)),
+            writing_mode: if layer.vertical {
+                WritingMode::Vertical
+            } else {
+                WritingMode::Horizontal
+            },
+            ..Default::default()
+        };
+        let rect = Rect::from_xywh(*x, *y, *w, *h);
+        if layer.bbcode {
+            canvas.draw_bbcode_text_area_auto_font_size(
+                rect,
+                text,
+                layer.min_font_size,
+                layer.max_font_size,
+                text_params,
+            )
+        } else {
+            canvas.draw_text_area_auto_font_size(
+                rect,
+                text,
+                layer.min_font_size,
+                layer.max_font_size,
+                text_params,
+            )
+        }
+    }
+
+    fn render_frame(
+        &self,
+        assets: &mut AssetCache,
+        frame: usize,
+        images: &[Image],
+        texts: &[String],
+        options: &HashMap<String, OptionValue>,
+    ) -> Result<Image, Error> {
+        let mut surface = new_surface(self.canvas_size(assets, frame)?);
+        let canvas = surface.canvas();
+        if let Some(background) = &self.template.background {
+            canvas.clear(color_from_str(background));
+        }
+        for layer in &self.template.layers {
+            match layer {
+                TemplateLayer::Asset(layer) => {
+                    if is_visible(&layer.frames, &layer.when, frame, options) {
+                        let image = self.load_asset_layer(assets, layer, frame)?;
+                        canvas.draw_image(&image, layer.position, None);
+                    }
+                }
+                TemplateLayer::Image(layer) => {
+                    if is_visible(&layer.frames, &layer.when, frame, options) {
+                        self.draw_image_layer(canvas, layer, frame, images, options);
+                    }
+                }
+                TemplateLayer::Text(layer) => {
+                    if is_visible(&layer.frames, &layer.when, frame, options) {
+                        self.draw_text_layer(canvas, layer, frame, texts)?;
+                    }
+                }
+            }
+        }
+        Ok(surface.image_snapshot())
+    }
+
+    fn render(
+        &self,
+        images: Vec<InputImage>,
+        texts: &[String],
+        options: &HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        let frames = &self.template.frames;
+        let mut assets = AssetCache::new();
+        let mut func = |frame: usize, images: Vec<Image>| {
+            self.render_frame(&mut assets, frame, &images, texts, options)
+        };
+        if frames.count > 1 {
+            make_gif_or_combined_gif(
+                images,
+                func,
+                GifInfo {
+                    frame_num: frames.count,
+                    duration: frames.duration,
+                },
+                frames.align,
+            )
+        } else {
+            make_png_or_gif(images, |images| func(0, images))
+        }
+    }
+}
+
+impl Meme for TemplateMeme {
+    fn key(&self) -> String {
+        self.template.key.clone()
+    }
+
+    fn info(&self) -> MemeInfo {
+        MemeInfo {
+            key: self.template.key.clone(),
+            pack: String::new(),
+            params: self.params(),
+            keywords: self.template.keywords.clone(),
+            shortcuts: self.template.shortcuts.clone(),
+            tags: self.template.tags.clone(),
+            date_created: self.date_created,
+            date_modified: self.date_modified,
+        }
+    }
+
+    fn generate(
+        &self,
+        images: Vec<meme::Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        self.generate_image(images, texts, options).map(Into::into)
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        self.generate_preview_image(options).map(Into::into)
+    }
+
+    fn generate_image(
+        &self,
+        images: Vec<meme::Image>,
+        texts: Vec<String>,
+        mut options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        let input_images = decode_inputs(&self.params(), &images, &texts, &options)?;
+        for option in &self.template.options {
+            if let Some(value) = default_value(option) {
+                options.entry(option.name().to_string()).or_insert(value);
+            }
+        }
+        let names = images
+            .iter()
+            .map(|image| image.name.clone())
+            .collect::<Vec<_>>();
+        let output = OutputFormat::from_options(&options)?;
+        with_output_format(output, || {
+            let (result, encoded) = capture_encoded(|| {
+                self.render(input_images, &texts, &options)
+                    .map_err(|err| locate_text_error(err, &texts, &names))
+            });
+            fit_output_size(result?, encoded)
+        })
+    }
+
+    fn generate_preview_image(
+        &self,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<GeneratedImage, Error> {
+        let (images, texts) = preview_inputs(&self.params())?;
+        self.generate_image(images, texts, options)
+    }
+}
@@ -1,35 +1,54 @@
 use std::{
-    collections::VecDeque,
-    sync::{LazyLock, Mutex},
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    sync::{Arc, LazyLock, RwLock},
 };
 
 use skia_safe::{
-    Canvas, Color, FontMgr, FontStyle, Paint, Point, scalar,
+    Canvas, Color, FontMgr, FontStyle, GlyphId, Image, Paint, Picture, PictureRecorder, Point,
+    Rect, Typeface, Unichar, scalar,
     textlayout::{
-        FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextAlign, TextDecoration,
-        TextStyle, TypefaceFontProvider,
+        FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, PlaceholderAlignment,
+        PlaceholderStyle, TextAlign, TextBaseline, TextDecoration, TextDecorationStyle, TextStyle,
+        TypefaceFontProvider,
     },
 };
-use tracing::warn;
+use tracing::{Level, debug, enabled, warn};
+
+use meme_generator_core::error::Error;
 
 use crate::{
     config::{CONFIG, FONTS_DIR},
-    tools::{color_from_str, new_decoration, new_paint, new_stroke_paint},
+    tools::{default_sampling_options, new_decoration, new_paint, new_stroke_paint, parse_color},
+    vertical::{VerticalText, is_attached},
 };
 
-static FONT_MANAGER: LazyLock<Mutex<FontManager>> =
-    LazyLock::new(|| Mutex::new(FontManager::init()));
+/// 字体目录中的字体，只读取一次，由所有线程共享
+struct LocalFonts {
+    /// 每次重新读取时递增，各线程据此重建自己的字体集合
+    generation: u64,
+    typefaces: Vec<Typeface>,
+}
+
+static LOCAL_FONTS: LazyLock<RwLock<Arc<LocalFonts>>> = LazyLock::new(|| {
+    RwLock::new(Arc::new(LocalFonts {
+        generation: 0,
+        typefaces: load_local_typefaces(),
+    }))
+});
 
-struct FontManager {
-    font_collection: FontCollection,
+thread_local! {
+    /// `FontCollection` 带有段落缓存，不能在线程间共享，每个线程使用各自的字体集合
+    static FONT_COLLECTION: RefCell<Option<(u64, FontCollection)>> = const { RefCell::new(None) };
 }
 
-fn construct_font_provider() -> TypefaceFontProvider {
-    let mut font_provider = TypefaceFontProvider::new();
-    let font_mgr = FontMgr::new();
-    if !FONTS_DIR.exists() {
-        return font_provider;
+fn load_local_typefaces() -> Vec<Typeface> {
+    let mut typefaces = Vec::new();
+    if !CONFIG.font.use_local_fonts || !FONTS_DIR.exists() {
+        return typefaces;
     }
+    let font_mgr = FontMgr::new();
     let entries = FONTS_DIR.read_dir();
     if let Ok(entries) = entries {
         for entry in entries {
@@ -43,7 +62,7 @@ fn construct_font_provider() -> TypefaceFontProvider {
                         }
                         if let Ok(bytes) = std::fs::read(path.clone()) {
                             if let Some(font) = font_mgr.new_from_data(&bytes, None) {
-                                font_provider.register_typeface(font, None);
+                                typefaces.push(font);
                             } else {
                                 warn!("Failed to create typeface from font file: {path:?}",);
                             }
@@ -53,31 +72,228 @@ fn construct_font_provider() -> TypefaceFontProvider {
             }
         }
     }
-    font_provider
+    typefaces
+}
+
+fn new_font_collection(typefaces: &[Typeface]) -> FontCollection {
+    let font_mgr = FontMgr::new();
+    let mut font_collection = FontCollection::new();
+    font_collection.set_default_font_manager(font_mgr, None);
+
+    if CONFIG.font.use_local_fonts {
+        let mut font_provider = TypefaceFontProvider::new();
+        for typeface in typefaces {
+            font_provider.register_typeface(typeface.clone(), None);
+        }
+        font_collection.set_asset_font_manager(FontMgr::from(font_provider));
+    }
+    font_collection
 }
 
-impl FontManager {
-    pub fn init() -> Self {
-        let font_mgr = FontMgr::new();
-        let mut font_collection = FontCollection::new();
-        font_collection.set_default_font_manager(font_mgr, None);
+/// 当前线程的字体集合，字体重新读取后会在下次使用时重建
+pub(crate) fn font_collection() -> FontCollection {
+    let fonts = LOCAL_FONTS.read().unwrap().clone();
+    FONT_COLLECTION.with_borrow_mut(|cached| match cached {
+        Some((generation, font_collection)) if *generation == fonts.generation => {
+            font_collection.clone()
+        }
+        _ => {
+            let font_collection = new_font_collection(&fonts.typefaces);
+            *cached = Some((fonts.generation, font_collection.clone()));
+            font_collection
+        }
+    })
+}
+
+/// 重新读取字体目录，之后所有线程生成文字时都使用新的字体
+pub fn reload_fonts() {
+    let typefaces = load_local_typefaces();
+    let mut fonts = LOCAL_FONTS.write().unwrap();
+    *fonts = Arc::new(LocalFonts {
+        generation: fonts.generation + 1,
+        typefaces,
+    });
+}
+
+/// 文字中字符的字形覆盖情况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlyphCoverage {
+    /// 指定的字体和默认字体中都没有，由其他字体回退绘制的字符
+    pub fallback: Vec<char>,
+    /// 所有字体中都没有，会绘制为方框的字符
+    pub missing: Vec<char>,
+}
+
+impl GlyphCoverage {
+    pub fn is_complete(&self) -> bool {
+        self.fallback.is_empty() && self.missing.is_empty()
+    }
+}
 
-        if CONFIG.font.use_local_fonts {
-            let font_provider = construct_font_provider();
-            font_collection.set_asset_font_manager(FontMgr::from(font_provider));
+/// 检查文字中的字符在 `font_families` 和默认字体中是否有字形
+pub fn check_glyph_coverage(text: &str, font_families: &[String]) -> GlyphCoverage {
+    let mut font_families = font_families.to_vec();
+    font_families.append(&mut CONFIG.font.default_font_families.clone());
+    let mut font_collection = font_collection();
+    // 找不到时会返回回退字体，只保留名称匹配的字体
+    let typefaces = font_collection
+        .find_typefaces(&font_families, FontStyle::normal())
+        .into_iter()
+        .filter(|typeface| {
+            let family_name = typeface.family_name();
+            font_families
+                .iter()
+                .any(|family| family_name.eq_ignore_ascii_case(family))
+        })
+        .collect::<Vec<_>>();
+    let local_fonts = LOCAL_FONTS.read().unwrap().clone();
+
+    let mut coverage = GlyphCoverage::default();
+    let mut checked = HashSet::new();
+    for c in text.chars() {
+        if c.is_whitespace() || c.is_control() || is_attached(c) || !checked.insert(c) {
+            continue;
         }
+        let has_glyph = |typeface: &Typeface| typeface.unichar_to_glyph(c as Unichar) != 0;
+        if typefaces.iter().any(has_glyph) {
+            continue;
+        }
+        if local_fonts.typefaces.iter().any(has_glyph)
+            || font_collection
+                .default_fallback_char(c as Unichar, FontStyle::normal(), "")
+                .is_some()
+        {
+            coverage.fallback.push(c);
+        } else {
+            coverage.missing.push(c);
+        }
+    }
+    coverage
+}
 
+/// 记录文字中由回退字体绘制的字符，只在开启调试日志时检查
+fn report_fallback(text: &str, font_families: &[String]) {
+    if !enabled!(Level::DEBUG) {
+        return;
+    }
+    let coverage = check_glyph_coverage(text, font_families);
+    if !coverage.fallback.is_empty() {
+        let chars = coverage.fallback.iter().collect::<String>();
+        debug!("Text {text:?} uses fallback fonts for characters: {chars}");
+    }
+}
+
+/// 已加载的字体及其覆盖的字符，用于排查字体配置
+#[derive(Debug, Clone)]
+pub struct TypefaceInfo {
+    pub family_name: String,
+    pub font_style: FontStyle,
+    /// 是否来自字体目录，否则为默认字体中的系统字体
+    pub local: bool,
+    pub glyph_count: usize,
+    /// 有字形的 Unicode 码位区间
+    pub ranges: Vec<RangeInclusive<u32>>,
+}
+
+impl TypefaceInfo {
+    fn new(typeface: &Typeface, local: bool) -> Self {
         Self {
-            font_collection: font_collection,
+            family_name: typeface.family_name(),
+            font_style: typeface.font_style(),
+            local,
+            glyph_count: typeface.count_glyphs(),
+            ranges: unicode_ranges(typeface),
+        }
+    }
+
+    /// 有字形的字符数
+    pub fn char_count(&self) -> u32 {
+        self.ranges
+            .iter()
+            .map(|range| range.end() - range.start() + 1)
+            .sum()
+    }
+}
+
+fn unicode_ranges(typeface: &Typeface) -> Vec<RangeInclusive<u32>> {
+    const CHUNK: u32 = 0x1000;
+    let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    let mut glyphs = [0 as GlyphId; CHUNK as usize];
+    for start in (0..=char::MAX as u32).step_by(CHUNK as usize) {
+        let unichars = (start..start + CHUNK)
+            .map(|code| code as Unichar)
+            .collect::<Vec<_>>();
+        typeface.unichars_to_glyphs(&unichars, &mut glyphs);
+        for (code, glyph) in (start..).zip(glyphs) {
+            if glyph == 0 {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if *range.end() + 1 == code => *range = *range.start()..=code,
+                _ => ranges.push(code..=code),
+            }
         }
     }
+    ranges
+}
 
-    pub fn font_collection(&self) -> &FontCollection {
-        &self.font_collection
+/// 列出字体目录中的字体和默认字体中的系统字体
+pub fn loaded_typefaces() -> Vec<TypefaceInfo> {
+    let local_fonts = LOCAL_FONTS.read().unwrap().clone();
+    let mut typefaces = local_fonts
+        .typefaces
+        .iter()
+        .map(|typeface| TypefaceInfo::new(typeface, true))
+        .collect::<Vec<_>>();
+    let mut font_collection = font_collection();
+    let mut seen = local_fonts
+        .typefaces
+        .iter()
+        .map(|typeface| typeface.unique_id())
+        .collect::<HashSet<_>>();
+    for family in &CONFIG.font.default_font_families {
+        for typeface in font_collection.find_typefaces(&[family], FontStyle::normal()) {
+            // 找不到时会返回回退字体，只保留名称匹配的字体
+            if typeface.family_name().eq_ignore_ascii_case(family)
+                && seen.insert(typeface.unique_id())
+            {
+                typefaces.push(TypefaceInfo::new(&typeface, false));
+            }
+        }
     }
+    typefaces
 }
 
-unsafe impl Send for FontManager {}
+/// 文字和描边的样式，描边的样式只在设置了 `stroke_paint` 时存在
+pub(crate) fn text_styles(
+    font_size: scalar,
+    text_params: &TextParams,
+) -> (TextStyle, Option<TextStyle>) {
+    let mut font_families = text_params.font_families.clone();
+    font_families.append(&mut CONFIG.font.default_font_families.clone());
+
+    let mut style = TextStyle::new();
+    style.set_font_size(font_size);
+    style.set_font_style(text_params.font_style);
+    style.set_foreground_paint(&text_params.paint);
+    style.set_font_families(&font_families);
+    let stroke_style = text_params.stroke_paint.as_ref().map(|stroke_paint| {
+        let mut stroke_style = style.clone();
+        stroke_style.set_foreground_paint(stroke_paint);
+        stroke_style
+    });
+    (style, stroke_style)
+}
+
+/// 文字的书写方向
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WritingMode {
+    /// 横排，从左到右
+    #[default]
+    Horizontal,
+    /// 竖排，从上到下、从右到左，`text_align` 决定每列在竖直方向上的对齐方式
+    Vertical,
+}
 
 #[derive(Debug, Clone)]
 pub struct TextParams {
@@ -86,6 +302,9 @@ pub struct TextParams {
     pub text_align: TextAlign,
     pub paint: Paint,
     pub stroke_paint: Option<Paint>,
+    pub writing_mode: WritingMode,
+    /// BBCode 中 `[img=name]` 引用的行内图片
+    pub inline_images: HashMap<String, Image>,
 }
 
 impl Default for TextParams {
@@ -96,10 +315,21 @@ impl Default for TextParams {
             text_align: TextAlign::Center,
             paint: new_paint(Color::BLACK),
             stroke_paint: None,
+            writing_mode: WritingMode::Horizontal,
+            inline_images: HashMap::new(),
         }
     }
 }
 
+/// 沿路径绘制文字的参数，文字沿路径的对齐方式由 `TextParams` 的 `text_align` 决定
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PathTextParams {
+    /// 文字在路径上的起始偏移
+    pub start_offset: scalar,
+    /// 相邻字符之间额外的间距
+    pub letter_spacing: scalar,
+}
+
 #[macro_export]
 macro_rules! text_params {
     ($($field:ident = $value:expr),* $(,)?) => {
@@ -113,7 +343,12 @@ macro_rules! text_params {
 }
 
 pub mod text_params_setters {
-    use skia_safe::{FontStyle, Paint, textlayout::TextAlign};
+    use std::collections::HashMap;
+
+    use skia_safe::{FontStyle, Image, Paint, textlayout::TextAlign};
+
+    use super::WritingMode;
+    use crate::builder::InputImage;
 
     pub fn font_style(style: FontStyle) -> FontStyle {
         style
@@ -134,11 +369,65 @@ pub mod text_params_setters {
     pub fn stroke_paint(paint: Paint) -> Option<Paint> {
         Some(paint)
     }
+
+    pub fn writing_mode(mode: WritingMode) -> WritingMode {
+        mode
+    }
+
+    /// 以图片名引用传入的图片
+    pub fn inline_images(images: &[InputImage]) -> HashMap<String, Image> {
+        images
+            .iter()
+            .map(|image| (image.name.clone(), image.image.clone()))
+            .collect()
+    }
+}
+
+/// 行内图片的占位符，高度与字号相同，宽度按图片比例
+pub(crate) fn image_placeholder(image: &Image, font_size: scalar) -> PlaceholderStyle {
+    let width = font_size * image.width() as scalar / image.height() as scalar;
+    PlaceholderStyle::new(
+        width,
+        font_size,
+        PlaceholderAlignment::Middle,
+        TextBaseline::Alphabetic,
+        0.0,
+    )
+}
+
+/// 绘制段落，并在占位符的位置按顺序绘制行内图片
+pub(crate) fn paint_paragraph(
+    canvas: &Canvas,
+    paragraph: &Paragraph,
+    images: &[Image],
+    origin: Point,
+) {
+    paragraph.paint(canvas, origin);
+    let placeholders = paragraph.get_rects_for_placeholders();
+    for (placeholder, image) in placeholders.iter().zip(images) {
+        canvas.draw_image_rect_with_sampling_options(
+            image,
+            None,
+            placeholder.rect.with_offset(origin),
+            default_sampling_options(),
+            &Paint::default(),
+        );
+    }
+}
+
+enum TextLayout {
+    Horizontal {
+        paragraph: Paragraph,
+        stroke_paragraph: Option<Paragraph>,
+        images: Vec<Image>,
+    },
+    Vertical(VerticalText),
 }
 
 pub struct Text2Image {
-    paragraph: Paragraph,
-    stroke_paragraph: Option<Paragraph>,
+    layout: TextLayout,
+    /// 描边的最大宽度，没有描边时为 0
+    stroke_width: scalar,
 }
 
 impl Text2Image {
@@ -149,65 +438,99 @@ impl Text2Image {
     ) -> Self {
         let text: String = text.into();
         let text_params: TextParams = text_params.into().unwrap_or_default();
-        let mut font_families = text_params.font_families.clone();
-        font_families.append(&mut CONFIG.font.default_font_families.clone());
+        report_fallback(&text, &text_params.font_families);
 
         let mut paragraph_style = ParagraphStyle::new();
         paragraph_style.set_text_align(text_params.text_align);
 
-        let font_manager = FONT_MANAGER.lock().unwrap();
-        let mut builder = ParagraphBuilder::new(&paragraph_style, font_manager.font_collection());
-        let mut style = TextStyle::new();
-        style.set_font_size(font_size);
-        style.set_font_style(text_params.font_style);
-        style.set_foreground_paint(&text_params.paint);
-        style.set_font_families(&font_families);
+        let font_collection = font_collection();
+        let (style, stroke_style) = text_styles(font_size, &text_params);
+        let stroke_width = text_params
+            .stroke_paint
+            .as_ref()
+            .map_or(0.0, |stroke_paint| stroke_paint.stroke_width());
+
+        if text_params.writing_mode == WritingMode::Vertical {
+            let mut vertical = VerticalText::new(font_size, text_params.text_align);
+            vertical.add_text(&text, &font_collection, &style, stroke_style.as_ref());
+            return Self::from_vertical(vertical, stroke_width);
+        }
+
+        let mut builder = ParagraphBuilder::new(&paragraph_style, &font_collection);
         builder.push_style(&style);
         builder.add_text(text.clone());
         let mut paragraph = builder.build();
         paragraph.layout(scalar::INFINITY);
 
-        let stroke_paragraph = match &text_params.stroke_paint {
-            Some(stroke_paint) => {
-                let mut stroke_builder =
-                    ParagraphBuilder::new(&paragraph_style, font_manager.font_collection());
-                let mut stroke_style = TextStyle::new();
-                stroke_style.set_font_size(font_size);
-                stroke_style.set_font_style(text_params.font_style);
-                stroke_style.set_foreground_paint(&stroke_paint);
-                stroke_style.set_font_families(&font_families);
-                stroke_builder.push_style(&stroke_style);
-                stroke_builder.add_text(text);
-                let mut stroke_paragraph = stroke_builder.build();
-                stroke_paragraph.layout(scalar::INFINITY);
-                Some(stroke_paragraph)
-            }
-            None => None,
-        };
+        let stroke_paragraph = stroke_style.map(|stroke_style| {
+            let mut stroke_builder = ParagraphBuilder::new(&paragraph_style, &font_collection);
+            stroke_builder.push_style(&stroke_style);
+            stroke_builder.add_text(text);
+            let mut stroke_paragraph = stroke_builder.build();
+            stroke_paragraph.layout(scalar::INFINITY);
+            stroke_paragraph
+        });
 
+        Self::from_paragraphs(paragraph, stroke_paragraph, Vec::new(), stroke_width)
+    }
+
+    fn from_paragraphs(
+        paragraph: Paragraph,
+        stroke_paragraph: Option<Paragraph>,
+        images: Vec<Image>,
+        stroke_width: scalar,
+    ) -> Self {
         let mut text2image = Self {
-            paragraph,
-            stroke_paragraph,
+            layout: TextLayout::Horizontal {
+                paragraph,
+                stroke_paragraph,
+                images,
+            },
+            stroke_width,
         };
         text2image.layout(text2image.longest_line().ceil());
         text2image
     }
 
+    fn from_vertical(mut vertical: VerticalText, stroke_width: scalar) -> Self {
+        vertical.layout(scalar::INFINITY);
+        Self {
+            layout: TextLayout::Vertical(vertical),
+            stroke_width,
+        }
+    }
+
+    /// 解析 BBCode 生成文字，支持的标签：
+    ///
+    /// - `[b]`、`[i]`：粗体、斜体
+    /// - `[u]`、`[del]`：下划线、删除线，可以用 `[u=wavy]` 指定线型，
+    ///   可选 `solid`、`double`、`dotted`、`dashed`、`wavy`
+    /// - `[color=..]`、`[stroke=..]`、`[bg=..]`：文字颜色、描边颜色、背景高亮颜色，
+    ///   颜色为 `#rrggbb` 或颜色名
+    /// - `[size=..]`：字号
+    /// - `[font=..]`：字体，找不到时使用默认字体
+    /// - `[img=..]`：在行内插入 `TextParams::inline_images` 中对应名称的图片，高度与字号相同，没有结束标签
+    ///
+    /// `[[` 表示 `[` 本身，可以用 [`escape_bbcode`] 转义用户输入的文字；
+    /// 未结束的标签在文字末尾自动结束。标签有误时返回 [`Error::InvalidBBCode`]
     pub fn from_bbcode_text(
         text: impl Into<String>,
         font_size: scalar,
         text_params: impl Into<Option<TextParams>>,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let text: String = text.into();
         let text_params: TextParams = text_params.into().unwrap_or_default();
+        let tokens = tokenize_bbcode(&text).map_err(|err| Error::invalid_bbcode(&text, err))?;
+        report_fallback(&text, &text_params.font_families);
+
         let mut font_families = text_params.font_families.clone();
         font_families.append(&mut CONFIG.font.default_font_families.clone());
 
         let mut paragraph_style = ParagraphStyle::new();
         paragraph_style.set_text_align(text_params.text_align);
 
-        let font_manager = FONT_MANAGER.lock().unwrap();
-        let mut builder = ParagraphBuilder::new(&paragraph_style, font_manager.font_collection());
+        let font_collection = font_collection();
+        let mut builder = ParagraphBuilder::new(&paragraph_style, &font_collection);
         let mut style = TextStyle::new();
         style.set_font_size(font_size);
         style.set_font_style(text_params.font_style);
@@ -215,8 +538,7 @@ impl Text2Image {
         style.set_font_families(&font_families);
         builder.push_style(&style);
 
-        let mut stroke_builder =
-            ParagraphBuilder::new(&paragraph_style, font_manager.font_collection());
+        let mut stroke_builder = ParagraphBuilder::new(&paragraph_style, &font_collection);
         let mut stroke_style = TextStyle::new();
         stroke_style.set_font_size(font_size);
         stroke_style.set_font_style(text_params.font_style);
@@ -226,110 +548,170 @@ impl Text2Image {
         stroke_style.set_font_families(&font_families);
         stroke_builder.push_style(&stroke_style);
 
-        let mut paint = text_params.paint;
+        let mut paint = text_params.paint.clone();
         let mut stroke_paint = text_params
             .stroke_paint
+            .clone()
             .unwrap_or(new_stroke_paint(Color::BLACK, 0.04 * font_size));
 
-        let mut bold_stack = VecDeque::new();
-        let mut italic_stack = VecDeque::new();
-        let mut underline_stack = VecDeque::new();
-        let mut strikethrough_stack = VecDeque::new();
-        let mut color_stack = VecDeque::new();
-        let mut stroke_stack = VecDeque::new();
-        let mut has_stroke = false;
+        let has_stroke = tokens
+            .iter()
+            .any(|token| matches!(token, BBCodeToken::OpenTag(BBCodeTag::Stroke(_))));
+        let mut vertical = (text_params.writing_mode == WritingMode::Vertical)
+            .then(|| VerticalText::new(font_size, text_params.text_align));
+        let mut images = Vec::new();
 
-        let tokens = tokenize_bbcode(&text);
+        let mut tags: Vec<BBCodeTag> = Vec::new();
         for token in tokens {
             match token {
-                BBCodeToken::OpenTag(tag) => match tag {
-                    BBCodeTag::Bold => {
-                        bold_stack.push_back(true);
-                    }
-                    BBCodeTag::Italic => {
-                        italic_stack.push_back(true);
-                    }
-                    BBCodeTag::Underline => {
-                        underline_stack.push_back(true);
-                    }
-                    BBCodeTag::Strikethrough => {
-                        strikethrough_stack.push_back(true);
-                    }
-                    BBCodeTag::Color(color) => {
-                        let color = color_from_str(&color);
-                        color_stack.push_back(color);
-                    }
-                    BBCodeTag::Stroke(color) => {
-                        let color = color_from_str(&color);
-                        stroke_stack.push_back(color);
-                        has_stroke = true;
-                    }
-                },
-                BBCodeToken::CloseTag(tag) => match tag {
-                    BBCodeTag::Bold => {
-                        bold_stack.pop_back();
-                    }
-                    BBCodeTag::Italic => {
-                        italic_stack.pop_back();
-                    }
-                    BBCodeTag::Underline => {
-                        underline_stack.pop_back();
-                    }
-                    BBCodeTag::Strikethrough => {
-                        strikethrough_stack.pop_back();
-                    }
-                    BBCodeTag::Color(_) => {
-                        color_stack.pop_back();
-                    }
-                    BBCodeTag::Stroke(_) => {
-                        stroke_stack.pop_back();
+                BBCodeToken::OpenTag(tag) => {
+                    tags.push(tag);
+                    continue;
+                }
+                BBCodeToken::CloseTag(name) => {
+                    if let Some(index) = tags.iter().rposition(|tag| tag.name() == name) {
+                        tags.remove(index);
                     }
-                },
-                BBCodeToken::Text(text) => {
-                    let bold = bold_stack.back().cloned().unwrap_or(false);
-                    let italic = italic_stack.back().cloned().unwrap_or(false);
-                    let underline = underline_stack.back().cloned().unwrap_or(false);
-                    let strikethrough = strikethrough_stack.back().cloned().unwrap_or(false);
-                    let color = color_stack.back().cloned().unwrap_or(paint.color());
-                    let stroke_color = stroke_stack.back().cloned().unwrap_or(stroke_paint.color());
-
-                    let font_style = if bold && italic {
-                        FontStyle::bold_italic()
-                    } else if bold {
-                        FontStyle::bold()
-                    } else if italic {
-                        FontStyle::italic()
-                    } else {
-                        FontStyle::normal()
-                    };
-                    let text_decoration = if underline && strikethrough {
-                        TextDecoration::UNDERLINE | TextDecoration::LINE_THROUGH
-                    } else if underline {
-                        TextDecoration::UNDERLINE
-                    } else if strikethrough {
-                        TextDecoration::LINE_THROUGH
-                    } else {
-                        TextDecoration::NO_DECORATION
-                    };
-                    let decoration = new_decoration(text_decoration, color);
-                    style.set_font_style(font_style);
-                    style.set_decoration(&decoration);
-                    paint.set_color(color);
-                    style.set_foreground_paint(&paint);
+                    continue;
+                }
+                BBCodeToken::Text(_) | BBCodeToken::Image(_) => {}
+            }
+
+            // 同类标签嵌套时以最内层的为准
+            let bold = tags.contains(&BBCodeTag::Bold);
+            let italic = tags.contains(&BBCodeTag::Italic);
+            let underline = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Underline(style) => Some(*style),
+                _ => None,
+            });
+            let strikethrough = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Strikethrough(style) => Some(*style),
+                _ => None,
+            });
+            let color = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Color(color) => Some(*color),
+                _ => None,
+            });
+            let stroke_color = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Stroke(color) => Some(*color),
+                _ => None,
+            });
+            let background = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Background(color) => Some(*color),
+                _ => None,
+            });
+            let size = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Size(size) => Some(*size),
+                _ => None,
+            });
+            let font = tags.iter().rev().find_map(|tag| match tag {
+                BBCodeTag::Font(font) => Some(font),
+                _ => None,
+            });
+            let color = color.unwrap_or(text_params.paint.color());
+            let stroke_color = stroke_color.unwrap_or(stroke_paint.color());
+            let size = size.unwrap_or(font_size);
+
+            let font_style = if bold && italic {
+                FontStyle::bold_italic()
+            } else if bold {
+                FontStyle::bold()
+            } else if italic {
+                FontStyle::italic()
+            } else {
+                FontStyle::normal()
+            };
+            let text_decoration = if underline.is_some() && strikethrough.is_some() {
+                TextDecoration::UNDERLINE | TextDecoration::LINE_THROUGH
+            } else if underline.is_some() {
+                TextDecoration::UNDERLINE
+            } else if strikethrough.is_some() {
+                TextDecoration::LINE_THROUGH
+            } else {
+                TextDecoration::NO_DECORATION
+            };
+            let mut decoration = new_decoration(text_decoration, color);
+            if let Some(decoration_style) = underline.or(strikethrough) {
+                decoration.style = decoration_style;
+            }
+            let mut span_families = font_families.clone();
+            if let Some(font) = font {
+                span_families.insert(0, font.clone());
+            }
 
-                    stroke_style.set_font_style(font_style);
-                    stroke_style.set_decoration(&decoration);
-                    stroke_paint.set_color(stroke_color);
-                    stroke_style.set_foreground_paint(&stroke_paint);
+            for style in [&mut style, &mut stroke_style] {
+                style.set_font_size(size);
+                style.set_font_families(&span_families);
+                style.set_font_style(font_style);
+                style.set_decoration(&decoration);
+            }
+            paint.set_color(color);
+            style.set_foreground_paint(&paint);
+            stroke_paint.set_color(stroke_color);
+            stroke_style.set_foreground_paint(&stroke_paint);
+
+            // 描边在文字之前绘制，有描边时背景放在描边中，避免遮住描边
+            let background_style = if has_stroke {
+                &mut stroke_style
+            } else {
+                &mut style
+            };
+            match background {
+                Some(background) => {
+                    background_style.set_background_paint(&new_paint(background));
+                }
+                None => {
+                    background_style.clear_background_color();
+                }
+            }
 
+            match token {
+                BBCodeToken::Text(content) => {
+                    if let Some(vertical) = &mut vertical {
+                        vertical.add_text(&content, &font_collection, &style, Some(&stroke_style));
+                        continue;
+                    }
+                    builder.pop();
+                    builder.push_style(&style);
+                    builder.add_text(content.clone());
+                    stroke_builder.pop();
+                    stroke_builder.push_style(&stroke_style);
+                    stroke_builder.add_text(content);
+                }
+                BBCodeToken::Image(name) => {
+                    let Some(image) = text_params.inline_images.get(&name) else {
+                        return Err(Error::invalid_bbcode(
+                            &text,
+                            format!("Image `{name}` in `[img={name}]` is not provided"),
+                        ));
+                    };
+                    if let Some(vertical) = &mut vertical {
+                        vertical.add_image(image, &font_collection, &style);
+                        continue;
+                    }
+                    let placeholder = image_placeholder(image, size);
                     builder.pop();
                     builder.push_style(&style);
-                    builder.add_text(text.clone());
+                    builder.add_placeholder(&placeholder);
                     stroke_builder.pop();
                     stroke_builder.push_style(&stroke_style);
-                    stroke_builder.add_text(text);
+                    stroke_builder.add_placeholder(&placeholder);
+                    images.push(image.clone());
                 }
+                _ => {}
+            }
+        }
+
+        let stroke_width = if has_stroke {
+            stroke_paint.stroke_width()
+        } else {
+            0.0
+        };
+        if let Some(mut vertical) = vertical {
+            if !has_stroke {
+                vertical.remove_stroke();
             }
+            return Ok(Self::from_vertical(vertical, stroke_width));
         }
 
         let mut paragraph = builder.build();
@@ -343,35 +725,79 @@ impl Text2Image {
             None
         };
 
-        let mut text2image = Self {
+        Ok(Self::from_paragraphs(
             paragraph,
             stroke_paragraph,
-        };
-        text2image.layout(text2image.longest_line().ceil());
-        text2image
+            images,
+            stroke_width,
+        ))
     }
 
+    pub fn is_vertical(&self) -> bool {
+        matches!(self.layout, TextLayout::Vertical(_))
+    }
+
+    /// 最长一行的宽度，竖排时为所有列的总宽度
     pub fn longest_line(&self) -> scalar {
-        self.paragraph.longest_line()
+        match &self.layout {
+            TextLayout::Horizontal { paragraph, .. } => paragraph.longest_line(),
+            TextLayout::Vertical(vertical) => vertical.width(),
+        }
     }
 
+    /// 文字的高度，竖排时为最长一列的长度
     pub fn height(&self) -> scalar {
-        self.paragraph.height()
+        match &self.layout {
+            TextLayout::Horizontal { paragraph, .. } => paragraph.height(),
+            TextLayout::Vertical(vertical) => vertical.height(),
+        }
     }
 
+    /// 横排时超过 `width` 换行，竖排时列高超过 `width` 换列
     pub fn layout(&mut self, width: scalar) {
-        self.paragraph.layout(width);
-        if let Some(stroke_paragraph) = &mut self.stroke_paragraph {
-            stroke_paragraph.layout(width);
+        match &mut self.layout {
+            TextLayout::Horizontal {
+                paragraph,
+                stroke_paragraph,
+                ..
+            } => {
+                paragraph.layout(width);
+                if let Some(stroke_paragraph) = stroke_paragraph {
+                    stroke_paragraph.layout(width);
+                }
+            }
+            TextLayout::Vertical(vertical) => vertical.layout(width),
         }
     }
 
     pub fn draw_on_canvas(&self, canvas: &Canvas, origin: impl Into<Point>) {
         let origin: Point = origin.into();
-        if let Some(stroke_paragraph) = &self.stroke_paragraph {
-            stroke_paragraph.paint(canvas, origin);
+        match &self.layout {
+            TextLayout::Horizontal {
+                paragraph,
+                stroke_paragraph,
+                images,
+            } => {
+                if let Some(stroke_paragraph) = stroke_paragraph {
+                    stroke_paragraph.paint(canvas, origin);
+                }
+                paint_paragraph(canvas, paragraph, images, origin);
+            }
+            TextLayout::Vertical(vertical) => vertical.draw_on_canvas(canvas, origin),
         }
-        self.paragraph.paint(canvas, origin);
+    }
+
+    /// 记录为以 (0, 0) 为原点的 `Picture`，可以在线程间共享
+    pub fn to_picture(&self) -> Result<Picture, Error> {
+        // 描边以文字轮廓为中心，超出文字的部分为描边宽度的一半
+        let outset = (self.stroke_width / 2.0).ceil();
+        let bounds =
+            Rect::from_wh(self.longest_line(), self.height()).with_outset((outset, outset));
+        let mut recorder = PictureRecorder::new();
+        self.draw_on_canvas(recorder.begin_recording(bounds, None), (0.0, 0.0));
+        recorder
+            .finish_recording_as_picture(None)
+            .ok_or_else(|| Error::ImageEncodeError("Failed to record text picture".to_string()))
     }
 }
 
@@ -379,77 +805,141 @@ impl Text2Image {
 enum BBCodeTag {
     Bold,
     Italic,
-    Underline,
-    Strikethrough,
-    Color(String),
-    Stroke(String),
+    Underline(TextDecorationStyle),
+    Strikethrough(TextDecorationStyle),
+    Color(Color),
+    Stroke(Color),
+    Background(Color),
+    Size(scalar),
+    Font(String),
+}
+
+impl BBCodeTag {
+    /// 解析开始标签中 `[` 和 `]` 之间的内容
+    fn parse(tag: &str) -> Result<Self, String> {
+        let (name, value) = match tag.split_once('=') {
+            Some((name, value)) => (name, Some(value.trim())),
+            None => (tag, None),
+        };
+        let parse_tag_color = |value: &str| {
+            parse_color(value).ok_or_else(|| {
+                format!("Invalid color `{value}` in `[{tag}]`, expected `#rrggbb` or a color name")
+            })
+        };
+        let parse_decoration_style = |value: Option<&str>| match value {
+            None | Some("solid") => Ok(TextDecorationStyle::Solid),
+            Some("double") => Ok(TextDecorationStyle::Double),
+            Some("dotted") => Ok(TextDecorationStyle::Dotted),
+            Some("dashed") => Ok(TextDecorationStyle::Dashed),
+            Some("wavy") => Ok(TextDecorationStyle::Wavy),
+            Some(value) => Err(format!(
+                "Invalid line style `{value}` in `[{tag}]`, expected one of solid, double, dotted, dashed, wavy"
+            )),
+        };
+        match (name, value) {
+            ("b", None) => Ok(BBCodeTag::Bold),
+            ("i", None) => Ok(BBCodeTag::Italic),
+            ("u", value) => parse_decoration_style(value).map(BBCodeTag::Underline),
+            ("del", value) => parse_decoration_style(value).map(BBCodeTag::Strikethrough),
+            ("b" | "i", Some(_)) => Err(format!("Tag `[{name}]` does not take a value")),
+            ("color" | "stroke" | "bg" | "size" | "font", None | Some("")) => Err(format!(
+                "Tag `[{name}]` requires a value, e.g. `[{name}=...]`"
+            )),
+            ("color", Some(value)) => parse_tag_color(value).map(BBCodeTag::Color),
+            ("stroke", Some(value)) => parse_tag_color(value).map(BBCodeTag::Stroke),
+            ("bg", Some(value)) => parse_tag_color(value).map(BBCodeTag::Background),
+            ("size", Some(value)) => value
+                .parse::<scalar>()
+                .ok()
+                .filter(|size| size.is_finite() && *size > 0.0)
+                .map(BBCodeTag::Size)
+                .ok_or_else(|| format!("Invalid font size `{value}` in `[{tag}]`")),
+            ("font", Some(value)) => Ok(BBCodeTag::Font(value.to_string())),
+            _ => Err(format!("Unknown tag `[{tag}]`")),
+        }
+    }
+
+    /// 结束标签的名称
+    fn name(&self) -> &'static str {
+        match self {
+            BBCodeTag::Bold => "b",
+            BBCodeTag::Italic => "i",
+            BBCodeTag::Underline(_) => "u",
+            BBCodeTag::Strikethrough(_) => "del",
+            BBCodeTag::Color(_) => "color",
+            BBCodeTag::Stroke(_) => "stroke",
+            BBCodeTag::Background(_) => "bg",
+            BBCodeTag::Size(_) => "size",
+            BBCodeTag::Font(_) => "font",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 enum BBCodeToken {
     Text(String),
     OpenTag(BBCodeTag),
-    CloseTag(BBCodeTag),
+    CloseTag(&'static str),
+    Image(String),
 }
 
-fn tokenize_bbcode(input: &str) -> Vec<BBCodeToken> {
-    let mut tokens = Vec::new();
-    let mut i = 0;
+/// 转义文字中的 `[`，使其在 BBCode 中按原样显示
+pub fn escape_bbcode(text: &str) -> String {
+    text.replace('[', "[[")
+}
 
-    while i < input.len() {
-        if let Some(tag_start) = input[i..].find('[') {
-            if tag_start > 0 {
-                let text = input[i..i + tag_start].to_string();
-                tokens.push(BBCodeToken::Text(text));
-            }
+fn tokenize_bbcode(input: &str) -> Result<Vec<BBCodeToken>, String> {
+    let mut tokens = Vec::new();
+    let mut open_tags: Vec<&'static str> = Vec::new();
+    let mut text = String::new();
+    let mut rest = input;
+
+    while let Some(tag_start) = rest.find('[') {
+        text.push_str(&rest[..tag_start]);
+        rest = &rest[tag_start + 1..];
+        if let Some(after) = rest.strip_prefix('[') {
+            text.push('[');
+            rest = after;
+            continue;
+        }
+        let Some(tag_end) = rest.find(']') else {
+            return Err(format!(
+                "Tag starting with `[{}` is not closed with `]`, use `[[` for a literal `[`",
+                rest.chars().take(10).collect::<String>()
+            ));
+        };
+        let tag = &rest[..tag_end];
+        rest = &rest[tag_end + 1..];
+        if !text.is_empty() {
+            tokens.push(BBCodeToken::Text(std::mem::take(&mut text)));
+        }
 
-            if let Some(tag_end) = input[i + tag_start..].find(']') {
-                let tag = &input[i + tag_start + 1..i + tag_start + tag_end];
-                let end_tag = tag.starts_with('/');
-                let tag_name = if end_tag { &tag[1..] } else { tag };
-
-                i += tag_start + tag_end + 1;
-
-                if end_tag {
-                    match tag_name {
-                        "b" => tokens.push(BBCodeToken::CloseTag(BBCodeTag::Bold)),
-                        "i" => tokens.push(BBCodeToken::CloseTag(BBCodeTag::Italic)),
-                        "u" => tokens.push(BBCodeToken::CloseTag(BBCodeTag::Underline)),
-                        "del" => tokens.push(BBCodeToken::CloseTag(BBCodeTag::Strikethrough)),
-                        "color" => {
-                            tokens.push(BBCodeToken::CloseTag(BBCodeTag::Color(String::new())))
-                        }
-                        "stroke" => {
-                            tokens.push(BBCodeToken::CloseTag(BBCodeTag::Stroke(String::new())))
-                        }
-                        _ => {}
-                    }
-                } else {
-                    if tag_name.starts_with("color=") {
-                        let color_code = tag_name[6..].to_string();
-                        tokens.push(BBCodeToken::OpenTag(BBCodeTag::Color(color_code)));
-                    } else if tag_name.starts_with("stroke=") {
-                        let stroke_code = tag_name[7..].to_string();
-                        tokens.push(BBCodeToken::OpenTag(BBCodeTag::Stroke(stroke_code)));
-                    } else {
-                        match tag_name {
-                            "b" => tokens.push(BBCodeToken::OpenTag(BBCodeTag::Bold)),
-                            "i" => tokens.push(BBCodeToken::OpenTag(BBCodeTag::Italic)),
-                            "u" => tokens.push(BBCodeToken::OpenTag(BBCodeTag::Underline)),
-                            "del" => tokens.push(BBCodeToken::OpenTag(BBCodeTag::Strikethrough)),
-                            _ => {}
-                        }
-                    }
+        if let Some(name) = tag.strip_prefix('/') {
+            let Some(index) = open_tags.iter().rposition(|open_tag| *open_tag == name) else {
+                return Err(format!(
+                    "Closing tag `[/{name}]` has no matching opening tag"
+                ));
+            };
+            tokens.push(BBCodeToken::CloseTag(open_tags.remove(index)));
+        } else if tag == "img" || tag.starts_with("img=") {
+            match tag.split_once('=').map(|(_, name)| name.trim()) {
+                Some(name) if !name.is_empty() => tokens.push(BBCodeToken::Image(name.to_string())),
+                _ => {
+                    return Err(format!(
+                        "Tag `[{tag}]` requires an image name, e.g. `[img=name]`"
+                    ));
                 }
-            } else {
-                tokens.push(BBCodeToken::Text(input[i..].to_string()));
-                break;
             }
         } else {
-            tokens.push(BBCodeToken::Text(input[i..].to_string()));
-            break;
+            let tag = BBCodeTag::parse(tag)?;
+            open_tags.push(tag.name());
+            tokens.push(BBCodeToken::OpenTag(tag));
         }
     }
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(BBCodeToken::Text(text));
+    }
 
-    tokens
+    Ok(tokens)
 }
@@ -0,0 +1,191 @@
+//! 沿路径排列的文字：整段文字只排版一次，每个字符按路径在该处的切线方向旋转，基线落在路径上
+
+use skia_safe::{
+    Canvas, ContourMeasure, Font, GlyphId, Paint, Point, scalar,
+    textlayout::{ParagraphBuilder, ParagraphStyle, TextAlign},
+};
+
+use crate::{
+    text::{PathTextParams, TextParams, font_collection, text_styles},
+    vertical::is_attached,
+};
+
+/// 每个字符在文字中的起始位置，附加符号和零宽连接符连接的字符与前一个字符合并
+fn cluster_starts(text: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut joining = false;
+    for (index, c) in text.char_indices() {
+        if starts.is_empty() || !(joining || is_attached(c)) {
+            starts.push(index);
+        }
+        joining = c == '\u{200D}';
+    }
+    starts
+}
+
+struct Glyph {
+    font: Font,
+    id: GlyphId,
+    /// 相对于所在字符左侧基线的位置
+    offset: Point,
+}
+
+/// 排版后的一个字符，由一个或多个字形组成
+struct Cluster {
+    glyphs: Vec<Glyph>,
+    /// 在整段文字中的左侧位置
+    left: scalar,
+    advance: scalar,
+}
+
+impl Cluster {
+    fn draw(&self, canvas: &Canvas, paint: &Paint) {
+        for glyph in &self.glyphs {
+            let position = [glyph.offset - Point::new(self.advance / 2.0, 0.0)];
+            canvas.draw_glyphs_at(
+                &[glyph.id],
+                &position[..],
+                Point::default(),
+                &glyph.font,
+                paint,
+            );
+        }
+    }
+}
+
+/// 排版整段文字，按照字形所属的字符分组，字符按从左到右的顺序排列
+fn shape_clusters(text: &str, font_size: scalar, text_params: &TextParams) -> Vec<Cluster> {
+    let (style, _) = text_styles(font_size, text_params);
+    let mut paragraph_style = ParagraphStyle::new();
+    paragraph_style.set_text_align(TextAlign::Left);
+    let mut builder = ParagraphBuilder::new(&paragraph_style, font_collection());
+    builder.push_style(&style);
+    builder.add_text(text);
+    let mut paragraph = builder.build();
+    paragraph.layout(scalar::INFINITY);
+
+    let starts = cluster_starts(text);
+    // 每个字符的字形和左右边界
+    let mut clusters = vec![(Vec::new(), scalar::INFINITY, scalar::NEG_INFINITY); starts.len()];
+    paragraph.visit(|_, info| {
+        let Some(info) = info else {
+            return;
+        };
+        let (origin, positions) = (info.origin(), info.positions());
+        for (i, (id, position)) in info.glyphs().iter().zip(positions).enumerate() {
+            let next = positions.get(i + 1).map_or(info.advance_x(), |next| next.x);
+            let index = starts.partition_point(|start| *start <= info.utf8_starts()[i] as usize);
+            let Some((glyphs, left, right)) =
+                index.checked_sub(1).map(|index| &mut clusters[index])
+            else {
+                continue;
+            };
+            let x = origin.x + position.x;
+            *left = left.min(x);
+            *right = right.max(origin.x + next);
+            glyphs.push((info.font().clone(), *id, Point::new(x, position.y)));
+        }
+    });
+
+    let mut clusters = clusters
+        .into_iter()
+        .filter(|(glyphs, _, _)| !glyphs.is_empty())
+        .map(|(glyphs, left, right)| Cluster {
+            glyphs: glyphs
+                .into_iter()
+                .map(|(font, id, position)| Glyph {
+                    font,
+                    id,
+                    offset: Point::new(position.x - left, position.y),
+                })
+                .collect(),
+            left,
+            advance: right - left,
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| a.left.total_cmp(&b.left));
+    clusters
+}
+
+pub(crate) struct PathText {
+    clusters: Vec<Cluster>,
+    paint: Paint,
+    stroke_paint: Option<Paint>,
+    text_align: TextAlign,
+    params: PathTextParams,
+}
+
+impl PathText {
+    pub fn new(
+        text: &str,
+        font_size: scalar,
+        text_params: &TextParams,
+        params: PathTextParams,
+    ) -> Self {
+        // 文字只排成一行，去掉换行等控制字符
+        let text = text.chars().filter(|c| !c.is_control()).collect::<String>();
+        Self {
+            clusters: shape_clusters(&text, font_size, text_params),
+            paint: text_params.paint.clone(),
+            stroke_paint: text_params.stroke_paint.clone(),
+            text_align: text_params.text_align,
+            params,
+        }
+    }
+
+    /// 文字沿路径的总长度
+    pub fn length(&self) -> scalar {
+        let advances = self
+            .clusters
+            .iter()
+            .map(|cluster| cluster.advance)
+            .sum::<scalar>();
+        advances + self.params.letter_spacing * self.clusters.len().saturating_sub(1) as scalar
+    }
+
+    /// 文字在路径上的起点，放不下时返回 `None`
+    ///
+    /// 闭合路径上的文字可以越过路径的起点，只要求总长度不超过路径长度
+    fn start(&self, contour: &ContourMeasure) -> Option<scalar> {
+        let (length, path_length) = (self.length(), contour.length());
+        let start = self.params.start_offset
+            + match self.text_align {
+                TextAlign::Center => (path_length - length) / 2.0,
+                TextAlign::Right | TextAlign::End => path_length - length,
+                _ => 0.0,
+            };
+        let fits = if contour.is_closed() {
+            length <= path_length
+        } else {
+            start >= 0.0 && start + length <= path_length
+        };
+        fits.then_some(start)
+    }
+
+    /// 沿 `contour` 绘制文字，放不下时返回 `false`
+    pub fn draw_on_contour(&self, canvas: &Canvas, contour: &ContourMeasure) -> bool {
+        let Some(start) = self.start(contour) else {
+            return false;
+        };
+        let path_length = contour.length();
+        // 先绘制所有描边，避免描边覆盖相邻的文字
+        for paint in [self.stroke_paint.as_ref(), Some(&self.paint)]
+            .into_iter()
+            .flatten()
+        {
+            let mut distance = start;
+            for cluster in &self.clusters {
+                let center = (distance + cluster.advance / 2.0).rem_euclid(path_length);
+                if let Some((point, tangent)) = contour.pos_tan(center) {
+                    canvas.save();
+                    canvas.translate(point);
+                    canvas.rotate(tangent.y.atan2(tangent.x).to_degrees(), None);
+                    cluster.draw(canvas, paint);
+                    canvas.restore();
+                }
+                distance += cluster.advance + self.params.letter_spacing;
+            }
+        }
+        true
+    }
+}
@@ -4,7 +4,7 @@ use chrono::{DateTime, Local, TimeZone};
 use regex::Regex;
 use skia_safe::{
     Color, Color4f, Data, FilterMode, IRect, ISize, Image, MipmapMode, Paint, PaintJoin,
-    PaintStyle, SamplingOptions, Surface, scalar, surfaces,
+    PaintStyle, Path, Point, Rect, SamplingOptions, Surface, scalar, surfaces,
     textlayout::{Decoration, TextDecoration, TextDecorationMode},
 };
 
@@ -53,9 +53,15 @@ pub fn color_from_hex_code(hex_code: &str) -> Color {
     Color::from_argb(a, r, g, b)
 }
 
+/// 无法识别的颜色视为黑色
 pub fn color_from_str(color: &str) -> Color {
+    parse_color(color).unwrap_or(Color::BLACK)
+}
+
+/// 解析 `#rrggbb` 形式的颜色或颜色名，无法识别时返回 `None`
+pub fn parse_color(color: &str) -> Option<Color> {
     if Regex::new(r"^#[0-9a-fA-F]{6}$").unwrap().is_match(color) {
-        color_from_hex_code(color)
+        Some(color_from_hex_code(color))
     } else {
         let color = color.trim().to_lowercase();
         let (r, g, b) = match color.as_str() {
@@ -207,12 +213,33 @@ pub fn color_from_str(color: &str) -> Color {
             "whitesmoke" => (245, 245, 245),
             "yellow" => (255, 255, 0),
             "yellowgreen" => (154, 205, 50),
-            _ => (0, 0, 0),
+            _ => return None,
         };
-        Color::from_rgb(r, g, b)
+        Some(Color::from_rgb(r, g, b))
     }
 }
 
+/// 以 `center` 为圆心、`radius` 为半径的圆弧，角度以 x 轴正方向为 0 度、顺时针为正
+///
+/// 沿圆弧绘制文字时，`sweep_angle` 为正时文字在圆弧外侧朝外，为负时文字在圆弧内侧朝内
+pub fn arc_path(
+    center: impl Into<Point>,
+    radius: scalar,
+    start_angle: scalar,
+    sweep_angle: scalar,
+) -> Path {
+    let center: Point = center.into();
+    let oval = Rect::from_xywh(
+        center.x - radius,
+        center.y - radius,
+        radius * 2.0,
+        radius * 2.0,
+    );
+    let mut path = Path::new();
+    path.add_arc(oval, start_angle, sweep_angle);
+    path
+}
+
 pub fn default_sampling_options() -> SamplingOptions {
     SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear)
 }
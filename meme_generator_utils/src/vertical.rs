@@ -0,0 +1,366 @@
+//! 竖排文字：每列从上到下排列，各列从右到左排列
+//!
+//! 汉字、假名等直立排列；西文单词和两位以上的数字顺时针旋转 90 度；
+//! 括号、破折号等标点旋转后排列，句号、逗号等标点移到字框的右上角。
+
+use std::ops::Range;
+
+use skia_safe::{
+    Canvas, Image, Point, scalar,
+    textlayout::{
+        FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextAlign, TextStyle,
+    },
+};
+
+use crate::text::{image_placeholder, paint_paragraph};
+
+/// 列间距与字号之比
+const COLUMN_GAP: scalar = 0.25;
+
+/// 旋转后排列的标点
+const ROTATED_PUNCTUATION: &str = "（）()「」『』《》〈〉【】〔〕［］｛｝〖〗—―…‥～〜ー－：；";
+
+/// 移到字框右上角的标点
+const SHIFTED_PUNCTUATION: &str = "，。、．";
+
+/// 不能位于列首的标点，放不下时留在上一列的末尾
+const NO_BREAK_BEFORE: &str = "，。、．！？：；）」』》〉】〕］｝〗…‥ー";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnitKind {
+    Upright,
+    Rotated,
+    Shifted,
+    Space,
+    Newline,
+}
+
+/// 横向书写的文字，在竖排中旋转排列
+fn is_sideways(c: char) -> bool {
+    c.is_ascii_graphic()
+        || ('\u{00A0}'..='\u{052F}').contains(&c)
+        || ('\u{1E00}'..='\u{1EFF}').contains(&c)
+}
+
+/// 附加在前一个字符上的字符，如组合附加符号、变体选择符、肤色修饰符和零宽连接符
+pub(crate) fn is_attached(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{200D}'
+            | '\u{3099}'..='\u{309A}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{1F3FB}'..='\u{1F3FF}'
+            | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+/// 将文字拆分为竖排的单元
+fn split_units(text: &str) -> Vec<(UnitKind, String)> {
+    let mut units: Vec<(UnitKind, String)> = Vec::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, units: &mut Vec<(UnitKind, String)>| {
+        if word.is_empty() {
+            return;
+        }
+        // 两位以内的数字直立排列（纵中横）
+        let kind = if word.len() <= 2 && word.chars().all(|c| c.is_ascii_digit()) {
+            UnitKind::Upright
+        } else {
+            UnitKind::Rotated
+        };
+        units.push((kind, std::mem::take(word)));
+    };
+
+    let mut joining = false;
+    for c in text.chars() {
+        if joining || is_attached(c) {
+            match units.last_mut() {
+                Some((_, last)) if word.is_empty() => last.push(c),
+                _ => word.push(c),
+            }
+            joining = c == '\u{200D}';
+            continue;
+        }
+        if is_sideways(c) {
+            word.push(c);
+            continue;
+        }
+        flush(&mut word, &mut units);
+        let kind = match c {
+            '\n' => UnitKind::Newline,
+            ' ' | '\u{3000}' => UnitKind::Space,
+            '\r' => continue,
+            _ if ROTATED_PUNCTUATION.contains(c) => UnitKind::Rotated,
+            _ if SHIFTED_PUNCTUATION.contains(c) => UnitKind::Shifted,
+            _ => UnitKind::Upright,
+        };
+        units.push((kind, c.to_string()));
+    }
+    flush(&mut word, &mut units);
+    units
+}
+
+fn build_paragraph(text: &str, font_collection: &FontCollection, style: &TextStyle) -> Paragraph {
+    let mut paragraph_style = ParagraphStyle::new();
+    paragraph_style.set_text_align(TextAlign::Left);
+    let mut builder = ParagraphBuilder::new(&paragraph_style, font_collection);
+    builder.push_style(style);
+    builder.add_text(text);
+    let mut paragraph = builder.build();
+    paragraph.layout(scalar::INFINITY);
+    paragraph
+}
+
+struct Unit {
+    kind: UnitKind,
+    text: String,
+    font_size: scalar,
+    paragraph: Paragraph,
+    stroke_paragraph: Option<Paragraph>,
+    /// 行内图片，绘制在段落中占位符的位置
+    image: Option<Image>,
+}
+
+impl Unit {
+    /// 沿列方向的长度
+    fn advance(&self) -> scalar {
+        match self.kind {
+            UnitKind::Upright | UnitKind::Shifted => self.font_size,
+            UnitKind::Rotated => self.paragraph.longest_line(),
+            UnitKind::Space if self.text == " " => self.font_size / 3.0,
+            UnitKind::Space => self.font_size,
+            UnitKind::Newline => 0.0,
+        }
+    }
+
+    /// 占据的列宽
+    fn thickness(&self) -> scalar {
+        match self.kind {
+            UnitKind::Upright | UnitKind::Shifted => {
+                self.paragraph.longest_line().max(self.font_size)
+            }
+            UnitKind::Rotated => self.paragraph.height(),
+            UnitKind::Space | UnitKind::Newline => 0.0,
+        }
+    }
+
+    fn keeps_with_previous(&self) -> bool {
+        self.text
+            .chars()
+            .next()
+            .is_some_and(|c| NO_BREAK_BEFORE.contains(c))
+    }
+
+    /// 以列的中线 `center` 和字框的顶部 `top` 绘制，`images` 为段落中的行内图片
+    fn draw(
+        &self,
+        canvas: &Canvas,
+        paragraph: &Paragraph,
+        images: &[Image],
+        center: scalar,
+        top: scalar,
+    ) {
+        let (width, height) = (paragraph.longest_line(), paragraph.height());
+        match self.kind {
+            UnitKind::Upright => {
+                let origin =
+                    Point::new(center - width / 2.0, top + (self.font_size - height) / 2.0);
+                paint_paragraph(canvas, paragraph, images, origin);
+            }
+            UnitKind::Shifted => {
+                let origin = Point::new(
+                    center - width / 2.0 + self.font_size / 2.0,
+                    top + (self.font_size - height) / 2.0 - self.font_size / 2.0,
+                );
+                paint_paragraph(canvas, paragraph, images, origin);
+            }
+            UnitKind::Rotated => {
+                canvas.save();
+                canvas.translate((center + height / 2.0, top));
+                canvas.rotate(90.0, None);
+                paint_paragraph(canvas, paragraph, images, Point::default());
+                canvas.restore();
+            }
+            UnitKind::Space | UnitKind::Newline => {}
+        }
+    }
+}
+
+struct Column {
+    units: Range<usize>,
+    length: scalar,
+    width: scalar,
+}
+
+/// 竖排的文字，`text_align` 决定每列在列方向上的对齐方式
+pub(crate) struct VerticalText {
+    font_size: scalar,
+    text_align: TextAlign,
+    units: Vec<Unit>,
+    columns: Vec<Column>,
+    /// 排版时的最大列高
+    max_height: scalar,
+}
+
+impl VerticalText {
+    pub fn new(font_size: scalar, text_align: TextAlign) -> Self {
+        Self {
+            font_size,
+            text_align,
+            units: Vec::new(),
+            columns: Vec::new(),
+            max_height: scalar::INFINITY,
+        }
+    }
+
+    /// 以 `style` 添加文字，`stroke_style` 为描边的样式
+    pub fn add_text(
+        &mut self,
+        text: &str,
+        font_collection: &FontCollection,
+        style: &TextStyle,
+        stroke_style: Option<&TextStyle>,
+    ) {
+        for (kind, text) in split_units(text) {
+            let paragraph = build_paragraph(&text, font_collection, style);
+            let stroke_paragraph = stroke_style
+                .map(|stroke_style| build_paragraph(&text, font_collection, stroke_style));
+            self.units.push(Unit {
+                kind,
+                text,
+                font_size: style.font_size(),
+                paragraph,
+                stroke_paragraph,
+                image: None,
+            });
+        }
+    }
+
+    /// 以 `style` 的字号添加直立排列的行内图片
+    pub fn add_image(
+        &mut self,
+        image: &Image,
+        font_collection: &FontCollection,
+        style: &TextStyle,
+    ) {
+        let font_size = style.font_size();
+        let mut paragraph_style = ParagraphStyle::new();
+        paragraph_style.set_text_align(TextAlign::Left);
+        let mut builder = ParagraphBuilder::new(&paragraph_style, font_collection);
+        builder.push_style(style);
+        builder.add_placeholder(&image_placeholder(image, font_size));
+        let mut paragraph = builder.build();
+        paragraph.layout(scalar::INFINITY);
+        self.units.push(Unit {
+            kind: UnitKind::Upright,
+            text: String::new(),
+            font_size,
+            paragraph,
+            stroke_paragraph: None,
+            image: Some(image.clone()),
+        });
+    }
+
+    pub fn remove_stroke(&mut self) {
+        for unit in &mut self.units {
+            unit.stroke_paragraph = None;
+        }
+    }
+
+    /// 列高超过 `max_height` 时换列
+    pub fn layout(&mut self, max_height: scalar) {
+        self.max_height = max_height;
+        self.columns.clear();
+        let mut start = 0;
+        let mut length = 0.0;
+        for (index, unit) in self.units.iter().enumerate() {
+            if unit.kind == UnitKind::Newline {
+                self.columns.push(self.column(start..index, length));
+                start = index + 1;
+                length = 0.0;
+                continue;
+            }
+            let advance = unit.advance();
+            if index > start && length + advance > max_height && !unit.keeps_with_previous() {
+                self.columns.push(self.column(start..index, length));
+                start = index;
+                length = 0.0;
+                // 列首的空格不占位置
+                if unit.kind == UnitKind::Space {
+                    start = index + 1;
+                    continue;
+                }
+            }
+            length += advance;
+        }
+        self.columns
+            .push(self.column(start..self.units.len(), length));
+    }
+
+    fn column(&self, units: Range<usize>, length: scalar) -> Column {
+        let width = self.units[units.clone()]
+            .iter()
+            .map(|unit| unit.thickness())
+            .fold(0.0, scalar::max);
+        Column {
+            units,
+            length,
+            width: if width > 0.0 { width } else { self.font_size },
+        }
+    }
+
+    fn gap(&self) -> scalar {
+        self.font_size * COLUMN_GAP
+    }
+
+    /// 所有列的总宽度
+    pub fn width(&self) -> scalar {
+        let widths = self
+            .columns
+            .iter()
+            .map(|column| column.width)
+            .sum::<scalar>();
+        widths + self.gap() * self.columns.len().saturating_sub(1) as scalar
+    }
+
+    /// 最长一列的长度
+    pub fn height(&self) -> scalar {
+        self.columns
+            .iter()
+            .map(|column| column.length)
+            .fold(0.0, scalar::max)
+    }
+
+    pub fn draw_on_canvas(&self, canvas: &Canvas, origin: Point) {
+        let extent = if self.max_height.is_finite() {
+            self.max_height
+        } else {
+            self.height()
+        };
+        // 先绘制所有描边，避免描边覆盖相邻的文字
+        for stroke in [true, false] {
+            let mut right = origin.x + self.width();
+            for column in &self.columns {
+                let center = right - column.width / 2.0;
+                let mut top = origin.y
+                    + match self.text_align {
+                        TextAlign::Center => (extent - column.length) / 2.0,
+                        TextAlign::Right | TextAlign::End => extent - column.length,
+                        _ => 0.0,
+                    };
+                for unit in &self.units[column.units.clone()] {
+                    let (paragraph, images) = match stroke {
+                        true => (unit.stroke_paragraph.as_ref(), &[][..]),
+                        false => (Some(&unit.paragraph), unit.image.as_slice()),
+                    };
+                    if let Some(paragraph) = paragraph {
+                        unit.draw(canvas, paragraph, images, center, top);
+                    }
+                    top += unit.advance();
+                }
+                right -= column.width + self.gap();
+            }
+        }
+    }
+}
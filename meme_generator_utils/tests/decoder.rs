@@ -0,0 +1,80 @@
+//! 依赖前一帧的 gif 的合成结果
+//!
+//! 每个测试图片为 8x8，第一帧为整张红色，第二帧在 (2, 2) 绘制 2x2 的蓝色，第三帧在 (5, 5) 绘制 2x2 的绿色，
+//! 区别在于第二帧的处置方式以及是否只记录变化的区域。
+
+use std::path::Path;
+
+use skia_safe::{Color, Image};
+
+use meme_generator_utils::decoder::{CodecExt, FrameDecoder};
+
+fn decoder(name: &str) -> FrameDecoder<'static> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    FrameDecoder::from_data(&std::fs::read(path).unwrap()).unwrap()
+}
+
+fn color_at(image: &Image, x: i32, y: i32) -> Color {
+    image.peek_pixels().unwrap().get_color((x, y))
+}
+
+/// 按顺序解码，以及不经过缓存直接解码每一帧，结果应当相同
+fn frames(name: &str) -> Vec<Image> {
+    let mut cached = decoder(name);
+    let frames = (0..cached.get_frame_count())
+        .map(|index| cached.get_frame(index).unwrap())
+        .collect::<Vec<_>>();
+    for (index, frame) in frames.iter().enumerate().rev() {
+        let mut uncached = decoder(name);
+        let direct = (*uncached).get_frame(index).unwrap();
+        for (x, y) in [(0, 0), (2, 2), (3, 3), (5, 5), (6, 6)] {
+            assert_eq!(color_at(frame, x, y), color_at(&direct, x, y));
+        }
+    }
+    frames
+}
+
+#[test]
+fn disposal_keep() {
+    let frames = frames("disposal_keep.gif");
+    assert_eq!(frames.len(), 3);
+    assert_eq!(color_at(&frames[1], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[1], 2, 2), Color::BLUE);
+    assert_eq!(color_at(&frames[2], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[2], 3, 3), Color::BLUE);
+    assert_eq!(color_at(&frames[2], 6, 6), Color::GREEN);
+}
+
+#[test]
+fn disposal_background() {
+    let frames = frames("disposal_background.gif");
+    assert_eq!(color_at(&frames[1], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[1], 2, 2), Color::BLUE);
+    // 第二帧的区域被清除为透明
+    assert_eq!(color_at(&frames[2], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[2], 3, 3).a(), 0);
+    assert_eq!(color_at(&frames[2], 6, 6), Color::GREEN);
+}
+
+#[test]
+fn disposal_previous() {
+    let frames = frames("disposal_previous.gif");
+    assert_eq!(color_at(&frames[1], 2, 2), Color::BLUE);
+    // 第二帧的区域恢复为第一帧的内容
+    assert_eq!(color_at(&frames[2], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[2], 3, 3), Color::RED);
+    assert_eq!(color_at(&frames[2], 6, 6), Color::GREEN);
+}
+
+#[test]
+fn transparent_delta() {
+    // 后两帧为整张图片，未变化的像素为透明色
+    let frames = frames("transparent_delta.gif");
+    assert_eq!(color_at(&frames[1], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[1], 2, 2), Color::BLUE);
+    assert_eq!(color_at(&frames[2], 0, 0), Color::RED);
+    assert_eq!(color_at(&frames[2], 3, 3), Color::BLUE);
+    assert_eq!(color_at(&frames[2], 6, 6), Color::GREEN);
+}
@@ -0,0 +1,118 @@
+//! 开启 `gif_frame_delta` 时编码的 gif 重新解码后应当与原来的各帧相同
+//!
+//! 配置从 `tests/fixtures/gif_frame_delta` 读取，每一帧为 8x8，以红色为底。
+
+use std::{path::Path, sync::Once};
+
+use skia_safe::{Color, Image, Rect};
+
+use meme_generator_utils::{
+    decoder::{CodecExt, FrameDecoder},
+    encoder::GifEncoder,
+    tools::{new_paint, new_surface},
+};
+
+fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        let home = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gif_frame_delta");
+        // 在读取配置之前设置，之后不再修改
+        unsafe { std::env::set_var("MEME_HOME", home) };
+    });
+}
+
+/// 以红色为底，清除 `clear` 区域并以 `color` 填充 `fill` 区域
+fn frame(clear: Option<Rect>, fill: Option<(Rect, Color)>) -> Image {
+    let mut surface = new_surface((8, 8));
+    let canvas = surface.canvas();
+    canvas.clear(Color::RED);
+    if let Some(rect) = clear {
+        canvas.save();
+        canvas.clip_rect(rect, None, None);
+        canvas.clear(Color::TRANSPARENT);
+        canvas.restore();
+    }
+    if let Some((rect, color)) = fill {
+        canvas.draw_rect(rect, &new_paint(color));
+    }
+    surface.image_snapshot()
+}
+
+fn encode(frames: Vec<Image>) -> Vec<Image> {
+    setup();
+    let mut encoder = GifEncoder::new();
+    for frame in frames {
+        encoder.add_frame(frame, 0.1).unwrap();
+    }
+    let data = encoder.finish().unwrap();
+    let mut decoder = FrameDecoder::from_data(&data).unwrap();
+    (0..decoder.get_frame_count())
+        .map(|index| decoder.get_frame(index).unwrap())
+        .collect()
+}
+
+fn color_at(image: &Image, x: i32, y: i32) -> Color {
+    image.peek_pixels().unwrap().get_color((x, y))
+}
+
+/// 调色板量化后颜色可能有偏差，只比较主要的颜色通道
+fn is_color(color: Color, expected: Color) -> bool {
+    color.a() == 255
+        && [
+            (color.r(), expected.r()),
+            (color.g(), expected.g()),
+            (color.b(), expected.b()),
+        ]
+        .iter()
+        .all(|(a, b)| a.abs_diff(*b) < 64)
+}
+
+#[test]
+fn region_frames() {
+    let blue = (Rect::from_xywh(2.0, 2.0, 2.0, 2.0), Color::BLUE);
+    let green = (Rect::from_xywh(5.0, 5.0, 2.0, 2.0), Color::GREEN);
+    let frames = encode(vec![
+        frame(None, None),
+        frame(None, Some(blue)),
+        frame(None, Some(green)),
+    ]);
+    assert_eq!(frames.len(), 3);
+    assert!(is_color(color_at(&frames[1], 0, 0), Color::RED));
+    assert!(is_color(color_at(&frames[1], 2, 2), Color::BLUE));
+    // 第二帧的蓝色不应保留到第三帧
+    assert!(is_color(color_at(&frames[2], 3, 3), Color::RED));
+    assert!(is_color(color_at(&frames[2], 6, 6), Color::GREEN));
+}
+
+#[test]
+fn full_frame_after_region() {
+    let blue = (Rect::from_xywh(2.0, 2.0, 2.0, 2.0), Color::BLUE);
+    // 第三帧左半部分变为透明，需要完整绘制
+    let left = Rect::from_xywh(0.0, 0.0, 4.0, 8.0);
+    let frames = encode(vec![
+        frame(None, None),
+        frame(None, Some(blue)),
+        frame(Some(left), None),
+    ]);
+    assert_eq!(frames.len(), 3);
+    assert!(is_color(color_at(&frames[1], 0, 0), Color::RED));
+    assert!(is_color(color_at(&frames[1], 2, 2), Color::BLUE));
+    // 第一帧的内容不应从透明的部分透出
+    assert_eq!(color_at(&frames[2], 0, 0).a(), 0);
+    assert_eq!(color_at(&frames[2], 3, 3).a(), 0);
+    assert!(is_color(color_at(&frames[2], 6, 6), Color::RED));
+}
+
+#[test]
+fn identical_frames_merged() {
+    let blue = (Rect::from_xywh(2.0, 2.0, 2.0, 2.0), Color::BLUE);
+    let frames = encode(vec![
+        frame(None, None),
+        frame(None, None),
+        frame(None, Some(blue)),
+    ]);
+    // 相同的第二帧合并到第一帧中
+    assert_eq!(frames.len(), 2);
+    assert!(is_color(color_at(&frames[0], 2, 2), Color::RED));
+    assert!(is_color(color_at(&frames[1], 2, 2), Color::BLUE));
+}
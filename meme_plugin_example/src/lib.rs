@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use meme_generator_core::{
+    declare_meme_plugin,
+    error::Error,
+    meme::{Image, Meme, MemeInfo, MemeOption, MemeParams, OptionValue, ParserFlags},
+    registry::MemeRegistry,
+};
+
+/// 原样返回传入的图片
+struct Echo;
+
+impl Meme for Echo {
+    fn key(&self) -> String {
+        "example_echo".to_string()
+    }
+
+    fn info(&self) -> MemeInfo {
+        MemeInfo {
+            key: self.key(),
+            params: MemeParams {
+                min_images: 1,
+                max_images: 1,
+                ..Default::default()
+            },
+            keywords: vec!["回声".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn generate(
+        &self,
+        images: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        let info = self.info();
+        if images.len() != 1 {
+            return Err(Error::ImageNumberMismatch(1, 1, images.len() as u8));
+        }
+        if !texts.is_empty() {
+            return Err(Error::TextNumberMismatch(0, 0, texts.len() as u8));
+        }
+        info.params.validate_options(&options)?;
+        Ok(images.into_iter().next().unwrap().data)
+    }
+
+    fn generate_preview(&self, _: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        Err(Error::MemeFeedback("该表情不支持预览".to_string()))
+    }
+}
+
+/// 将文字重复若干次后以 UTF-8 字节返回，用于演示选项的传递
+struct Repeat;
+
+impl Meme for Repeat {
+    fn key(&self) -> String {
+        "example_repeat".to_string()
+    }
+
+    fn info(&self) -> MemeInfo {
+        MemeInfo {
+            key: self.key(),
+            params: MemeParams {
+                min_texts: 1,
+                max_texts: 1,
+                default_texts: vec!["example".to_string()],
+                options: vec![MemeOption::Integer {
+                    name: "times".to_string(),
+                    default: Some(2),
+                    minimum: Some(1),
+                    maximum: Some(10),
+                    description: Some("重复次数".to_string()),
+                    parser_flags: ParserFlags::default(),
+                }],
+                ..Default::default()
+            },
+            keywords: vec!["复读".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn generate(
+        &self,
+        _: Vec<Image>,
+        texts: Vec<String>,
+        options: HashMap<String, OptionValue>,
+    ) -> Result<Vec<u8>, Error> {
+        let info = self.info();
+        if texts.len() != 1 {
+            return Err(Error::TextNumberMismatch(1, 1, texts.len() as u8));
+        }
+        info.params.validate_options(&options)?;
+        let times = match options.get("times") {
+            Some(OptionValue::Integer(times)) => *times as usize,
+            _ => 2,
+        };
+        Ok(texts[0].repeat(times).into_bytes())
+    }
+
+    fn generate_preview(&self, options: HashMap<String, OptionValue>) -> Result<Vec<u8>, Error> {
+        self.generate(Vec::new(), self.info().params.default_texts, options)
+    }
+}
+
+fn register_memes(registry: &mut dyn MemeRegistry) {
+    registry.register_meme("example_echo", Box::new(Echo));
+    registry.register_meme("example_repeat", Box::new(Repeat));
+}
+
+declare_meme_plugin!(register_memes);
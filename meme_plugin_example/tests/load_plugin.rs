@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    path::PathBuf,
+};
+
+use meme_generator_core::{
+    error::Error,
+    meme::{Image, Meme, OptionValue},
+    plugin::{PluginMeme, load_plugin},
+};
+
+fn library_path() -> PathBuf {
+    // 测试可执行文件位于 target/<profile>/deps，cdylib 产物位于同一目录或其上级目录
+    let exe = std::env::current_exe().unwrap();
+    let deps_dir = exe.parent().unwrap();
+    let file_name = format!("{DLL_PREFIX}meme_plugin_example{DLL_SUFFIX}");
+    [deps_dir, deps_dir.parent().unwrap()]
+        .iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| panic!("plugin library `{file_name}` not found"))
+}
+
+fn load_memes() -> HashMap<String, PluginMeme> {
+    unsafe { load_plugin(library_path()) }
+        .unwrap()
+        .into_iter()
+        .map(|meme| (meme.key(), meme))
+        .collect()
+}
+
+#[test]
+fn loads_memes_with_info() {
+    let memes = load_memes();
+    let mut keys = memes.keys().cloned().collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, ["example_echo", "example_repeat"]);
+
+    let info = memes["example_repeat"].info();
+    assert_eq!(info.keywords, ["复读"]);
+    assert_eq!(info.params.options.len(), 1);
+}
+
+#[test]
+fn generates_across_abi() {
+    let memes = load_memes();
+
+    let data = vec![1, 2, 3, 4];
+    let images = vec![Image {
+        name: "image".to_string(),
+        data: data.clone(),
+    }];
+    let result = memes["example_echo"].generate(images, Vec::new(), HashMap::new());
+    assert_eq!(result.unwrap(), data);
+
+    let options = HashMap::from([("times".to_string(), OptionValue::Integer(3))]);
+    let result = memes["example_repeat"].generate(Vec::new(), vec!["ab".to_string()], options);
+    assert_eq!(result.unwrap(), b"ababab");
+}
+
+#[test]
+fn returns_errors_across_abi() {
+    let memes = load_memes();
+
+    let result = memes["example_echo"].generate(Vec::new(), Vec::new(), HashMap::new());
+    assert!(matches!(result, Err(Error::ImageNumberMismatch(1, 1, 0))));
+
+    let options = HashMap::from([("times".to_string(), OptionValue::Integer(100))]);
+    let result = memes["example_repeat"].generate(Vec::new(), vec!["ab".to_string()], options);
+    match result {
+        Err(Error::InvalidOptionValue { name, .. }) => assert_eq!(name, "times"),
+        _ => panic!("expected InvalidOptionValue, got {result:?}"),
+    }
+
+    let result = memes["example_echo"].generate_preview(HashMap::new());
+    assert!(matches!(result, Err(Error::MemeFeedback(_))));
+}